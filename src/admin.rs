@@ -0,0 +1,405 @@
+//! Chat commands the admins pause and resume the bots with
+
+use std::{collections::HashSet, sync::RwLock};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use secrecy::ExposeSecret;
+use tokio::sync::{mpsc::UnboundedReceiver, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, trace, warn};
+use twitch_irc::message::ServerMessage;
+
+use crate::{
+    board,
+    bot::Error,
+    chat::{ChatClient, ChatTransport, Connection},
+    normalize::normalize,
+    secrettoken::SharedToken,
+    Timestamp,
+};
+
+static PREFIX: &str = "!cookiebot";
+static USAGE: &str = "Usage: !cookiebot pause [bot], !cookiebot resume [bot] or !cookiebot status";
+
+lazy_static! {
+    /// Labels of the paused bots
+    static ref PAUSED: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+
+    /// Wakes the bots waiting while paused
+    static ref RESUMED: Notify = Notify::new();
+}
+
+/// Returns whether the bot with the label `bot` is paused.
+pub fn is_paused(bot: &str) -> bool {
+    PAUSED
+        .read()
+        .expect("paused bots should not be poisoned")
+        .contains(bot)
+}
+
+pub fn pause(bot: &str) {
+    PAUSED
+        .write()
+        .expect("paused bots should not be poisoned")
+        .insert(bot.to_string());
+}
+
+pub fn resume(bot: &str) {
+    PAUSED
+        .write()
+        .expect("paused bots should not be poisoned")
+        .remove(bot);
+    RESUMED.notify_waiters();
+}
+
+/// Waits until the bot with the label `bot` is no longer paused.
+pub async fn wait_while_paused(bot: &str) {
+    let mut logged = false;
+
+    loop {
+        // created before checking, so a resume in between is not missed
+        let resumed = RESUMED.notified();
+        if !is_paused(bot) {
+            break;
+        }
+
+        if !logged {
+            info!("Paused by an admin, waiting to be resumed");
+            logged = true;
+        }
+        resumed.await;
+    }
+
+    if logged {
+        info!("Resumed by an admin");
+    }
+}
+
+/// Command sent by an admin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Pauses the bot with the label or every running bot
+    Pause(Option<String>),
+    Resume(Option<String>),
+    Status,
+    Usage,
+}
+
+impl Command {
+    /// Parses `text` if it is a command for cookiebot.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = normalize(text);
+        let mut words = text.split_whitespace();
+
+        if !words.next()?.eq_ignore_ascii_case(PREFIX) {
+            return None;
+        }
+
+        let command = words.next().map(str::to_lowercase);
+        let bot = words.next().map(str::to_lowercase);
+
+        Some(match command.as_deref() {
+            Some("pause") => Self::Pause(bot),
+            Some("resume") => Self::Resume(bot),
+            Some("status") => Self::Status,
+            _ => Self::Usage,
+        })
+    }
+
+    /// Executes the command and returns the reply.
+    pub fn execute(&self) -> String {
+        match self {
+            Self::Pause(bot) => match bots(bot.as_deref()) {
+                Ok(bots) => {
+                    bots.iter().for_each(|bot| pause(bot));
+                    format!("Paused {}", bots.join(", "))
+                }
+                Err(reply) => reply,
+            },
+            Self::Resume(bot) => match bots(bot.as_deref()) {
+                Ok(bots) => {
+                    bots.iter().for_each(|bot| resume(bot));
+                    format!("Resumed {}", bots.join(", "))
+                }
+                Err(reply) => reply,
+            },
+            Self::Status => status(),
+            Self::Usage => USAGE.to_string(),
+        }
+    }
+}
+
+/// Returns `bot` if it is running or every running bot, otherwise the reply
+/// telling why not.
+fn bots(bot: Option<&str>) -> Result<Vec<String>, String> {
+    let mut running: Vec<_> = board::snapshot()
+        .into_iter()
+        .filter(|status| status.enabled)
+        .map(|status| status.bot.to_string())
+        .collect();
+    running.dedup();
+
+    match bot {
+        Some(bot) if running.iter().any(|running| running == bot) => Ok(vec![bot.to_string()]),
+        Some(bot) => Err(format!("{} is not running", bot)),
+        None if running.is_empty() => Err("No bot is running".to_string()),
+        None => Ok(running),
+    }
+}
+
+fn status() -> String {
+    let statuses: Vec<_> = board::snapshot()
+        .into_iter()
+        .filter(|status| status.enabled)
+        .map(|status| {
+            let state = if is_paused(status.bot) {
+                "paused".to_string()
+            } else {
+                match status
+                    .next_claim
+                    .and_then(|at| (at - Utc::now()).to_std().ok())
+                {
+                    Some(remaining) => format!("next claim in {}", remaining.as_readable()),
+                    None => "claiming".to_string(),
+                }
+            };
+
+            format!("{} of {}: {}", status.bot, status.account, state)
+        })
+        .collect();
+
+    if statuses.is_empty() {
+        "No bot is running".to_string()
+    } else {
+        statuses.join(", ")
+    }
+}
+
+/// Where a command was sent and the reply goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    Channel(String),
+    Whisper(String),
+}
+
+/// Listens for the commands of the admins in the channels of the bots and
+/// in whispers
+#[derive(Debug)]
+pub struct Listener {
+    username: String,
+    token: SharedToken,
+    channels: Vec<String>,
+    admins: HashSet<String>,
+    dry_run: bool,
+    transport: ChatTransport,
+}
+
+impl Listener {
+    pub fn new(
+        username: &str,
+        token: SharedToken,
+        channels: Vec<String>,
+        admins: &[String],
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            username: username.to_string(),
+            token,
+            channels,
+            admins: admins.iter().map(|admin| admin.to_lowercase()).collect(),
+            dry_run,
+            transport: ChatTransport::default(),
+        }
+    }
+
+    /// Connects to chat over `transport`.
+    pub const fn with_chat_transport(mut self, transport: ChatTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Returns the command in `message` and where to reply to it, if it was
+    /// sent by an admin.
+    fn command(&self, message: &ServerMessage) -> Option<(Origin, Command)> {
+        let (origin, sender, text) = match message {
+            ServerMessage::Privmsg(msg) => (
+                Origin::Channel(msg.channel_login.clone()),
+                &msg.sender.login,
+                &msg.message_text,
+            ),
+            ServerMessage::Whisper(msg) => (
+                Origin::Whisper(msg.sender.login.clone()),
+                &msg.sender.login,
+                &msg.message_text,
+            ),
+            _ => return None,
+        };
+
+        let command = Command::parse(text)?;
+        if !self.admins.contains(&sender.to_lowercase()) {
+            debug!("Ignoring command of {}, who is no admin", sender);
+            return None;
+        }
+
+        Some((origin, command))
+    }
+
+    /// Answers the commands of the admins until `cancel` is cancelled.
+    #[instrument(skip(self, cancel))]
+    pub async fn run(&self, cancel: &CancellationToken) -> Result<(), Error> {
+        info!("Listening for commands of {} admins", self.admins.len());
+
+        let (mut incoming_messages, client) = self.login();
+
+        loop {
+            tokio::select! {
+                server_message = incoming_messages.recv() => match server_message {
+                    Some(server_message) => self.handle(&client, server_message).await?,
+                    None => return Err(Error::ConnectionLost),
+                },
+                _ = cancel.cancelled() => return Ok(()),
+            }
+        }
+    }
+
+    fn login(&self) -> (UnboundedReceiver<ServerMessage>, Connection) {
+        let (incoming_messages, client) = self.transport.connect(
+            self.username.to_lowercase(),
+            self.token.get().expose_secret().to_string(),
+        );
+
+        for channel in &self.channels {
+            client.join(channel.to_string());
+        }
+
+        (incoming_messages, client)
+    }
+
+    async fn handle(
+        &self,
+        client: &Connection,
+        server_message: ServerMessage,
+    ) -> Result<(), Error> {
+        trace!("received message: {:?}", &server_message);
+
+        if let ServerMessage::Notice(msg) = &server_message {
+            if msg.message_text == "Login authentication failed" {
+                return Err(Error::AuthenticateChat);
+            }
+        }
+
+        let (origin, command) = match self.command(&server_message) {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+
+        info!("Executing {:?}", command);
+        let reply = command.execute();
+
+        let (channel, message) = match origin {
+            Origin::Channel(channel) => (channel, reply),
+            Origin::Whisper(sender) => (
+                self.username.to_lowercase(),
+                format!("/w {} {}", sender, reply),
+            ),
+        };
+
+        if self.dry_run {
+            info!("Dry run: would send '{}' to #{}", message, channel);
+        } else if let Err(err) = client.say(channel, message).await {
+            warn!("Could not reply to {:?}: {}", command, err);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, time::Duration};
+
+    use tokio::time::timeout;
+    use twitch_irc::message::IRCMessage;
+
+    use super::*;
+    use crate::{secrettoken::Token, SecretToken};
+
+    fn listener() -> Listener {
+        Listener::new(
+            "cookiebot",
+            SharedToken::new(SecretToken::new(Token::new("token"))),
+            vec!["cookies".to_string()],
+            &["Chronophylos".to_string()],
+            true,
+        )
+    }
+
+    fn privmsg(sender: &str, text: &str) -> ServerMessage {
+        let raw = format!(
+            "@badge-info=;badges=;color=;display-name={0};emotes=;id=1;room-id=1;tmi-sent-ts=1614600000000;user-id=1 :{0}!{0}@{0}.tmi.twitch.tv PRIVMSG #cookies :{1}",
+            sender, text
+        );
+
+        ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("!cookiebot pause OkayegBot"),
+            Some(Command::Pause(Some("okayegbot".to_string())))
+        );
+        assert_eq!(
+            Command::parse("!CookieBot resume \u{E0000}"),
+            Some(Command::Resume(None))
+        );
+        assert_eq!(Command::parse("!cookiebot"), Some(Command::Usage));
+        assert_eq!(Command::parse("!cookie"), None);
+    }
+
+    #[test]
+    fn ignores_everyone_but_admins() {
+        let listener = listener();
+
+        assert_eq!(
+            listener.command(&privmsg("chronophylos", "!cookiebot status")),
+            Some((Origin::Channel("cookies".to_string()), Command::Status))
+        );
+        assert_eq!(
+            listener.command(&privmsg("someone", "!cookiebot pause")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn pauses_until_resumed() {
+        board::started("admin_test", "chronophylos");
+
+        assert_eq!(
+            Command::Pause(Some("admin_test".to_string())).execute(),
+            "Paused admin_test"
+        );
+        assert!(
+            timeout(Duration::from_millis(50), wait_while_paused("admin_test"))
+                .await
+                .is_err()
+        );
+
+        let waiting = tokio::spawn(wait_while_paused("admin_test"));
+        tokio::task::yield_now().await;
+        assert_eq!(
+            Command::Resume(Some("admin_test".to_string())).execute(),
+            "Resumed admin_test"
+        );
+        timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("bot should be resumed")
+            .unwrap();
+
+        assert_eq!(
+            Command::Pause(Some("unknown".to_string())).execute(),
+            "unknown is not running"
+        );
+    }
+}
@@ -0,0 +1,558 @@
+//! Alerts about bots behaving unusually while they still stay within their
+//! limits, e.g. sending many messages without hitting the message limit
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display},
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tokio::time::{interval, Instant};
+use tracing::{info, warn};
+
+use crate::{
+    notify::{self, Event},
+    once::ClaimOutcome,
+    util::SlidingWindowCounter,
+    Timestamp,
+};
+
+/// How often the counters are checked against the thresholds
+const EVALUATE_EVERY: Duration = Duration::from_secs(60);
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many of the last claims are attached to an alert
+const RECENT_CLAIMS: usize = 5;
+
+/// Claims needed within a day before their success rate is judged, so a
+/// single failure after a restart is no anomaly
+const MIN_CLAIMS: usize = 4;
+
+lazy_static! {
+    /// What the bots did lately, if anomalies are looked for
+    static ref COUNTERS: Mutex<Option<Counters>> = Mutex::new(None);
+}
+
+/// Thresholds above or below which the bots behave unusually
+///
+/// Unset thresholds are not checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct AnomalyOptions {
+    /// Messages sent by all bots within an hour
+    #[serde(default)]
+    pub max_messages_per_hour: Option<usize>,
+
+    /// Answers of target bots within a day that matched no pattern
+    #[serde(default)]
+    pub max_parse_failures_per_day: Option<usize>,
+
+    /// Share of the claims within a day answered by their target bot, in
+    /// percent
+    #[serde(default)]
+    pub min_success_percent: Option<u8>,
+
+    /// How long no alert is sent again about the same kind of anomaly
+    #[serde(default = "default_cooldown", with = "humantime_serde")]
+    pub cooldown: Duration,
+}
+
+const fn default_cooldown() -> Duration {
+    Duration::from_secs(6 * 60 * 60)
+}
+
+/// A threshold that was crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    Messages {
+        sent: usize,
+        max: usize,
+    },
+    ParseFailures {
+        failures: usize,
+        max: usize,
+    },
+    SuccessRate {
+        succeeded: usize,
+        failed: usize,
+        min_percent: u8,
+    },
+}
+
+/// Kinds of anomalies, each alerted about at most once per cooldown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Category {
+    Messages,
+    ParseFailures,
+    SuccessRate,
+}
+
+impl Finding {
+    const fn category(&self) -> Category {
+        match self {
+            Self::Messages { .. } => Category::Messages,
+            Self::ParseFailures { .. } => Category::ParseFailures,
+            Self::SuccessRate { .. } => Category::SuccessRate,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Messages { sent, max } => {
+                write!(
+                    f,
+                    "{} messages sent within an hour, more than {}",
+                    sent, max
+                )
+            }
+            Self::ParseFailures { failures, max } => write!(
+                f,
+                "{} answers matched no pattern within a day, more than {}",
+                failures, max
+            ),
+            Self::SuccessRate {
+                succeeded,
+                failed,
+                min_percent,
+            } => write!(
+                f,
+                "{} of {} claims succeeded within a day, less than {}%",
+                succeeded,
+                succeeded + failed,
+                min_percent
+            ),
+        }
+    }
+}
+
+/// A claim as it is attached to an alert
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub bot: &'static str,
+    pub account: String,
+    pub at: DateTime<Utc>,
+
+    /// The answer of the target bot, or why there was none
+    pub outcome: Result<ClaimOutcome, String>,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} at {}: ",
+            self.bot,
+            self.account,
+            self.at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )?;
+
+        match &self.outcome {
+            Ok(ClaimOutcome::Claimed { amount, total }) => {
+                write!(f, "claimed {}, total {}", amount, total)
+            }
+            Ok(ClaimOutcome::Cooldown {
+                remaining_secs: Some(secs),
+                ..
+            }) => write!(
+                f,
+                "on cooldown for {}",
+                Duration::from_secs(*secs).as_readable()
+            ),
+            Ok(ClaimOutcome::Cooldown { .. }) => write!(f, "on cooldown"),
+            Err(err) => write!(f, "failed: {}", err),
+        }
+    }
+}
+
+/// The thresholds crossed at once, with the claims that led there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    pub findings: Vec<Finding>,
+
+    /// The last claims, oldest first
+    pub recent: Vec<Record>,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bots behave unusually:")?;
+        for finding in &self.findings {
+            write!(f, "\n- {}", finding)?;
+        }
+
+        if !self.recent.is_empty() {
+            write!(f, "\nLast claims:")?;
+            for record in &self.recent {
+                write!(f, "\n- {}", record)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the bots did within the windows of the thresholds
+#[derive(Debug)]
+struct Counters {
+    messages: SlidingWindowCounter,
+    parse_failures: SlidingWindowCounter,
+    succeeded: SlidingWindowCounter,
+    failed: SlidingWindowCounter,
+    recent: VecDeque<Record>,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Self {
+            messages: SlidingWindowCounter::new(HOUR),
+            parse_failures: SlidingWindowCounter::new(DAY),
+            succeeded: SlidingWindowCounter::new(DAY),
+            failed: SlidingWindowCounter::new(DAY),
+            recent: VecDeque::with_capacity(RECENT_CLAIMS),
+        }
+    }
+
+    fn claim_at(&mut self, now: Instant, record: Record) {
+        match record.outcome {
+            Ok(_) => self.succeeded.record_at(now),
+            Err(_) => self.failed.record_at(now),
+        }
+
+        if self.recent.len() == RECENT_CLAIMS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(record);
+    }
+}
+
+/// Checks the counters against the thresholds
+#[derive(Debug)]
+struct Detector {
+    options: AnomalyOptions,
+
+    /// When each kind of anomaly was last alerted about
+    alerted: HashMap<Category, Instant>,
+}
+
+impl Detector {
+    fn new(options: AnomalyOptions) -> Self {
+        Self {
+            options,
+            alerted: HashMap::new(),
+        }
+    }
+
+    /// Returns the thresholds `counters` crossed at `now`, leaving out those
+    /// alerted about within the cooldown, or `None` if there are none.
+    fn evaluate(&mut self, counters: &mut Counters, now: Instant) -> Option<Anomaly> {
+        let mut findings = Vec::new();
+
+        if let Some(max) = self.options.max_messages_per_hour {
+            let sent = counters.messages.count_at(now);
+            if sent > max {
+                findings.push(Finding::Messages { sent, max });
+            }
+        }
+
+        if let Some(max) = self.options.max_parse_failures_per_day {
+            let failures = counters.parse_failures.count_at(now);
+            if failures > max {
+                findings.push(Finding::ParseFailures { failures, max });
+            }
+        }
+
+        if let Some(min_percent) = self.options.min_success_percent {
+            let succeeded = counters.succeeded.count_at(now);
+            let failed = counters.failed.count_at(now);
+            let claims = succeeded + failed;
+            if claims >= MIN_CLAIMS && succeeded * 100 < claims * min_percent as usize {
+                findings.push(Finding::SuccessRate {
+                    succeeded,
+                    failed,
+                    min_percent,
+                });
+            }
+        }
+
+        let cooldown = self.options.cooldown;
+        let alerted = &mut self.alerted;
+        findings.retain(|finding| {
+            let category = finding.category();
+            if alerted
+                .get(&category)
+                .is_some_and(|at| now.saturating_duration_since(*at) < cooldown)
+            {
+                return false;
+            }
+
+            alerted.insert(category, now);
+            true
+        });
+
+        if findings.is_empty() {
+            return None;
+        }
+
+        Some(Anomaly {
+            findings,
+            recent: counters.recent.iter().cloned().collect(),
+        })
+    }
+}
+
+/// Runs `f` on the counters, if anomalies are looked for.
+fn with_counters<F>(f: F)
+where
+    F: FnOnce(&mut Counters),
+{
+    if let Some(counters) = COUNTERS
+        .lock()
+        .expect("anomaly counters should not be poisoned")
+        .as_mut()
+    {
+        f(counters)
+    }
+}
+
+/// Counts a message sent to chat.
+pub fn message_sent() {
+    with_counters(|counters| counters.messages.record())
+}
+
+/// Counts an answer of a target bot that matched no pattern.
+pub fn parse_failed() {
+    with_counters(|counters| counters.parse_failures.record())
+}
+
+/// Counts a claim the target bot answered.
+pub fn claimed(bot: &'static str, account: &str, outcome: ClaimOutcome) {
+    with_counters(|counters| {
+        counters.claim_at(
+            Instant::now(),
+            Record {
+                bot,
+                account: account.to_string(),
+                at: Utc::now(),
+                outcome: Ok(outcome),
+            },
+        )
+    })
+}
+
+/// Counts a claim that failed with `err`.
+pub fn failed<E>(bot: &'static str, account: &str, err: &E)
+where
+    E: Display,
+{
+    with_counters(|counters| {
+        counters.claim_at(
+            Instant::now(),
+            Record {
+                bot,
+                account: account.to_string(),
+                at: Utc::now(),
+                outcome: Err(err.to_string()),
+            },
+        )
+    })
+}
+
+/// Starts counting what the bots do and sends a notification whenever
+/// they cross a threshold of `options`.
+pub fn start(options: AnomalyOptions) {
+    *COUNTERS
+        .lock()
+        .expect("anomaly counters should not be poisoned") = Some(Counters::new());
+    let mut detector = Detector::new(options);
+    info!(
+        "Looking for anomalies every {}",
+        EVALUATE_EVERY.as_readable()
+    );
+
+    tokio::spawn(async move {
+        let mut evaluations = interval(EVALUATE_EVERY);
+        loop {
+            evaluations.tick().await;
+
+            let anomaly = COUNTERS
+                .lock()
+                .expect("anomaly counters should not be poisoned")
+                .as_mut()
+                .and_then(|counters| detector.evaluate(counters, Instant::now()));
+            if let Some(anomaly) = anomaly {
+                warn!("{}", anomaly);
+                notify::send(Event::Anomaly(anomaly));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> Detector {
+        Detector::new(AnomalyOptions {
+            max_messages_per_hour: Some(10),
+            max_parse_failures_per_day: Some(2),
+            min_success_percent: Some(75),
+            cooldown: default_cooldown(),
+        })
+    }
+
+    fn record(outcome: Result<ClaimOutcome, String>) -> Record {
+        Record {
+            bot: "thepositivebot",
+            account: "chronophylos".to_string(),
+            at: DateTime::parse_from_rfc3339("2021-03-01T12:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            outcome,
+        }
+    }
+
+    fn claimed(amount: i64, total: i64) -> Record {
+        record(Ok(ClaimOutcome::Claimed { amount, total }))
+    }
+
+    fn failed() -> Record {
+        record(Err("Failed to communicate after 2 retries".to_string()))
+    }
+
+    #[test]
+    fn alerts_once_per_cooldown() {
+        let mut detector = detector();
+        let mut counters = Counters::new();
+        let start = Instant::now();
+
+        for minute in 0..11 {
+            counters
+                .messages
+                .record_at(start + Duration::from_secs(minute * 60));
+        }
+        let anomaly = detector.evaluate(&mut counters, start + Duration::from_secs(15 * 60));
+        assert_eq!(
+            anomaly.map(|anomaly| anomaly.findings),
+            Some(vec![Finding::Messages { sent: 11, max: 10 }])
+        );
+
+        // sending as much again within the cooldown is not alerted about
+        let later = start + Duration::from_secs(2 * 60 * 60);
+        for _ in 0..11 {
+            counters.messages.record_at(later);
+        }
+        assert_eq!(detector.evaluate(&mut counters, later), None);
+
+        let after_cooldown = start + Duration::from_secs(7 * 60 * 60);
+        for _ in 0..11 {
+            counters.messages.record_at(after_cooldown);
+        }
+        assert!(detector.evaluate(&mut counters, after_cooldown).is_some());
+    }
+
+    #[test]
+    fn forgets_messages_older_than_an_hour() {
+        let mut detector = detector();
+        let mut counters = Counters::new();
+        let start = Instant::now();
+
+        for _ in 0..6 {
+            counters.messages.record_at(start);
+        }
+        let later = start + Duration::from_secs(61 * 60);
+        for _ in 0..6 {
+            counters.messages.record_at(later);
+        }
+
+        assert_eq!(detector.evaluate(&mut counters, later), None);
+    }
+
+    #[test]
+    fn aggregates_findings_with_the_last_claims() {
+        let mut detector = detector();
+        let mut counters = Counters::new();
+        let start = Instant::now();
+
+        for hour in 0..3 {
+            counters
+                .parse_failures
+                .record_at(start + Duration::from_secs(hour * 60 * 60));
+        }
+        counters.claim_at(start, claimed(14, 65));
+        for _ in 0..6 {
+            counters.claim_at(start, failed());
+        }
+
+        let anomaly = detector
+            .evaluate(&mut counters, start + Duration::from_secs(3 * 60 * 60))
+            .unwrap();
+        assert_eq!(
+            anomaly.findings,
+            vec![
+                Finding::ParseFailures {
+                    failures: 3,
+                    max: 2
+                },
+                Finding::SuccessRate {
+                    succeeded: 1,
+                    failed: 6,
+                    min_percent: 75
+                },
+            ]
+        );
+        assert_eq!(anomaly.recent, vec![failed(); RECENT_CLAIMS]);
+
+        // each category has a cooldown of its own
+        let later = start + Duration::from_secs(4 * 60 * 60);
+        for _ in 0..11 {
+            counters.messages.record_at(later);
+        }
+        let anomaly = detector.evaluate(&mut counters, later).unwrap();
+        assert_eq!(
+            anomaly.findings,
+            vec![Finding::Messages { sent: 11, max: 10 }]
+        );
+    }
+
+    #[test]
+    fn judges_the_success_rate_only_after_a_few_claims() {
+        let mut detector = detector();
+        let mut counters = Counters::new();
+        let start = Instant::now();
+
+        counters.claim_at(start, failed());
+        counters.claim_at(start, claimed(14, 65));
+        counters.claim_at(start, claimed(12, 77));
+        assert_eq!(detector.evaluate(&mut counters, start), None);
+
+        counters.claim_at(start, claimed(11, 88));
+        assert_eq!(detector.evaluate(&mut counters, start), None);
+
+        counters.claim_at(start, failed());
+        assert!(detector.evaluate(&mut counters, start).is_some());
+    }
+
+    #[test]
+    fn describes_findings_and_claims() {
+        let anomaly = Anomaly {
+            findings: vec![Finding::Messages { sent: 11, max: 10 }],
+            recent: vec![claimed(14, 65), failed()],
+        };
+
+        assert_eq!(
+            anomaly.to_string(),
+            "Bots behave unusually:\n\
+             - 11 messages sent within an hour, more than 10\n\
+             Last claims:\n\
+             - thepositivebot of chronophylos at 2021-03-01T12:00:00Z: claimed 14, total 65\n\
+             - thepositivebot of chronophylos at 2021-03-01T12:00:00Z: failed: Failed to communicate after 2 retries"
+        );
+    }
+}
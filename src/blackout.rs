@@ -0,0 +1,368 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Weekday};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::Timestamp;
+
+/// A weekly window in which no claims should be made in a channel
+///
+/// Windows where `to` is not after `from` end on the next day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Blackout {
+    #[serde(deserialize_with = "deserialize_weekday")]
+    pub weekday: Weekday,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub to: NaiveTime,
+}
+
+impl Display for Blackout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} - {}",
+            self.weekday,
+            self.from.format("%H:%M"),
+            self.to.format("%H:%M")
+        )
+    }
+}
+
+impl Blackout {
+    /// Returns the window starting on `date` if it falls on the weekday of the
+    /// blackout.
+    fn window_on<Tz: TimeZone>(
+        &self,
+        tz: &Tz,
+        date: NaiveDate,
+    ) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        if date.weekday() != self.weekday {
+            return None;
+        }
+
+        let end_date = if self.to > self.from {
+            date
+        } else {
+            date.succ()
+        };
+
+        Some((
+            resolve(tz, date.and_time(self.from)),
+            resolve(tz, end_date.and_time(self.to)),
+        ))
+    }
+}
+
+/// All blackouts of a channel
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Blackouts(Vec<Blackout>);
+
+impl From<Vec<Blackout>> for Blackouts {
+    fn from(blackouts: Vec<Blackout>) -> Self {
+        Self(blackouts)
+    }
+}
+
+impl Blackouts {
+    /// Combines the windows of several channels.
+    pub fn merged<'a, I>(all: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Blackouts>,
+    {
+        Self(
+            all.into_iter()
+                .flat_map(|blackouts| blackouts.0.iter().copied())
+                .collect(),
+        )
+    }
+
+    /// Extends `wait` so it does not end inside a blackout.
+    pub fn defer(&self, wait: Duration) -> Duration {
+        self.defer_at(Local::now(), wait)
+    }
+
+    fn defer_at<Tz: TimeZone>(&self, now: DateTime<Tz>, wait: Duration) -> Duration {
+        if self.0.is_empty() {
+            return wait;
+        }
+
+        let due = now.clone() + chrono::Duration::from_std(wait).expect("duration should fit");
+
+        self.end_of_blackout(due)
+            .signed_duration_since(now)
+            .to_std()
+            .unwrap_or(wait)
+    }
+
+    /// Returns the first instant at or after `at` that is not inside a
+    /// blackout.
+    fn end_of_blackout<Tz: TimeZone>(&self, mut at: DateTime<Tz>) -> DateTime<Tz> {
+        // every window can push `at` at most once per day of the week, which
+        // stops windows covering the whole week from looping forever
+        for _ in 0..=self.0.len() * 7 {
+            match self.containing(&at) {
+                Some(end) => at = end,
+                None => break,
+            }
+        }
+
+        at
+    }
+
+    /// Returns the latest end of all windows containing `at`.
+    fn containing<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let tz = at.timezone();
+        let date = at.naive_local().date();
+
+        self.0
+            .iter()
+            .flat_map(|blackout| {
+                vec![
+                    blackout.window_on(&tz, date.pred()),
+                    blackout.window_on(&tz, date),
+                ]
+            })
+            .flatten()
+            .filter(|(start, end)| start <= at && at < end)
+            .map(|(_, end)| end)
+            .max()
+    }
+
+    /// Returns the next `count` windows that have not ended yet.
+    pub fn upcoming<Tz: TimeZone>(
+        &self,
+        now: DateTime<Tz>,
+        count: usize,
+    ) -> Vec<(DateTime<Tz>, DateTime<Tz>)> {
+        let tz = now.timezone();
+        let today = now.naive_local().date();
+
+        let mut windows: Vec<_> = (-1..7)
+            .map(|days| today + chrono::Duration::days(days))
+            .flat_map(|date| {
+                self.0
+                    .iter()
+                    .filter_map(|blackout| blackout.window_on(&tz, date))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|(_, end)| *end > now)
+            .collect();
+
+        windows.sort_by(|a, b| a.0.cmp(&b.0));
+        windows.truncate(count);
+
+        windows
+    }
+
+    /// Returns a readable list of the next `count` windows.
+    pub fn describe_upcoming(&self, count: usize) -> Vec<String> {
+        let now = Local::now();
+
+        self.upcoming(now, count)
+            .into_iter()
+            .map(|(start, end)| {
+                format!(
+                    "{} until {} (in {})",
+                    start.format("%a %H:%M"),
+                    end.format("%a %H:%M"),
+                    start
+                        .signed_duration_since(now)
+                        .to_std()
+                        .unwrap_or_default()
+                        .as_readable()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Resolves a local time to an instant.
+///
+/// Ambiguous times use the earlier instant. Times skipped by a DST gap are
+/// moved forward by the length of the gap.
+pub(crate) fn resolve<Tz: TimeZone>(tz: &Tz, naive: chrono::NaiveDateTime) -> DateTime<Tz> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(naive + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| tz.from_utc_datetime(&naive))
+}
+
+fn deserialize_weekday<'de, D>(deserializer: D) -> Result<Weekday, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    s.parse()
+        .map_err(|_| de::Error::custom(format!("invalid weekday: {}", s)))
+}
+
+pub(crate) fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    NaiveTime::parse_from_str(&s, "%H:%M").map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn blackout(weekday: Weekday, from: &str, to: &str) -> Blackout {
+        Blackout {
+            weekday,
+            from: NaiveTime::parse_from_str(from, "%H:%M").unwrap(),
+            to: NaiveTime::parse_from_str(to, "%H:%M").unwrap(),
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn minutes(m: u64) -> Duration {
+        Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn outside_window_is_not_deferred() {
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sat, "20:00", "22:00")]);
+
+        // 2021-03-06 is a Saturday
+        let wait = blackouts.defer_at(at("2021-03-06T18:00:00Z"), minutes(30));
+
+        assert_eq!(wait, minutes(30));
+    }
+
+    #[test]
+    fn defers_to_end_of_window() {
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sat, "20:00", "22:00")]);
+
+        let wait = blackouts.defer_at(at("2021-03-06T19:30:00Z"), minutes(60));
+
+        assert_eq!(wait, minutes(150));
+    }
+
+    #[test]
+    fn window_end_is_exclusive() {
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sat, "20:00", "22:00")]);
+
+        let wait = blackouts.defer_at(at("2021-03-06T21:00:00Z"), minutes(60));
+
+        assert_eq!(wait, minutes(60));
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        let blackouts = Blackouts::from(vec![
+            blackout(Weekday::Sat, "20:00", "22:00"),
+            blackout(Weekday::Sat, "21:30", "23:00"),
+        ]);
+
+        let wait = blackouts.defer_at(at("2021-03-06T20:00:00Z"), minutes(0));
+
+        assert_eq!(wait, minutes(180));
+    }
+
+    #[test]
+    fn midnight_crossing_window() {
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sat, "23:00", "01:00")]);
+
+        // due at 00:30 on Sunday, still inside the window that began on Saturday
+        let wait = blackouts.defer_at(at("2021-03-07T00:00:00Z"), minutes(30));
+
+        assert_eq!(wait, minutes(60));
+    }
+
+    #[test]
+    fn whole_week_does_not_loop_forever() {
+        let blackouts = Blackouts::from(
+            [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+                Weekday::Sun,
+            ]
+            .iter()
+            .map(|weekday| blackout(*weekday, "00:00", "00:00"))
+            .collect::<Vec<_>>(),
+        );
+
+        blackouts.defer_at(at("2021-03-06T20:00:00Z"), minutes(0));
+    }
+
+    #[test]
+    fn window_ending_in_dst_gap() {
+        // clocks in Berlin jump from 02:00 to 03:00 on 2021-03-28, a Sunday
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sun, "01:30", "02:30")]);
+        let now = Berlin.ymd(2021, 3, 28).and_hms(1, 0, 0);
+
+        let wait = blackouts.defer_at(now, minutes(45));
+
+        // the window ends at 03:30 CEST, 90 minutes of real time after 01:00 CET
+        assert_eq!(wait, minutes(90));
+    }
+
+    #[test]
+    fn window_during_dst_overlap() {
+        // clocks in Berlin fall back from 03:00 to 02:00 on 2021-10-31
+        let blackouts = Blackouts::from(vec![blackout(Weekday::Sun, "02:15", "02:45")]);
+
+        // 02:30 CEST is inside the first occurrence of the window
+        let wait = blackouts.defer_at(
+            at("2021-10-31T00:00:00Z").with_timezone(&Berlin),
+            minutes(30),
+        );
+        assert_eq!(wait, minutes(45));
+
+        // 02:30 CET, one hour later, is not
+        let wait = blackouts.defer_at(
+            at("2021-10-31T01:00:00Z").with_timezone(&Berlin),
+            minutes(30),
+        );
+        assert_eq!(wait, minutes(30));
+    }
+
+    #[test]
+    fn lists_upcoming_windows() {
+        let blackouts = Blackouts::from(vec![
+            blackout(Weekday::Sat, "20:00", "22:00"),
+            blackout(Weekday::Mon, "08:00", "09:00"),
+        ]);
+
+        let upcoming = blackouts.upcoming(at("2021-03-06T21:00:00Z"), 2);
+
+        assert_eq!(
+            upcoming,
+            vec![
+                (at("2021-03-06T20:00:00Z"), at("2021-03-06T22:00:00Z")),
+                (at("2021-03-08T08:00:00Z"), at("2021-03-08T09:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_blackouts() {
+        let blackouts: Blackouts =
+            ron::de::from_str(r#"[(weekday: "Sat", from: "20:00", to: "22:00")]"#).unwrap();
+
+        assert_eq!(
+            blackouts,
+            Blackouts::from(vec![blackout(Weekday::Sat, "20:00", "22:00")])
+        );
+    }
+}
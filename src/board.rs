@@ -0,0 +1,455 @@
+//! State the bots publish for the status and health endpoints
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use hyper::{
+    header::CONTENT_TYPE,
+    http,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{
+    anomaly,
+    once::ClaimOutcome,
+    state::{self, Saved},
+};
+
+lazy_static! {
+    /// States by bot label and account
+    static ref BOARD: RwLock<BTreeMap<(&'static str, String), BotStatus>> =
+        RwLock::new(BTreeMap::new());
+}
+
+/// Whether the bots enabled on startup were all started
+static LOADED: AtomicBool = AtomicBool::new(false);
+
+/// What a bot of an account last did and does next
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BotStatus {
+    pub bot: &'static str,
+    pub account: String,
+    pub enabled: bool,
+    pub channel: Option<String>,
+    pub last_claim: Option<DateTime<Utc>>,
+    pub last_outcome: Option<ClaimOutcome>,
+    pub next_claim: Option<DateTime<Utc>>,
+
+    /// Total the target bot reported on the last claim
+    pub total: Option<i64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+
+    /// Whether the bot knows its cooldown since it was started
+    pub ready: bool,
+
+    /// Why the bot cannot claim right now
+    pub unhealthy: Option<String>,
+}
+
+impl BotStatus {
+    fn new(bot: &'static str, account: &str) -> Self {
+        Self {
+            bot,
+            account: account.to_string(),
+            enabled: false,
+            channel: None,
+            last_claim: None,
+            last_outcome: None,
+            next_claim: None,
+            total: None,
+            last_error: None,
+            last_error_at: None,
+            ready: false,
+            unhealthy: None,
+        }
+    }
+
+    /// Returns the status of a bot that is not running, from what it saved.
+    pub fn restored(bot: &'static str, account: &str, saved: Saved) -> Self {
+        Self {
+            next_claim: saved.next_claim,
+            total: saved.total,
+            ..Self::new(bot, account)
+        }
+    }
+
+    /// Returns the part of the status kept across restarts.
+    const fn saved(&self) -> Saved {
+        Saved {
+            next_claim: self.next_claim,
+            total: self.total,
+        }
+    }
+}
+
+fn update<F, R>(bot: &'static str, account: &str, f: F) -> R
+where
+    F: FnOnce(&mut BotStatus) -> R,
+{
+    let mut board = BOARD.write().expect("board should not be poisoned");
+    let status = board
+        .entry((bot, account.to_lowercase()))
+        .or_insert_with(|| BotStatus::new(bot, &account.to_lowercase()));
+
+    f(status)
+}
+
+/// Returns the states of all bots that ever ran, ordered by bot and account.
+pub fn snapshot() -> Vec<BotStatus> {
+    BOARD
+        .read()
+        .expect("board should not be poisoned")
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// Marks the bots enabled on startup as started, so readiness only waits for
+/// them to claim.
+pub fn loaded() {
+    LOADED.store(true, Ordering::Relaxed);
+}
+
+pub fn started(bot: &'static str, account: &str) {
+    update(bot, account, |status| {
+        status.enabled = true;
+        status.ready = false;
+        status.unhealthy = None;
+    });
+}
+
+pub fn stopped(bot: &'static str, account: &str) {
+    update(bot, account, |status| {
+        status.enabled = false;
+        status.next_claim = None;
+        status.ready = false;
+    });
+}
+
+/// Marks the bot as ready without a claim, once it fetched its cooldown or
+/// waits out a saved one.
+pub fn ready(bot: &'static str, account: &str) {
+    update(bot, account, |status| status.ready = true);
+}
+
+/// Marks the bot as unable to claim for `reason` until it claims again.
+pub fn unhealthy<R>(bot: &'static str, account: &str, reason: R)
+where
+    R: Display,
+{
+    update(bot, account, |status| {
+        status.unhealthy = Some(reason.to_string())
+    });
+}
+
+pub fn channel(bot: &'static str, account: &str, channel: &str) {
+    update(bot, account, |status| {
+        status.channel = Some(channel.to_string())
+    });
+}
+
+pub fn claimed(bot: &'static str, account: &str, outcome: ClaimOutcome) {
+    anomaly::claimed(bot, account, outcome);
+    let saved = update(bot, account, |status| {
+        status.last_claim = Some(Utc::now());
+        status.last_outcome = Some(outcome);
+        status.ready = true;
+        status.unhealthy = None;
+        status.total = match outcome {
+            ClaimOutcome::Claimed { total, .. } => Some(total),
+            ClaimOutcome::Cooldown { total, .. } => total.or(status.total),
+        };
+        status.saved()
+    });
+
+    state::save(bot, account, saved);
+}
+
+pub fn next_claim(bot: &'static str, account: &str, deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let at = chrono::Duration::from_std(remaining)
+        .ok()
+        .map(|remaining| Utc::now() + remaining);
+
+    let saved = update(bot, account, |status| {
+        status.next_claim = at;
+        status.saved()
+    });
+
+    state::save(bot, account, saved);
+}
+
+pub fn failed<E>(bot: &'static str, account: &str, err: &E)
+where
+    E: Display,
+{
+    anomaly::failed(bot, account, err);
+    update(bot, account, |status| {
+        status.last_error = Some(err.to_string());
+        status.last_error_at = Some(Utc::now());
+    });
+}
+
+/// An enabled bot that is not ready
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Unready {
+    pub bot: &'static str,
+    pub account: String,
+    pub reason: String,
+}
+
+/// Whether every enabled bot can claim
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    /// Whether the bots enabled on startup were all started
+    pub loaded: bool,
+    pub unready: Vec<Unready>,
+}
+
+impl Readiness {
+    pub const fn is_ready(&self) -> bool {
+        self.loaded && self.unready.is_empty()
+    }
+}
+
+/// Returns which enabled bots are not ready and why.
+pub fn readiness() -> Readiness {
+    let unready = snapshot()
+        .into_iter()
+        .filter(|status| status.enabled)
+        .filter_map(|status| {
+            let reason = match status.unhealthy {
+                Some(reason) => reason,
+                None if !status.ready => "starting".to_string(),
+                None => return None,
+            };
+
+            Some(Unready {
+                bot: status.bot,
+                account: status.account,
+                reason,
+            })
+        })
+        .collect();
+
+    Readiness {
+        loaded: LOADED.load(Ordering::Relaxed),
+        unready,
+    }
+}
+
+fn json<T>(status: StatusCode, value: &T) -> http::Result<Response<Body>>
+where
+    T: Serialize,
+{
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body)),
+        Err(err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(err.to_string())),
+    }
+}
+
+fn not_found() -> http::Result<Response<Body>> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/status") => json(StatusCode::OK, &snapshot()),
+        _ => not_found(),
+    };
+
+    Ok(response.expect("response should be valid"))
+}
+
+async fn handle_health(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/healthz") => Response::builder().body(Body::from("ok")),
+        (&Method::GET, "/readyz") => {
+            let readiness = readiness();
+            let status = if readiness.is_ready() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+
+            json(status, &readiness)
+        }
+        _ => not_found(),
+    };
+
+    Ok(response.expect("response should be valid"))
+}
+
+/// Binds `addr` and returns the server answering `GET /status` with the
+/// states of all bots as JSON.
+pub fn serve(addr: SocketAddr) -> hyper::Result<impl Future<Output = hyper::Result<()>>> {
+    let make_service = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    Ok(Server::try_bind(&addr)?.serve(make_service))
+}
+
+/// Binds `addr` and returns the server answering `GET /healthz` while the
+/// process runs and `GET /readyz` with 503 until every enabled bot can claim.
+pub fn serve_health(addr: SocketAddr) -> hyper::Result<impl Future<Output = hyper::Result<()>>> {
+    let make_service =
+        make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle_health)) });
+
+    Ok(Server::try_bind(&addr)?.serve(make_service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(account: &str) -> BotStatus {
+        snapshot()
+            .into_iter()
+            .find(|status| status.account == account)
+            .expect("bot should be on the board")
+    }
+
+    #[test]
+    fn keeps_total_of_last_claim() {
+        started("test", "Board_Total");
+        claimed(
+            "test",
+            "board_total",
+            ClaimOutcome::Claimed {
+                amount: 14,
+                total: 65,
+            },
+        );
+        claimed(
+            "test",
+            "board_total",
+            ClaimOutcome::Cooldown {
+                remaining_secs: Some(90),
+                total: None,
+            },
+        );
+
+        let status = status("board_total");
+        assert!(status.enabled);
+        assert_eq!(status.total, Some(65));
+        assert!(matches!(
+            status.last_outcome,
+            Some(ClaimOutcome::Cooldown { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn readiness_waits_for_every_enabled_bot() {
+        let unready = |account: &str| {
+            readiness()
+                .unready
+                .into_iter()
+                .find(|unready| unready.account == account)
+                .map(|unready| unready.reason)
+        };
+
+        started("test", "board_ready");
+        assert_eq!(unready("board_ready"), Some("starting".to_string()));
+
+        claimed(
+            "test",
+            "board_ready",
+            ClaimOutcome::Cooldown {
+                remaining_secs: Some(90),
+                total: None,
+            },
+        );
+        assert_eq!(unready("board_ready"), None);
+
+        unhealthy(
+            "test",
+            "board_ready",
+            "suspended, thepositivebot is not in #cookies",
+        );
+        assert_eq!(
+            unready("board_ready"),
+            Some("suspended, thepositivebot is not in #cookies".to_string())
+        );
+
+        // only enabled bots count
+        stopped("test", "board_ready");
+        assert_eq!(unready("board_ready"), None);
+
+        loaded();
+        started("test", "board_ready");
+        let response = handle_health(Request::get("/readyz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let readiness: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(readiness["unready"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|unready| unready["account"] == "board_ready" && unready["reason"] == "starting"));
+
+        let response = handle_health(Request::get("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn fetched_cooldown_makes_ready() {
+        started("test", "board_fetched");
+        assert!(readiness()
+            .unready
+            .iter()
+            .any(|unready| unready.account == "board_fetched"));
+
+        ready("test", "board_fetched");
+        let status = status("board_fetched");
+        assert!(status.ready);
+        assert_eq!(status.last_claim, None);
+        assert!(!readiness()
+            .unready
+            .iter()
+            .any(|unready| unready.account == "board_fetched"));
+    }
+
+    #[tokio::test]
+    async fn serves_status() {
+        failed("test", "board_serve", &"Could not join channel #cookies");
+
+        let response = handle(Request::get("/status").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let statuses: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(statuses
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|status| status["account"] == "board_serve"
+                && status["last_error"] == "Could not join channel #cookies"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Channels a bot may claim in and the one it currently claims in
+
+use std::{
+    convert::Infallible,
+    fmt::{self, Display},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use metrics::{gauge, register_gauge, Unit};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::board;
+
+static METRIC_ACTIVE_CHANNEL: &str = "cookiebot.channel.active";
+
+/// Registers the channel metrics with the recorder.
+pub(crate) fn register_metrics() {
+    register_gauge!(
+        METRIC_ACTIVE_CHANNEL,
+        Unit::Count,
+        "whether the bot claims in the channel"
+    );
+}
+
+/// Channels a bot may claim in, in order of preference
+///
+/// Written as a single channel or a list of channels in the config, or as a
+/// comma separated list in environment variables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Channels(Vec<String>);
+
+impl Channels {
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<&str> for Channels {
+    fn from(channel: &str) -> Self {
+        Self(vec![channel.to_string()])
+    }
+}
+
+impl From<Vec<String>> for Channels {
+    fn from(channels: Vec<String>) -> Self {
+        Self(channels)
+    }
+}
+
+/// Parses a comma separated list of channels.
+impl FromStr for Channels {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(
+            s.split(',')
+                .map(str::trim)
+                .filter(|channel| !channel.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ))
+    }
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels: Vec<_> = self.iter().map(|channel| format!("#{}", channel)).collect();
+        write!(f, "{}", channels.join(", "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Channels {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ChannelsVisitor;
+
+        impl<'de> Visitor<'de> for ChannelsVisitor {
+            type Value = Channels;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a channel or a list of channels")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                // an empty channel is how "no channel yet" was written
+                Ok(v.parse().expect("parsing channels is infallible"))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut channels = Vec::new();
+                while let Some(channel) = seq.next_element()? {
+                    channels.push(channel);
+                }
+
+                Ok(Channels(channels))
+            }
+        }
+
+        deserializer.deserialize_any(ChannelsVisitor)
+    }
+}
+
+/// Channel a bot claims in, failing over to the other configured channels
+#[derive(Debug)]
+pub struct ChannelFailover {
+    bot: &'static str,
+    account: String,
+    channels: Channels,
+
+    /// Index of the last channel the target bot was found in
+    current: AtomicUsize,
+}
+
+impl ChannelFailover {
+    /// Starts with the first of `channels`, which must not be empty.
+    pub fn new(bot: &'static str, account: &str, channels: Channels) -> Self {
+        assert!(!channels.is_empty(), "a bot needs a channel");
+
+        for (index, channel) in channels.iter().enumerate() {
+            let active = if index == 0 { 1. } else { 0. };
+            gauge!(
+                METRIC_ACTIVE_CHANNEL,
+                active,
+                "bot" => bot,
+                "account" => account.to_string(),
+                "channel" => channel.to_string()
+            );
+        }
+        board::channel(bot, account, &channels.0[0]);
+
+        Self {
+            bot,
+            account: account.to_string(),
+            channels,
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the label of the bot claiming in the channels.
+    pub const fn bot(&self) -> &'static str {
+        self.bot
+    }
+
+    pub const fn channels(&self) -> &Channels {
+        &self.channels
+    }
+
+    /// Returns the channel the bot currently claims in.
+    pub fn current(&self) -> &str {
+        &self.channels.0[self.current.load(Ordering::Relaxed)]
+    }
+
+    /// Returns the channels to try when the target bot left the current one,
+    /// in the configured order.
+    pub fn others(&self) -> impl Iterator<Item = (usize, &str)> {
+        let current = self.current.load(Ordering::Relaxed);
+
+        self.channels
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| *index != current)
+    }
+
+    /// Makes the channel at `index` the current one.
+    pub fn select(&self, index: usize) {
+        let previous = self.current.swap(index, Ordering::Relaxed);
+
+        for (index, active) in [(previous, 0.), (index, 1.)] {
+            gauge!(
+                METRIC_ACTIVE_CHANNEL,
+                active,
+                "bot" => self.bot,
+                "account" => self.account.clone(),
+                "channel" => self.channels.0[index].clone()
+            );
+        }
+        board::channel(self.bot, &self.account, self.current());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_one_or_many() {
+        let one: Channels = ron::de::from_str(r#""thepositivebot""#).unwrap();
+        let many: Channels = ron::de::from_str(r#"["thepositivebot", "cookies"]"#).unwrap();
+
+        assert_eq!(one, Channels::from("thepositivebot"));
+        assert_eq!(
+            many,
+            Channels::from(vec!["thepositivebot".to_string(), "cookies".to_string()])
+        );
+        assert_eq!(many.to_string(), "#thepositivebot, #cookies");
+    }
+
+    #[test]
+    fn remembers_selected_channel() {
+        let failover = ChannelFailover::new("test", "chronophylos", "a, b, c".parse().unwrap());
+        failover.select(2);
+
+        assert_eq!(failover.current(), "c");
+        assert_eq!(
+            failover.others().collect::<Vec<_>>(),
+            vec![(0, "a"), (1, "b")]
+        );
+    }
+}
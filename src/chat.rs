@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+use twitch_irc::{
+    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, TCPTransport,
+    TwitchIRCClient, WSSTransport,
+};
+
+use crate::bot::Error;
+
+/// Client of Twitch chat connecting over `T`
+type Client<T> = TwitchIRCClient<T, StaticLoginCredentials>;
+
+/// How the bots connect to Twitch chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ChatTransport {
+    /// IRC over TLS on port 6697, twitch_irc has no plain IRC transport
+    #[default]
+    #[serde(alias = "Tls")]
+    Tcp,
+
+    /// WebSocket over TLS on port 443
+    Wss,
+}
+
+impl ChatTransport {
+    /// Connects to chat as `username` with `token`.
+    ///
+    /// Returns the messages received and the client to send and join with.
+    pub fn connect(
+        self,
+        username: String,
+        token: String,
+    ) -> (UnboundedReceiver<ServerMessage>, Connection) {
+        let config = ClientConfig::new_simple(StaticLoginCredentials::new(username, Some(token)));
+
+        match self {
+            Self::Tcp => {
+                let (incoming_messages, client) = Client::<TCPTransport>::new(config);
+                (incoming_messages, Connection::Tcp(client))
+            }
+            Self::Wss => {
+                let (incoming_messages, client) = Client::<WSSTransport>::new(config);
+                (incoming_messages, Connection::Wss(client))
+            }
+        }
+    }
+}
+
+/// Connection to chat over one of the transports
+#[derive(Debug)]
+pub enum Connection {
+    Tcp(Client<TCPTransport>),
+    Wss(Client<WSSTransport>),
+}
+
+impl Connection {
+    /// Joins `channel`, rejoining it whenever the connection is rebuilt.
+    pub fn join(&self, channel: String) {
+        match self {
+            Self::Tcp(client) => client.join(channel),
+            Self::Wss(client) => client.join(channel),
+        }
+    }
+}
+
+/// Sends messages to chat
+///
+/// The bots only talk through this, so they can be tested without a
+/// connection to Twitch.
+#[async_trait]
+pub trait ChatClient: Send + Sync {
+    async fn say(&self, channel: String, message: String) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl ChatClient for Connection {
+    async fn say(&self, channel: String, message: String) -> Result<(), Error> {
+        match self {
+            Self::Tcp(client) => client
+                .say(channel, message)
+                .await
+                .map_err(|err| Error::SendMessage(Box::new(err))),
+            Self::Wss(client) => client
+                .say(channel, message)
+                .await
+                .map_err(|err| Error::SendMessage(Box::new(err))),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{collections::VecDeque, convert::TryFrom, sync::Mutex};
+
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+    use twitch_irc::message::{IRCMessage, ServerMessage};
+
+    use super::*;
+
+    /// Parses the raw IRC line `raw`.
+    pub fn server_message(raw: &str) -> ServerMessage {
+        let message = IRCMessage::parse(raw).expect("raw message should parse");
+        ServerMessage::try_from(message).expect("message should convert")
+    }
+
+    /// Raw IRC line of `login` with the user id `id` writing `text` in
+    /// `channel`
+    pub fn privmsg(id: &str, login: &str, channel: &str, text: &str) -> String {
+        format!(
+            "@badge-info=;badges=;color=;display-name={1};emotes=;id=1;room-id=1;tmi-sent-ts=1614600000000;user-id={0} :{1}!{1}@{1}.tmi.twitch.tv PRIVMSG #{2} :{3}",
+            id, login, channel, text
+        )
+    }
+
+    /// Chat client recording what is said and answering with scripted
+    /// messages
+    #[derive(Debug)]
+    pub struct ScriptedChat {
+        incoming: UnboundedSender<ServerMessage>,
+        answers: Mutex<VecDeque<Vec<String>>>,
+        said: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedChat {
+        /// Returns the client and the receiver of the messages it feeds in.
+        pub fn new() -> (Self, UnboundedReceiver<ServerMessage>) {
+            let (incoming, receiver) = unbounded_channel();
+
+            (
+                Self {
+                    incoming,
+                    answers: Mutex::new(VecDeque::new()),
+                    said: Mutex::new(Vec::new()),
+                },
+                receiver,
+            )
+        }
+
+        /// Feeds the raw IRC line `raw` in right away.
+        pub fn receive(&self, raw: &str) {
+            self.incoming
+                .send(server_message(raw))
+                .expect("receiver should still be alive");
+        }
+
+        /// Feeds `lines` in once the next message is said, leaving it
+        /// unanswered without lines.
+        pub fn answer_next(&self, lines: &[&str]) {
+            self.answers
+                .lock()
+                .unwrap()
+                .push_back(lines.iter().map(|line| line.to_string()).collect());
+        }
+
+        /// Returns every message said so far.
+        pub fn said(&self) -> Vec<String> {
+            self.said.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl ChatClient for ScriptedChat {
+        async fn say(&self, _channel: String, message: String) -> Result<(), Error> {
+            self.said.lock().unwrap().push(message);
+
+            let answer = self.answers.lock().unwrap().pop_front();
+            for line in answer.unwrap_or_default() {
+                self.receive(&line);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_transports() {
+        let transports: Vec<ChatTransport> = ron::de::from_str("[Tcp, Tls, Wss]").unwrap();
+
+        assert_eq!(
+            transports,
+            vec![ChatTransport::Tcp, ChatTransport::Tcp, ChatTransport::Wss]
+        );
+    }
+}
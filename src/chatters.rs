@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use secrecy::ExposeSecret;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::time::Instant;
+use tracing::{debug, instrument, warn};
+
+use crate::{bot::Error, http, util::ExponentialBackoff, SecretToken, Timestamp};
+
+pub static TMI_BASE: &str = "https://tmi.twitch.tv";
+pub static HELIX_BASE: &str = "https://api.twitch.tv/helix";
+
+/// How the bots find out who is in a channel
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChattersOptions {
+    /// Credentials for the Helix Get Chatters endpoint
+    pub helix: Option<HelixCredentials>,
+
+    /// Whether to use the deprecated tmi.twitch.tv endpoint if Helix is not
+    /// configured or fails
+    pub tmi_fallback: bool,
+
+    /// How long a looked up list of chatters is reused
+    #[serde(with = "humantime_serde")]
+    pub cache_ttl: Duration,
+
+    /// How long a target bot counts as present after writing in chat
+    #[serde(with = "humantime_serde")]
+    pub presence_window: Duration,
+
+    /// How long to suspend a bot the first time its target bot is missing
+    ///
+    /// The suspension doubles while the target bot stays missing.
+    #[serde(with = "humantime_serde")]
+    pub suspension: Duration,
+
+    /// Longest suspension while the target bot is missing
+    #[serde(with = "humantime_serde")]
+    pub max_suspension: Duration,
+}
+
+impl Default for ChattersOptions {
+    fn default() -> Self {
+        Self {
+            helix: None,
+            tmi_fallback: true,
+            cache_ttl: Duration::from_secs(60),
+            presence_window: Duration::from_secs(10 * 60),
+            suspension: Duration::from_secs(2 * 60),
+            max_suspension: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// Credentials of a moderator of the channel
+#[derive(Debug, Clone, Deserialize)]
+pub struct HelixCredentials {
+    pub client_id: String,
+
+    /// User access token with the `moderator:read:chatters` scope
+    pub token: SecretToken,
+
+    /// User id of the moderator the token belongs to
+    pub moderator_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatterResponse {
+    #[allow(dead_code)]
+    pub chatter_count: u32,
+    pub chatters: Chatters,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Chatters {
+    pub broadcaster: Vec<String>,
+    pub vips: Vec<String>,
+    pub moderators: Vec<String>,
+    pub staff: Vec<String>,
+    pub admins: Vec<String>,
+    pub global_mods: Vec<String>,
+    pub viewers: Vec<String>,
+}
+
+impl Chatters {
+    pub fn contains(&self, x: &str) -> bool {
+        self.broadcaster.iter().any(|v| v == x)
+            || self.vips.iter().any(|v| v == x)
+            || self.moderators.iter().any(|v| v == x)
+            || self.staff.iter().any(|v| v == x)
+            || self.admins.iter().any(|v| v == x)
+            || self.global_mods.iter().any(|v| v == x)
+            || self.viewers.iter().any(|v| v == x)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixResponse<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    pagination: HelixPagination,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HelixPagination {
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixUser {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixChatter {
+    user_login: String,
+}
+
+/// Client for the chatter lists of Twitch
+///
+/// Clones share their cache, so bots in the same channel can share lookups.
+#[derive(Debug, Clone)]
+pub struct ChattersApi {
+    options: ChattersOptions,
+    tmi_base: String,
+    helix_base: String,
+    cache: Arc<Mutex<HashMap<String, (Chatters, Instant)>>>,
+}
+
+impl Default for ChattersApi {
+    fn default() -> Self {
+        Self::new(ChattersOptions::default())
+    }
+}
+
+impl ChattersApi {
+    pub fn new(options: ChattersOptions) -> Self {
+        Self::with_bases(options, TMI_BASE, HELIX_BASE)
+    }
+
+    pub fn with_bases<S, T>(options: ChattersOptions, tmi_base: S, helix_base: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        Self {
+            options,
+            tmi_base: tmi_base.into(),
+            helix_base: helix_base.into(),
+            cache: Arc::default(),
+        }
+    }
+
+    pub const fn presence_window(&self) -> Duration {
+        self.options.presence_window
+    }
+
+    /// Returns the suspensions for a missing target bot.
+    pub const fn suspensions(&self) -> ExponentialBackoff {
+        ExponentialBackoff::new(self.options.suspension, self.options.max_suspension)
+    }
+
+    /// Returns everyone in `channel`.
+    ///
+    /// Lookups are cached for the configured TTL. If a lookup fails, the last
+    /// known chatters are returned no matter how old they are.
+    #[instrument(skip(self, client))]
+    pub async fn chatters(&self, client: &http::Client, channel: &str) -> Result<Chatters, Error> {
+        let cached = self
+            .cache
+            .lock()
+            .expect("chatters cache should not be poisoned")
+            .get(channel)
+            .cloned();
+
+        if let Some((chatters, fetched_at)) = &cached {
+            if fetched_at.elapsed() < self.options.cache_ttl {
+                debug!("Using cached chatters");
+                return Ok(chatters.clone());
+            }
+        }
+
+        match self.fetch(client, channel).await {
+            Ok(chatters) => {
+                self.cache
+                    .lock()
+                    .expect("chatters cache should not be poisoned")
+                    .insert(channel.to_string(), (chatters.clone(), Instant::now()));
+
+                Ok(chatters)
+            }
+            Err(err) => match cached {
+                Some((chatters, fetched_at)) => {
+                    warn!(
+                        "Could not look up chatters, using the ones from {} ago: {}",
+                        fetched_at.elapsed().as_readable(),
+                        err
+                    );
+
+                    Ok(chatters)
+                }
+                None => Err(err),
+            },
+        }
+    }
+
+    /// Looks up everyone in `channel`, asking Helix first if it is
+    /// configured.
+    async fn fetch(&self, client: &http::Client, channel: &str) -> Result<Chatters, Error> {
+        let helix = match &self.options.helix {
+            Some(credentials) => self.helix(client, credentials, channel).await,
+            None => Err(Error::NoChattersSource),
+        };
+
+        match helix {
+            Ok(chatters) => Ok(chatters),
+            Err(err) if self.options.tmi_fallback => {
+                if self.options.helix.is_some() {
+                    warn!("Falling back to tmi.twitch.tv: {}", err);
+                }
+
+                self.tmi(client, channel).await
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn tmi(&self, client: &http::Client, channel: &str) -> Result<Chatters, Error> {
+        let request = client.get(format!("{}/group/user/{}/chatters", self.tmi_base, channel));
+        let response: ChatterResponse = client
+            .send(request)
+            .await
+            .map_err(Error::SendChattersRequest)?
+            .json()
+            .await
+            .map_err(Error::DeserializeChatters)?;
+
+        Ok(response.chatters)
+    }
+
+    async fn helix(
+        &self,
+        client: &http::Client,
+        credentials: &HelixCredentials,
+        channel: &str,
+    ) -> Result<Chatters, Error> {
+        let users: HelixResponse<HelixUser> = self
+            .helix_get(client, credentials, "users", &[("login", channel)])
+            .await?;
+        let broadcaster_id = users
+            .data
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::UnknownChannel(channel.to_string()))?
+            .id;
+
+        let mut viewers = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("broadcaster_id", broadcaster_id.as_str()),
+                ("moderator_id", credentials.moderator_id.as_str()),
+                ("first", "1000"),
+            ];
+            if let Some(cursor) = &cursor {
+                query.push(("after", cursor.as_str()));
+            }
+
+            let page: HelixResponse<HelixChatter> = self
+                .helix_get(client, credentials, "chat/chatters", &query)
+                .await?;
+            viewers.extend(page.data.into_iter().map(|chatter| chatter.user_login));
+
+            match page.pagination.cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        debug!("Got {} chatters from Helix", viewers.len());
+
+        Ok(Chatters {
+            viewers,
+            ..Chatters::default()
+        })
+    }
+
+    async fn helix_get<T>(
+        &self,
+        client: &http::Client,
+        credentials: &HelixCredentials,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let request = client
+            .get(format!("{}/{}", self.helix_base, path))
+            .query(query)
+            .header("Client-Id", &credentials.client_id)
+            .bearer_auth(credentials.token.expose_secret().as_str());
+
+        client
+            .send(request)
+            .await
+            .map_err(Error::SendChattersRequest)?
+            .json()
+            .await
+            .map_err(Error::DeserializeChatters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{secrettoken::Token, HttpOptions};
+
+    fn helix_options(tmi_fallback: bool) -> ChattersOptions {
+        ChattersOptions {
+            helix: Some(HelixCredentials {
+                client_id: "client".to_string(),
+                token: SecretToken::new(Token::new("token")),
+                moderator_id: "54946241".to_string(),
+            }),
+            tmi_fallback,
+            ..ChattersOptions::default()
+        }
+    }
+
+    async fn mock_tmi(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path("/group/user/thepositivebot/chatters"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "chatter_count": 2,
+                "chatters": {
+                    "broadcaster": ["thepositivebot"],
+                    "vips": [],
+                    "moderators": [],
+                    "staff": [],
+                    "admins": [],
+                    "global_mods": [],
+                    "viewers": ["chronophylos"],
+                },
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn uses_tmi_by_default() {
+        let server = MockServer::start().await;
+        mock_tmi(&server).await;
+
+        let api = ChattersApi::with_bases(ChattersOptions::default(), server.uri(), server.uri());
+        let chatters = api
+            .chatters(&http::Client::default(), "thepositivebot")
+            .await
+            .unwrap();
+
+        assert!(chatters.contains("thepositivebot"));
+        assert!(chatters.contains("chronophylos"));
+        assert!(!chatters.contains("okayegbot"));
+    }
+
+    #[tokio::test]
+    async fn caches_lookups() {
+        let server = MockServer::start().await;
+        mock_tmi(&server).await;
+
+        let api = ChattersApi::with_bases(ChattersOptions::default(), server.uri(), server.uri());
+        let shared = api.clone();
+        let client = http::Client::default();
+
+        api.chatters(&client, "thepositivebot").await.unwrap();
+        shared.chatters(&client, "thepositivebot").await.unwrap();
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_chatters_on_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/group/user/thepositivebot/chatters"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let options = ChattersOptions {
+            cache_ttl: Duration::from_secs(0),
+            ..ChattersOptions::default()
+        };
+        let api = ChattersApi::with_bases(options, server.uri(), server.uri());
+        api.cache.lock().unwrap().insert(
+            "thepositivebot".to_string(),
+            (
+                Chatters {
+                    broadcaster: vec!["thepositivebot".to_string()],
+                    ..Chatters::default()
+                },
+                Instant::now(),
+            ),
+        );
+
+        let client = http::Client::new(
+            reqwest::Client::new(),
+            HttpOptions {
+                max_attempts: 1,
+                ..HttpOptions::default()
+            },
+        );
+        let chatters = api.chatters(&client, "thepositivebot").await.unwrap();
+
+        assert!(chatters.contains("thepositivebot"));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pages_through_helix() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users"))
+            .and(query_param("login", "thepositivebot"))
+            .and(header("Client-Id", "client"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [{ "id": "425363834", "login": "thepositivebot" }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chat/chatters"))
+            .and(query_param("broadcaster_id", "425363834"))
+            .and(query_param("after", "page2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [{ "user_id": "425363834", "user_login": "thepositivebot", "user_name": "ThePositiveBot" }],
+                "pagination": {},
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chat/chatters"))
+            .and(query_param("broadcaster_id", "425363834"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [{ "user_id": "54946241", "user_login": "chronophylos", "user_name": "Chronophylos" }],
+                "pagination": { "cursor": "page2" },
+            })))
+            .mount(&server)
+            .await;
+
+        let api = ChattersApi::with_bases(helix_options(false), server.uri(), server.uri());
+        let chatters = api
+            .chatters(&http::Client::default(), "thepositivebot")
+            .await
+            .unwrap();
+
+        assert!(chatters.contains("thepositivebot"));
+        assert!(chatters.contains("chronophylos"));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_tmi() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        mock_tmi(&server).await;
+
+        let api = ChattersApi::with_bases(helix_options(true), server.uri(), server.uri());
+        let chatters = api
+            .chatters(&http::Client::default(), "thepositivebot")
+            .await
+            .unwrap();
+
+        assert!(chatters.contains("thepositivebot"));
+    }
+
+    #[tokio::test]
+    async fn helix_errors_without_fallback() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        mock_tmi(&server).await;
+
+        let api = ChattersApi::with_bases(helix_options(false), server.uri(), server.uri());
+
+        assert!(api
+            .chatters(&http::Client::default(), "thepositivebot")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! Per bot gauge of the time left until the next claim
+
+use std::time::Duration;
+
+use metrics::{gauge, register_gauge, Unit};
+use tokio::time::Instant;
+
+use crate::board;
+
+static METRIC_COOLDOWN_REMAINING: &str = "cookiebot.cooldown.seconds_remaining";
+
+/// How often the gauge is updated while sleeping
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Registers the cooldown metrics with the recorder.
+pub(crate) fn register_metrics() {
+    register_gauge!(
+        METRIC_COOLDOWN_REMAINING,
+        Unit::Seconds,
+        "time left until the next claim"
+    );
+}
+
+pub fn set(bot: &'static str, account: &str, remaining: Duration) {
+    gauge!(
+        METRIC_COOLDOWN_REMAINING,
+        remaining.as_secs_f64(),
+        "bot" => bot,
+        "account" => account.to_string()
+    );
+}
+
+/// Sleeps for `duration` while counting the gauge down.
+pub async fn sleep(bot: &'static str, account: &str, duration: Duration) {
+    sleep_until(bot, account, Instant::now() + duration).await
+}
+
+/// Sleeps until `deadline` while counting the gauge down.
+pub async fn sleep_until(bot: &'static str, account: &str, deadline: Instant) {
+    board::next_claim(bot, account, deadline);
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        set(bot, account, remaining);
+
+        if remaining == Duration::from_secs(0) {
+            break;
+        }
+
+        tokio::time::sleep(remaining.min(REFRESH_INTERVAL)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn sleeps_for_whole_duration() {
+        let start = Instant::now();
+
+        sleep("test", "chronophylos", Duration::from_secs(150)).await;
+
+        assert_eq!(start.elapsed(), Duration::from_secs(150));
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::VecDeque, ops::Range, sync::Mutex};
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use tracing::{debug, warn};
+
+const BUFFER_SIZE: usize = 32;
+
+lazy_static! {
+    static ref LAST_MATCHES: Mutex<VecDeque<MatchRecord>> =
+        Mutex::new(VecDeque::with_capacity(BUFFER_SIZE));
+}
+
+/// A named part of a pattern
+pub type Component = (&'static str, &'static str);
+
+/// Spans of the named groups of a successful match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchRecord {
+    pub pattern: &'static str,
+    pub spans: Vec<(String, Range<usize>)>,
+}
+
+/// The first component of a pattern that did not match a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub pattern: &'static str,
+    pub component: &'static str,
+
+    /// Number of components that matched before the failing one
+    pub matched: usize,
+}
+
+/// Builds the full pattern from its components.
+pub fn build(components: &[Component]) -> Regex {
+    Regex::new(&components.iter().map(|(_, part)| *part).collect::<String>())
+        .expect("pattern should compile")
+}
+
+/// Matches growing prefixes of the pattern against `message` and returns the
+/// first component that fails, or `None` if the whole pattern matches.
+pub fn first_failing(
+    pattern: &'static str,
+    components: &[Component],
+    message: &str,
+) -> Option<Diagnosis> {
+    let mut prefix = String::new();
+
+    for (matched, (component, part)) in components.iter().enumerate() {
+        prefix.push_str(part);
+
+        let regex = Regex::new(&prefix).expect("pattern prefix should compile");
+        if !regex.is_match(message) {
+            return Some(Diagnosis {
+                pattern,
+                component,
+                matched,
+            });
+        }
+    }
+
+    None
+}
+
+/// Returns the diagnosis of the pattern that came closest to matching, or
+/// `None` if any of the patterns matched. Ties go to the earlier pattern.
+pub fn closest<I>(diagnoses: I) -> Option<Diagnosis>
+where
+    I: IntoIterator<Item = Option<Diagnosis>>,
+{
+    diagnoses
+        .into_iter()
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .rev()
+        .max_by_key(|diagnosis| diagnosis.matched)
+}
+
+/// Remembers the spans of all named groups of a successful match.
+pub fn record(pattern: &'static str, regex: &Regex, captures: &Captures) {
+    let spans = regex
+        .capture_names()
+        .flatten()
+        .filter_map(|name| captures.name(name).map(|m| (name.to_string(), m.range())))
+        .collect();
+
+    let mut buffer = LAST_MATCHES.lock().expect("lock should not be poisoned");
+    if buffer.len() == BUFFER_SIZE {
+        buffer.pop_front();
+    }
+    buffer.push_back(MatchRecord { pattern, spans });
+}
+
+/// Returns the last successful match of `pattern`.
+pub fn last_match(pattern: &str) -> Option<MatchRecord> {
+    LAST_MATCHES
+        .lock()
+        .expect("lock should not be poisoned")
+        .iter()
+        .rev()
+        .find(|record| record.pattern == pattern)
+        .cloned()
+}
+
+/// Logs the failing component of `diagnosis` together with the spans of the
+/// last successful match of the same pattern.
+pub fn report(message: &str, diagnosis: Option<Diagnosis>) {
+    let diagnosis = match diagnosis {
+        Some(diagnosis) => diagnosis,
+        None => return,
+    };
+
+    warn!(
+        "Message did not match {}: component {} failed after {} matched: {}",
+        diagnosis.pattern, diagnosis.component, diagnosis.matched, message
+    );
+
+    if let Some(last) = last_match(diagnosis.pattern) {
+        debug!(
+            "Last successful match of {} had spans {:?}",
+            last.pattern, last.spans
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPONENTS: &[Component] = &[
+        ("prefix", r"\[Test\] "),
+        ("username", r"(?P<username>\w+) "),
+        ("total", r"(?P<total>\d+) total"),
+    ];
+
+    #[test]
+    fn build_concatenates_components() {
+        assert!(build(COMPONENTS).is_match("[Test] chronophylos 12 total"));
+    }
+
+    #[test]
+    fn reports_first_failing_component() {
+        let diagnosis = first_failing("test", COMPONENTS, "[Test] chronophylos many total")
+            .expect("pattern should fail");
+
+        assert_eq!(diagnosis.component, "total");
+        assert_eq!(diagnosis.matched, 2);
+    }
+
+    #[test]
+    fn reports_nothing_on_match() {
+        assert_eq!(
+            first_failing("test", COMPONENTS, "[Test] chronophylos 12 total"),
+            None
+        );
+    }
+
+    #[test]
+    fn records_spans() {
+        let regex = build(COMPONENTS);
+        let captures = regex.captures("[Test] chronophylos 12 total").unwrap();
+
+        record("diagnostics::test", &regex, &captures);
+
+        assert_eq!(
+            last_match("diagnostics::test").unwrap().spans,
+            vec![
+                ("username".to_string(), 7..19),
+                ("total".to_string(), 20..22)
+            ]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::bot;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Error a bot stops with
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not communicate with target bot: {0}")]
+    Communication(#[source] bot::Error),
+
+    #[error("Could not check chatters: {0}")]
+    CheckChatters(#[source] bot::Error),
+
+    #[error("Could not parse reply {reply:?}: {source}")]
+    Parse {
+        reply: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error(transparent)]
+    Http(BoxError),
+
+    #[error("Could not log in to chat: {0}")]
+    Auth(#[source] bot::Error),
+
+    #[error("Invalid configuration: {0}")]
+    Config(#[source] bot::Error),
+}
+
+impl Error {
+    /// Returns an error for the reply `reply` the target bot sent, which
+    /// could not be parsed because of `source`.
+    pub fn parse<E>(reply: &str, source: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Parse {
+            reply: reply.to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    /// Returns whether the error affects every bot, e.g. because the token
+    /// was rejected.
+    pub const fn is_fatal(&self) -> bool {
+        match self {
+            Self::Communication(err) | Self::CheckChatters(err) | Self::Auth(err) => err.is_fatal(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether restarting the bot may help.
+    pub const fn is_retryable(&self) -> bool {
+        !self.is_fatal() && !matches!(self, Self::Config(_))
+    }
+}
+
+impl From<bot::Error> for Error {
+    fn from(err: bot::Error) -> Self {
+        match err {
+            bot::Error::AuthenticateChat | bot::Error::TokenRejected => Self::Auth(err),
+            bot::Error::BuildReqwestClient(_)
+            | bot::Error::ParsingHeaderValue(_)
+            | bot::Error::NoChattersSource
+            | bot::Error::UnknownChannel(_) => Self::Config(err),
+            err => Self::Communication(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_chat_errors() {
+        let auth = Error::from(bot::Error::AuthenticateChat);
+        let config = Error::from(bot::Error::NoChattersSource);
+        let lost = Error::from(bot::Error::ConnectionLost);
+
+        assert!(auth.is_fatal() && !auth.is_retryable());
+        assert!(!config.is_fatal() && !config.is_retryable());
+        assert!(matches!(lost, Error::Communication(_)));
+        assert!(lost.is_retryable());
+        assert!(Error::from(bot::Error::TokenRejected).is_retryable());
+    }
+}
@@ -0,0 +1,267 @@
+//! Every claim, recorded in a SQLite database for long-term stats
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use rusqlite::{params, types::Value, Connection};
+use tracing::{debug, info, warn};
+
+use crate::once::ClaimOutcome;
+
+/// Schema changes, applied in order to databases at an older version
+static MIGRATIONS: &[&str] = &["CREATE TABLE claims (
+    id INTEGER PRIMARY KEY,
+    bot TEXT NOT NULL,
+    account TEXT NOT NULL,
+    claimed_at TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    amount INTEGER,
+    total INTEGER,
+    remaining_secs INTEGER,
+    name TEXT
+)"];
+
+static CSV_HEADER: &str = "bot,account,claimed_at,outcome,amount,total,remaining_secs,name";
+
+lazy_static! {
+    /// Feeds the thread writing to the database, if one was opened
+    static ref WRITER: Mutex<Option<Sender<Claim>>> = Mutex::new(None);
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not open history {0}: {1}")]
+    Open(PathBuf, #[source] rusqlite::Error),
+
+    #[error("Could not migrate history to version {0}: {1}")]
+    Migrate(usize, #[source] rusqlite::Error),
+
+    #[error("Could not start writing history: {0}")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("Could not read history: {0}")]
+    Read(#[source] rusqlite::Error),
+
+    #[error("Could not write export: {0}")]
+    Export(#[source] std::io::Error),
+}
+
+/// A claim of a bot as it is recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub bot: &'static str,
+    pub account: String,
+    pub at: DateTime<Utc>,
+    pub outcome: ClaimOutcome,
+
+    /// What was claimed, for target bots that say so
+    pub name: Option<String>,
+}
+
+impl Claim {
+    pub fn new(bot: &'static str, account: &str, outcome: ClaimOutcome) -> Self {
+        Self {
+            bot,
+            account: account.to_lowercase(),
+            at: Utc::now(),
+            outcome,
+            name: None,
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    fn insert(&self, connection: &Connection) -> rusqlite::Result<()> {
+        let (outcome, amount, total, remaining_secs) = match self.outcome {
+            ClaimOutcome::Claimed { amount, total } => ("claimed", Some(amount), Some(total), None),
+            ClaimOutcome::Cooldown {
+                remaining_secs,
+                total,
+            } => (
+                "cooldown",
+                None,
+                total,
+                remaining_secs.map(|secs| secs as i64),
+            ),
+        };
+
+        connection.execute(
+            "INSERT INTO claims
+                (bot, account, claimed_at, outcome, amount, total, remaining_secs, name)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                self.bot,
+                self.account,
+                self.at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                outcome,
+                amount,
+                total,
+                remaining_secs,
+                self.name,
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Opens the database at `path` and brings its schema up to date.
+fn connect(path: &Path) -> Result<Connection, Error> {
+    let mut connection =
+        Connection::open(path).map_err(|err| Error::Open(path.to_path_buf(), err))?;
+
+    let version: usize = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|err| Error::Open(path.to_path_buf(), err))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let version = index + 1;
+        debug!("Migrating history to version {}", version);
+
+        let transaction = connection
+            .transaction()
+            .map_err(|err| Error::Migrate(version, err))?;
+        transaction
+            .execute_batch(migration)
+            .and_then(|_| transaction.pragma_update(None, "user_version", &(version as i64)))
+            .and_then(|_| transaction.commit())
+            .map_err(|err| Error::Migrate(version, err))?;
+    }
+
+    Ok(connection)
+}
+
+/// Opens the database at `path` and records every later claim in it.
+///
+/// Claims are written by a thread of their own, so the bots never wait on
+/// the disk.
+pub fn open(path: &Path) -> Result<(), Error> {
+    let connection = connect(path)?;
+    let (sender, receiver) = channel::<Claim>();
+
+    thread::Builder::new()
+        .name("history".to_string())
+        .spawn(move || {
+            for claim in receiver {
+                if let Err(err) = claim.insert(&connection) {
+                    warn!("Could not record {:?}: {}", claim, err);
+                }
+            }
+        })
+        .map_err(Error::Spawn)?;
+
+    info!("Recording claims in {}", path.display());
+    *WRITER
+        .lock()
+        .expect("history writer should not be poisoned") = Some(sender);
+
+    Ok(())
+}
+
+/// Records `claim`, if a database was opened.
+pub fn record(claim: Claim) {
+    if let Some(writer) = WRITER
+        .lock()
+        .expect("history writer should not be poisoned")
+        .as_ref()
+    {
+        // the thread only ends with the process
+        let _ = writer.send(claim);
+    }
+}
+
+/// Writes every claim in the database at `path` to `out` as CSV, oldest
+/// first.
+pub fn export_csv<W: Write>(path: &Path, mut out: W) -> Result<(), Error> {
+    let connection = connect(path)?;
+    let mut statement = connection
+        .prepare(
+            "SELECT bot, account, claimed_at, outcome, amount, total, remaining_secs, name
+                FROM claims ORDER BY id",
+        )
+        .map_err(Error::Read)?;
+    let mut rows = statement.query([]).map_err(Error::Read)?;
+
+    writeln!(out, "{}", CSV_HEADER).map_err(Error::Export)?;
+    while let Some(row) = rows.next().map_err(Error::Read)? {
+        let fields = (0..8)
+            .map(|column| row.get::<_, Value>(column).map(|value| csv_field(&value)))
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Error::Read)?;
+
+        writeln!(out, "{}", fields.join(",")).map_err(Error::Export)?;
+    }
+
+    Ok(())
+}
+
+/// Formats `value` as a CSV field, quoting text with separators or quotes.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null | Value::Blob(_) => String::new(),
+        Value::Integer(int) => int.to_string(),
+        Value::Real(real) => real.to_string(),
+        Value::Text(text) if text.contains(&[',', '"', '\n', '\r'][..]) => {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        }
+        Value::Text(text) => text.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn exports_recorded_claims() {
+        let path = env::temp_dir().join(format!("cookiebot-history-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let connection = connect(&path).unwrap();
+        let claimed = Claim::new(
+            "thepositivebot",
+            "ChronoPhylos",
+            ClaimOutcome::Claimed {
+                amount: 14,
+                total: 65,
+            },
+        )
+        .with_name("Sugar, \"sweet\" cookie");
+        let cooldown = Claim::new(
+            "okayegbot",
+            "chronophylos",
+            ClaimOutcome::Cooldown {
+                remaining_secs: Some(600),
+                total: Some(42),
+            },
+        );
+        claimed.insert(&connection).unwrap();
+        cooldown.insert(&connection).unwrap();
+        drop(connection);
+
+        // connecting again must not migrate twice
+        let mut out = Vec::new();
+        export_csv(&path, &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with("thepositivebot,chronophylos,"));
+        assert!(lines[1].ends_with(",claimed,14,65,,\"Sugar, \"\"sweet\"\" cookie\""));
+        assert!(lines[2].ends_with(",cooldown,,42,600,"));
+    }
+}
@@ -0,0 +1,298 @@
+//! HTTP client for the APIs the bots talk to
+
+use std::{fmt, time::Duration};
+
+use reqwest::{IntoUrl, Method, RequestBuilder, Response, StatusCode, Url};
+use serde::Deserialize;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::{util::ExponentialBackoff, Timestamp};
+
+/// Methods that can be sent again without changing the outcome
+static IDEMPOTENT_METHODS: &[Method] = &[Method::GET, Method::HEAD];
+
+/// URL of a proxy, with credentials if it needs authentication
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ProxyUrl(String);
+
+impl ProxyUrl {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ProxyUrl {
+    fn from(url: &str) -> Self {
+        Self(url.to_string())
+    }
+}
+
+impl fmt::Debug for ProxyUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Url::parse(&self.0) {
+            Ok(mut url) if url.password().is_some() => {
+                let _ = url.set_password(Some("***"));
+                write!(f, "{:?}", url.as_str())
+            }
+            _ => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+/// How long and how often API requests are tried and where they are sent
+/// through
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HttpOptions {
+    /// Time a whole request may take, including reading the response
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+
+    /// How often a request is sent before giving up
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubling after every further attempt
+    #[serde(with = "humantime_serde")]
+    pub retry_delay: Duration,
+
+    /// Proxy for plain HTTP requests, instead of `HTTP_PROXY`
+    pub http_proxy: Option<ProxyUrl>,
+
+    /// Proxy for HTTPS requests, instead of `HTTPS_PROXY`
+    pub https_proxy: Option<ProxyUrl>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_attempts: 3,
+            retry_delay: Duration::from_secs(1),
+            http_proxy: None,
+            https_proxy: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Returns the proxies set in the config.
+    ///
+    /// Without any, reqwest falls back to the `HTTP_PROXY` and `HTTPS_PROXY`
+    /// environment variables.
+    pub fn proxies(&self) -> Result<Vec<reqwest::Proxy>, reqwest::Error> {
+        let http = self
+            .http_proxy
+            .as_ref()
+            .map(|url| reqwest::Proxy::http(url.as_str()));
+        let https = self
+            .https_proxy
+            .as_ref()
+            .map(|url| reqwest::Proxy::https(url.as_str()));
+
+        http.into_iter().chain(https).collect()
+    }
+
+    fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff::new(self.retry_delay, self.retry_delay * 16).with_jitter(0.1)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{source} (after {attempts} attempts)")]
+pub struct RetryError {
+    pub attempts: u32,
+    pub source: reqwest::Error,
+}
+
+impl RetryError {
+    /// Returns whether the server answered with an error status.
+    pub fn is_status(&self) -> bool {
+        self.source.is_status()
+    }
+
+    /// Returns the error status the server answered with.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.source.status()
+    }
+}
+
+/// Returns whether the request might succeed if it is sent again.
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err
+            .status()
+            .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+}
+
+/// `reqwest::Client` that retries idempotent requests on transient failures
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    options: HttpOptions,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(reqwest::Client::new(), HttpOptions::default())
+    }
+}
+
+impl Client {
+    pub const fn new(inner: reqwest::Client, options: HttpOptions) -> Self {
+        Self { inner, options }
+    }
+
+    pub fn get<U>(&self, url: U) -> RequestBuilder
+    where
+        U: IntoUrl,
+    {
+        self.inner.get(url)
+    }
+
+    pub fn post<U>(&self, url: U) -> RequestBuilder
+    where
+        U: IntoUrl,
+    {
+        self.inner.post(url)
+    }
+
+    /// Sends `request` and fails on error statuses.
+    ///
+    /// Timeouts, connection errors and server errors are retried with backoff
+    /// if the request is idempotent.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, RetryError> {
+        let mut request = request.build().map_err(|source| RetryError {
+            attempts: 0,
+            source,
+        })?;
+        let idempotent = IDEMPOTENT_METHODS.contains(request.method());
+        let mut backoff = self.options.backoff();
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let retry = request
+                .try_clone()
+                .filter(|_| idempotent && attempts < self.options.max_attempts);
+            let result = self
+                .inner
+                .execute(request)
+                .await
+                .and_then(Response::error_for_status);
+
+            match (result, retry) {
+                (Ok(response), _) => return Ok(response),
+                (Err(err), Some(retry)) if is_transient(&err) => {
+                    let delay = backoff.next_delay();
+                    warn!("{}. Retrying in {}", err, delay.as_readable());
+                    sleep(delay).await;
+
+                    request = retry;
+                }
+                (Err(source), _) => return Err(RetryError { attempts, source }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn client() -> Client {
+        Client::new(
+            reqwest::Client::builder()
+                .timeout(Duration::from_millis(200))
+                .build()
+                .unwrap(),
+            HttpOptions {
+                retry_delay: Duration::from_millis(10),
+                ..HttpOptions::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = client();
+        client.send(client.get(server.uri())).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_timeouts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let client = client();
+        let err = client.send(client.get(server.uri())).await.unwrap_err();
+
+        assert_eq!(err.attempts, 3);
+        assert!(err.source.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client();
+        let err = client.send(client.get(server.uri())).await.unwrap_err();
+
+        assert_eq!(err.attempts, 1);
+        assert!(err.is_status());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_posts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client();
+        let err = client.send(client.post(server.uri())).await.unwrap_err();
+
+        assert_eq!(err.attempts, 1);
+    }
+
+    #[test]
+    fn deserialize_partial_options() {
+        let options: HttpOptions =
+            ron::de::from_str("(timeout: \"10s\")").expect("options should parse");
+
+        assert_eq!(
+            options,
+            HttpOptions {
+                timeout: Duration::from_secs(10),
+                ..HttpOptions::default()
+            }
+        );
+    }
+}
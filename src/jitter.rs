@@ -0,0 +1,138 @@
+use std::{fmt::Display, time::Duration};
+
+use rand::Rng;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::Timestamp;
+
+#[derive(Debug, thiserror::Error)]
+pub enum JitterError {
+    #[error("Jitter ends at {max} before it starts at {min}")]
+    EndsBeforeStart { min: String, max: String },
+}
+
+/// Random extra delay before every claim, so claims do not happen at the
+/// second the cooldown expires
+///
+/// The delay is drawn uniformly from `min` to `max` each time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub struct Jitter {
+    #[serde(default, with = "humantime_serde")]
+    pub min: Duration,
+    #[serde(default, with = "humantime_serde")]
+    pub max: Duration,
+}
+
+impl Display for Jitter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_none() {
+            write!(f, "none")
+        } else {
+            write!(
+                f,
+                "{} to {}",
+                self.min.as_readable(),
+                self.max.as_readable()
+            )
+        }
+    }
+}
+
+impl Jitter {
+    pub fn validate(&self) -> Result<(), JitterError> {
+        if self.max < self.min {
+            return Err(JitterError::EndsBeforeStart {
+                min: self.min.as_readable(),
+                max: self.max.as_readable(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether no delay is ever added.
+    pub fn is_none(&self) -> bool {
+        self.max == Duration::from_secs(0)
+    }
+
+    /// Returns `wait` with a random delay added.
+    pub fn add_to(&self, wait: Duration) -> Duration {
+        self.add_to_with(wait, &mut rand::thread_rng())
+    }
+
+    /// Returns `wait` with a delay drawn from `rng` added.
+    pub fn add_to_with<R: Rng>(&self, wait: Duration, rng: &mut R) -> Duration {
+        if self.is_none() {
+            return wait;
+        }
+
+        let delay = if self.max <= self.min {
+            self.min
+        } else {
+            rng.gen_range(self.min..=self.max)
+        };
+        info!("Adding {} of jitter", delay.as_readable());
+
+        wait + delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn deserialize_window() {
+        let jitter: Jitter = ron::de::from_str(r#"(max: "10m")"#).unwrap();
+
+        assert_eq!(jitter.min, Duration::from_secs(0));
+        assert_eq!(jitter.max, Duration::from_secs(600));
+        assert_eq!(jitter.to_string(), "0s to 10m");
+    }
+
+    #[test]
+    fn rejects_window_ending_before_start() {
+        let jitter = Jitter {
+            min: Duration::from_secs(600),
+            max: Duration::from_secs(60),
+        };
+
+        assert_eq!(
+            jitter.validate().unwrap_err().to_string(),
+            "Jitter ends at 1m before it starts at 10m"
+        );
+    }
+
+    #[test]
+    fn none_adds_nothing() {
+        let wait = Duration::from_secs(7200);
+
+        assert_eq!(Jitter::default().add_to(wait), wait);
+    }
+
+    proptest! {
+        #[test]
+        fn stays_within_window(
+            min in 0u64..10_000,
+            spread in 0u64..10_000,
+            wait in 0u64..10_000,
+            seed: u64,
+        ) {
+            let jitter = Jitter {
+                min: Duration::from_secs(min),
+                max: Duration::from_secs(min + spread),
+            };
+            let wait = Duration::from_secs(wait);
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let jittered = jitter.add_to_with(wait, &mut rng);
+
+            prop_assert!(jittered >= wait + jitter.min || jitter.is_none());
+            prop_assert!(jittered <= wait + jitter.max);
+        }
+    }
+}
@@ -1,63 +1,144 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use lazy_static::lazy_static;
-use secrecy::ExposeSecret;
-use tokio::{
-    sync::mpsc::UnboundedReceiver,
-    time::{sleep, sleep_until, Instant},
-};
+use metrics::{register_counter, register_gauge, register_histogram, Unit};
+use tokio::{sync::mpsc::UnboundedReceiver, time::Instant};
 use tracing::{info, instrument, warn};
-use twitch_irc::{
-    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, TCPTransport,
-    TwitchIRCClient,
-};
+use twitch_irc::message::ServerMessage;
 
 use crate::{
-    bot::{self, Bot},
+    admin, board,
+    bot::{self, Bot, DryRun},
+    chat::{ChatClient, ChatTransport},
+    chatters::ChattersApi,
+    cooldown, history, http,
     leavesbot::parser::ClaimResponse,
-    SecretToken, Timestamp,
+    metrics::{ClaimMetrics, LegacyNames},
+    notify,
+    once::{run_once, CancellationToken, OnceError},
+    presence::Presence,
+    secrettoken::SharedToken,
+    state,
+    suffix::AntiDuplicate,
+    util::TokenBucket,
+    Blackouts, ChannelFailover, CommunicateOptions, Error, HttpOptions, Jitter, QuietHours,
+    Schedule, SkipProbability, Timestamp,
 };
 
-use super::{parser::ClaimResponseParserError, patterns::GENERIC_ANSWER};
+use super::{patterns::GENERIC_ANSWER, Config};
 
-const COOLDOWN_COST: f32 = 8.;
-const MULTIPLIER_COST: f32 = 24.;
-const THRESHOLD_COST_MULTIPLIER: f32 = 1.5;
+static BOT_LABEL: &str = "leavesbot";
 static USER_ID: &str = "731132488";
 static USER_NAME: &str = "leavesbot";
 static CLAIM_MESSAGE: &str = "*leaves";
 static CDR_MESSAGE: &str = "*cdr";
 static MULTIPLIER_MESSAGE: &str = "*multiplier";
-lazy_static! {
-    static ref CLAIM_COOLDOWN: Duration = Duration::from_secs(3600);
+
+/// Names of the claim metrics before they were labeled by bot
+static LEGACY_METRICS: LegacyNames = LegacyNames {
+    total: "cookiebot.leaves.total",
+    claims_attempted: "cookiebot.leaves.claims.attempted",
+    claims_skipped: "cookiebot.leaves.claims.skipped",
+    claims_succeeded: "cookiebot.leaves.claims.succeeded",
+    claims_failed: "cookiebot.leaves.claims.failed",
+    claims_unparsed: "cookiebot.leaves.claims.parse_failures",
+    claimed_amount: "cookiebot.leaves.claimed",
+};
+
+/// Registers the leaf metrics with the recorder, with the names before
+/// they were labeled by bot if `legacy` is set.
+pub(crate) fn register_metrics(legacy: bool) {
+    if !legacy {
+        return;
+    }
+
+    register_gauge!(LEGACY_METRICS.total, Unit::Count, "total number of leaves");
+    register_counter!(
+        LEGACY_METRICS.claims_attempted,
+        Unit::Count,
+        "number of leaf claims sent to chat"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_skipped,
+        Unit::Count,
+        "number of leaf claims skipped on purpose"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_succeeded,
+        Unit::Count,
+        "number of successful leaf claims"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_failed,
+        Unit::Count,
+        "number of leaf claims that hit the cooldown"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_unparsed,
+        Unit::Count,
+        "number of leaf claim answers that could not be parsed"
+    );
+    register_histogram!(
+        LEGACY_METRICS.claimed_amount,
+        Unit::Count,
+        "leaves gained per claim"
+    );
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("Could not check chatters: {0}")]
-    CheckChatters(#[source] bot::Error),
+/// Something worth buying in the LeavesBot shop after a claim
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Purchase {
+    CooldownReset,
+    Multiplier,
+}
 
-    #[error("Could not communicate with target bot: {0}")]
-    CommunicationError(#[source] bot::Error),
+impl Purchase {
+    const fn message(self) -> &'static str {
+        match self {
+            Self::CooldownReset => CDR_MESSAGE,
+            Self::Multiplier => MULTIPLIER_MESSAGE,
+        }
+    }
+}
 
-    #[error("Could not parse claim egs message: {0}")]
-    ParseClaimResponse(#[from] ClaimResponseParserError),
+/// Everything a [`LeafBot`] is built from besides its account
+#[derive(Debug, Clone, Default)]
+pub struct LeafBotOptions {
+    /// The leavesbot section, which also holds the channels, schedule and
+    /// communication options
+    pub config: Config,
+    pub blackouts: Blackouts,
+    pub http: HttpOptions,
+    pub chatters: ChattersApi,
 }
 
 #[derive(Debug)]
 pub struct LeafBot {
     username: String,
-    token: SecretToken,
-    channel: String,
+    metrics: ClaimMetrics,
+    token: SharedToken,
+    channels: ChannelFailover,
+    cooldown_cost: f32,
+    multiplier_cost: f32,
+    threshold_multiplier: f32,
+    schedule: Schedule,
+    blackouts: Blackouts,
+    communicate: CommunicateOptions,
+    chatters: ChattersApi,
+    presence: Presence,
+    anti_duplicate: AntiDuplicate,
+    pacing: Option<Arc<TokenBucket>>,
+    message_limiter: Option<Arc<TokenBucket>>,
+    chat_transport: ChatTransport,
+    dry_run: Option<DryRun>,
+    jitter: Jitter,
+    quiet_hours: Option<QuietHours>,
+    skip_probability: SkipProbability,
+    client: http::Client,
 }
 
 impl Bot for LeafBot {
-    fn accepts_invalid_certs(&self) -> bool {
-        false
-    }
-
-    fn get_channel(&self) -> &str {
-        &self.channel
+    fn get_channels(&self) -> &ChannelFailover {
+        &self.channels
     }
 
     fn get_bot_id(&self) -> &str {
@@ -68,72 +149,255 @@ impl Bot for LeafBot {
         &self.username
     }
 
+    fn get_token(&self) -> &SharedToken {
+        &self.token
+    }
+
+    fn get_communicate_options(&self) -> CommunicateOptions {
+        self.communicate
+    }
+
     fn get_generic_answer(&self) -> &regex::Regex {
         &GENERIC_ANSWER
     }
+
+    fn get_chatters_api(&self) -> &ChattersApi {
+        &self.chatters
+    }
+
+    fn get_presence(&self) -> &Presence {
+        &self.presence
+    }
+
+    fn get_anti_duplicate(&self) -> &AntiDuplicate {
+        &self.anti_duplicate
+    }
+
+    fn get_pacing(&self) -> Option<&TokenBucket> {
+        self.pacing.as_deref()
+    }
+
+    fn get_message_limiter(&self) -> Option<&TokenBucket> {
+        self.message_limiter.as_deref()
+    }
+
+    fn get_chat_transport(&self) -> ChatTransport {
+        self.chat_transport
+    }
+
+    fn get_dry_run(&self) -> Option<&DryRun> {
+        self.dry_run.as_ref()
+    }
+
+    fn get_client(&self) -> &http::Client {
+        &self.client
+    }
 }
 
 impl LeafBot {
-    pub fn new(username: String, token: SecretToken, channel: String) -> Self {
-        Self {
+    pub const CLAIM_COOLDOWN: Duration = Duration::from_secs(3600);
+
+    /// Creates the bot of the account `username` logging in with `token`.
+    pub fn with_options(
+        username: String,
+        token: SharedToken,
+        options: LeafBotOptions,
+    ) -> Result<Self, bot::Error> {
+        let LeafBotOptions {
+            config,
+            blackouts,
+            http,
+            chatters,
+        } = options;
+        let client = bot::build_client(http, false)?;
+        let login = username.to_lowercase();
+
+        Ok(Self {
+            metrics: ClaimMetrics::new(BOT_LABEL, &login, &LEGACY_METRICS),
             username,
             token,
-            channel,
-        }
+            channels: ChannelFailover::new(BOT_LABEL, &login, config.channel),
+            cooldown_cost: config.cooldown_cost,
+            multiplier_cost: config.multiplier_cost,
+            threshold_multiplier: config.threshold_multiplier,
+            schedule: config.schedule,
+            blackouts,
+            communicate: config.communicate,
+            chatters,
+            presence: Presence::default(),
+            anti_duplicate: AntiDuplicate::new(config.duplicate_suffix),
+            pacing: None,
+            message_limiter: None,
+            chat_transport: ChatTransport::default(),
+            dry_run: None,
+            jitter: Jitter::default(),
+            quiet_hours: None,
+            skip_probability: config.skip_probability,
+            client,
+        })
+    }
+
+    /// Creates the bot from positional arguments.
+    ///
+    /// Kept for compatibility, prefer [`with_options`](Self::with_options).
+    pub fn new(
+        username: String,
+        token: SharedToken,
+        config: Config,
+        blackouts: Blackouts,
+        http: HttpOptions,
+        chatters: ChattersApi,
+    ) -> Result<Self, bot::Error> {
+        Self::with_options(
+            username,
+            token,
+            LeafBotOptions {
+                config,
+                blackouts,
+                http,
+                chatters,
+            },
+        )
     }
 
-    #[instrument]
+    /// Spaces the commands of the bot with those of the other accounts
+    /// sharing `pacing`.
+    pub fn with_pacing(mut self, pacing: Arc<TokenBucket>) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
+    /// Keeps the messages of the bot within the limit of its account, shared
+    /// with the other bots of the account through `limiter`.
+    pub fn with_message_limiter(mut self, limiter: Arc<TokenBucket>) -> Self {
+        self.message_limiter = Some(limiter);
+        self
+    }
+
+    /// Connects to chat over `transport`.
+    pub const fn with_chat_transport(mut self, transport: ChatTransport) -> Self {
+        self.chat_transport = transport;
+        self
+    }
+
+    /// Logs the commands of the bot instead of sending them if `dry_run` is
+    /// enabled.
+    pub fn with_dry_run(mut self, dry_run: DryRun) -> Self {
+        self.dry_run = Some(dry_run).filter(|dry_run| dry_run.enabled);
+        self
+    }
+
+    /// Adds a random delay from `jitter` to every wait before a claim.
+    pub const fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Keeps the bot from claiming during `quiet_hours`.
+    pub const fn with_quiet_hours(mut self, quiet_hours: Option<QuietHours>) -> Self {
+        self.quiet_hours = quiet_hours;
+        self
+    }
+
+    #[instrument(skip(self), fields(bot = BOT_LABEL, username = %self.get_login()))]
     pub async fn run(&self) -> Result<(), Error> {
         info!("Running LeafBot");
+        let _running = self.metrics.running();
+        info!("Claim schedule: {}", self.schedule);
+        info!("Claim jitter: {}", self.jitter);
+        if let Some(quiet_hours) = &self.quiet_hours {
+            info!("Quiet hours: {}", quiet_hours);
+        }
+
+        for blackout in self.blackouts.describe_upcoming(3) {
+            info!("Upcoming blackout: {}", blackout);
+        }
+
+        self.resume_saved_state().await;
+
+        // the connection stays open between claims so chat activity of
+        // LeavesBot shows that it is online
+        let (mut incoming_messages, mut client) = self.login().await?;
 
         loop {
-            // check if the bot is online
-            if !self
-                .check_chatters(USER_NAME)
-                .await
-                .map_err(Error::CheckChatters)?
-            {
-                warn!(
-                    "LeavesBot is not in #{}. Suspending bot for 30 minutes",
-                    self.channel
-                );
-                sleep(Duration::from_secs(60 * 30)).await;
+            if self.skip_probability.should_skip() {
+                self.metrics.skipped(self.get_channel());
+                self.wait_for(self.claim_delay(Self::CLAIM_COOLDOWN)).await;
                 continue;
             }
 
-            // login to tmi
-            let (mut incoming_messages, client) = self.login();
+            self.wait_for_quiet_hours().await;
+
+            // check if the bot is online
+            self.wait_for_presence(&mut incoming_messages, USER_NAME)
+                .await
+                .map_err(Error::CheckChatters)?;
 
             // try claiming leaves
-            let amount = match self.claim(&client, &mut incoming_messages).await? {
+            admin::wait_while_paused(BOT_LABEL).await;
+            cooldown::set(BOT_LABEL, &self.get_login(), Duration::from_secs(0));
+            let response = match self.claim(&client, &mut incoming_messages).await {
+                Err(Error::Communication(err)) if err.suspension().is_some() => {
+                    self.wait_out(&err).await;
+                    continue;
+                }
+                Err(Error::Communication(bot::Error::ConnectionLost)) => {
+                    let (new_incoming_messages, new_client) = self.reconnect().await?;
+                    incoming_messages = new_incoming_messages;
+                    client = new_client;
+                    continue;
+                }
+                result => result?,
+            };
+
+            board::claimed(BOT_LABEL, &self.get_login(), response.clone().into());
+            history::record(history::Claim::new(
+                BOT_LABEL,
+                &self.get_login(),
+                response.clone().into(),
+            ));
+            notify::send(notify::Event::Claimed {
+                bot: BOT_LABEL,
+                account: self.get_login(),
+                outcome: response.clone().into(),
+            });
+            let amount = match response {
                 ClaimResponse::Success { amount, total, .. } => {
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.metrics.succeeded(self.get_channel(), amount as f64);
+
                     info!("Claimed {} leaves for a total of {} leaves", amount, total);
 
                     amount as f32
                 }
                 ClaimResponse::Cooldown {
-                    minutes, seconds, ..
+                    minutes,
+                    seconds,
+                    total,
+                    ..
                 } => {
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.metrics.failed(self.get_channel());
+
                     warn!("Could not claim leaves since cooldown is active");
                     let secs = seconds.unwrap_or(0);
                     let mins = minutes.unwrap_or(0);
 
-                    self.wait_for(Duration::from_secs(secs + mins * 60)).await;
+                    self.wait_for(self.claim_delay(Duration::from_secs(secs + mins * 60)))
+                        .await;
                     continue;
                 }
             };
 
-            let cooldown_deadline = Instant::now() + *CLAIM_COOLDOWN;
-
-            // buy cooldown reduction or multiplier
-            if amount >= (COOLDOWN_COST * THRESHOLD_COST_MULTIPLIER) {
-                // wait 5 seconds before sending command
-                // buy cooldown
-            }
+            let cooldown_deadline = Instant::now() + self.claim_delay(Self::CLAIM_COOLDOWN);
 
-            if amount >= (COOLDOWN_COST + MULTIPLIER_COST * THRESHOLD_COST_MULTIPLIER) {
-                // wait 5 seconds before sending command
-                // buy multiplier
+            // nothing is bought automatically, buying spends the leaves
+            for purchase in self.purchases(amount) {
+                info!(
+                    "Claimed enough leaves for {:?}, buy it with {}",
+                    purchase,
+                    purchase.message()
+                );
             }
 
             // wait 1 hour
@@ -141,45 +405,165 @@ impl LeafBot {
         }
     }
 
+    /// Restores the total and waits out the cooldown saved before the last
+    /// restart.
+    async fn resume_saved_state(&self) {
+        let saved = match state::saved(BOT_LABEL, &self.get_login()) {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        if let Some(total) = saved.total {
+            self.metrics.total(self.get_channel(), total as f64);
+        }
+        if let Some(remaining) = saved.remaining() {
+            info!("Saved cooldown, waiting for {}", remaining.as_readable());
+            board::ready(BOT_LABEL, &self.get_login());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), remaining).await;
+        }
+    }
+
+    /// Waits until the quiet hours are over, plus jitter.
+    async fn wait_for_quiet_hours(&self) {
+        if let Some(remaining) = self.quiet_hours.as_ref().and_then(QuietHours::remaining) {
+            let wait = self.jitter.add_to(remaining);
+            info!("Quiet hours, waiting for {}", wait.as_readable());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), wait).await;
+        }
+    }
+
+    /// Returns how long to wait before claiming when the cooldown expires in
+    /// `cooldown`, following the schedule, adding jitter and skipping
+    /// blackouts.
+    fn claim_delay(&self, cooldown: Duration) -> Duration {
+        self.blackouts
+            .defer(self.jitter.add_to(self.schedule.wait_time(cooldown)))
+    }
+
     async fn wait_for(&self, duration: Duration) {
         info!("Waiting for {}", duration.as_readable());
-        sleep(duration).await;
+        cooldown::sleep(BOT_LABEL, &self.get_login(), duration).await;
+    }
+
+    /// Returns what is worth buying after claiming `amount` leaves.
+    fn purchases(&self, amount: f32) -> Vec<Purchase> {
+        let mut purchases = Vec::new();
+
+        if amount >= self.cooldown_cost * self.threshold_multiplier {
+            purchases.push(Purchase::CooldownReset);
+        }
+
+        if amount >= self.cooldown_cost + self.multiplier_cost * self.threshold_multiplier {
+            purchases.push(Purchase::Multiplier);
+        }
+
+        purchases
     }
 
     async fn wait_until(&self, deadline: Instant) {
-        info!("Waiting until {:?}", deadline);
-        sleep_until(deadline).await;
+        info!(
+            "Waiting for {}",
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_readable()
+        );
+        cooldown::sleep_until(BOT_LABEL, &self.get_login(), deadline).await;
     }
 
-    #[instrument]
-    fn login(
+    /// Claims leaves once over a new chat connection.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use cookiebot::{once::CancellationToken, LeafBot};
+    /// # async fn example(bot: LeafBot) {
+    /// let response = bot
+    ///     .claim_once(Some(Duration::from_secs(30)), &CancellationToken::new())
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn claim_once(
         &self,
-    ) -> (
-        UnboundedReceiver<ServerMessage>,
-        TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
-    ) {
-        let config = ClientConfig::new_simple(StaticLoginCredentials::new(
-            self.username.clone(),
-            Some(self.token.expose_secret().to_string()),
-        ));
-        let (incoming_messages, client) =
-            TwitchIRCClient::<TCPTransport, StaticLoginCredentials>::new(config);
-
-        client.join(self.channel.clone());
-
-        (incoming_messages, client)
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<ClaimResponse, OnceError<Error>> {
+        run_once(
+            async {
+                let (mut incoming_messages, client) = self.login().await?;
+                self.claim(&client, &mut incoming_messages).await
+            },
+            timeout,
+            cancel,
+        )
+        .await
     }
 
     #[instrument(skip(self, client, incoming_messages))]
     async fn claim(
         &self,
-        client: &TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
+        client: &dyn ChatClient,
         incoming_messages: &mut UnboundedReceiver<ServerMessage>,
     ) -> Result<ClaimResponse, Error> {
-        self.communicate(client, incoming_messages, CLAIM_MESSAGE)
-            .await
-            .map_err(Error::CommunicationError)?
-            .parse()
-            .map_err(Error::ParseClaimResponse)
+        self.metrics.attempted(self.get_channel());
+
+        let message = self
+            .communicate(client, incoming_messages, CLAIM_MESSAGE)
+            .await?;
+
+        message.parse().map_err(|err| {
+            self.metrics.unparsed(self.get_channel());
+            Error::parse(&message, err)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{secrettoken::Token, Channels, SecretToken};
+
+    fn test_bot(config: Config) -> LeafBot {
+        LeafBot::with_options(
+            "Chronophylos".to_string(),
+            SecretToken::new(Token::new("token")).into(),
+            LeafBotOptions {
+                config: Config {
+                    channel: Channels::from("leavesbot"),
+                    ..config
+                },
+                ..LeafBotOptions::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn suggests_what_the_claim_pays_for() {
+        let bot = test_bot(Config::default());
+
+        assert_eq!(bot.purchases(11.), vec![]);
+        assert_eq!(bot.purchases(12.), vec![Purchase::CooldownReset]);
+        assert_eq!(
+            bot.purchases(44.),
+            vec![Purchase::CooldownReset, Purchase::Multiplier]
+        );
+    }
+
+    #[test]
+    fn suggests_with_configured_prices() {
+        let bot = test_bot(Config {
+            cooldown_cost: 20.,
+            multiplier_cost: 40.,
+            threshold_multiplier: 1.,
+            ..Config::default()
+        });
+
+        assert_eq!(bot.purchases(19.), vec![]);
+        assert_eq!(bot.purchases(20.), vec![Purchase::CooldownReset]);
+        assert_eq!(
+            bot.purchases(60.),
+            vec![Purchase::CooldownReset, Purchase::Multiplier]
+        );
     }
 }
@@ -1,7 +1,76 @@
 use serde::Deserialize;
 
+use crate::{
+    Channels, CommunicateOptions, Jitter, Schedule, SecretToken, SkipProbability, SuffixStrategy,
+};
+
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct Config {
     pub disabled: bool,
-    pub channel: String,
+
+    /// Channels to claim leaves in, in order of preference, required once
+    /// the bot is enabled
+    pub channel: Channels,
+
+    /// Account to claim leaves with instead of the top level `username`
+    pub username: Option<String>,
+
+    /// Token of the account instead of the top level `token`, never refreshed
+    pub token: Option<SecretToken>,
+
+    /// Price of a cooldown reset in the LeavesBot shop
+    pub cooldown_cost: f32,
+
+    /// Price of a multiplier in the LeavesBot shop
+    pub multiplier_cost: f32,
+
+    /// Factor applied to shop prices before suggesting a purchase
+    pub threshold_multiplier: f32,
+
+    pub schedule: Schedule,
+
+    /// Random delay before every claim instead of the top level
+    /// `claim_jitter`
+    pub claim_jitter: Option<Jitter>,
+
+    /// Chance of skipping a claim on purpose and waiting another cooldown
+    pub skip_probability: SkipProbability,
+
+    pub communicate: CommunicateOptions,
+    pub duplicate_suffix: SuffixStrategy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            disabled: true,
+            channel: Channels::default(),
+            username: None,
+            token: None,
+            cooldown_cost: 8.,
+            multiplier_cost: 24.,
+            threshold_multiplier: 1.5,
+            schedule: Schedule::default(),
+            claim_jitter: None,
+            skip_probability: SkipProbability::default(),
+            communicate: CommunicateOptions::default(),
+            duplicate_suffix: SuffixStrategy::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_shop_prices() {
+        let config: Config = ron::de::from_str(r#"(disabled: false, channel: "teischente")"#)
+            .expect("config should parse");
+
+        assert_eq!(config.cooldown_cost, 8.);
+        assert_eq!(config.multiplier_cost, 24.);
+        assert_eq!(config.threshold_multiplier, 1.5);
+    }
 }
@@ -3,5 +3,7 @@ mod config;
 mod parser;
 mod patterns;
 
-pub use bot::LeafBot;
+pub(crate) use bot::register_metrics;
+pub use bot::{LeafBot, LeafBotOptions};
 pub use config::Config;
+pub use parser::ClaimResponse;
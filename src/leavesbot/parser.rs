@@ -2,7 +2,9 @@ use std::str::FromStr;
 
 use tracing::instrument;
 
-use super::patterns::{CLAIM_BAD, CLAIM_GOOD};
+use crate::{diagnostics, normalize::normalize, once::ClaimOutcome};
+
+use super::patterns::{self, CLAIM_BAD, CLAIM_BAD_NAME, CLAIM_GOOD, CLAIM_GOOD_NAME};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClaimResponseParserError {
@@ -49,6 +51,7 @@ impl ClaimResponse {
         let captures = CLAIM_GOOD
             .captures(s)
             .ok_or(ClaimResponseParserError::ExpectedSuccess)?;
+        diagnostics::record(CLAIM_GOOD_NAME, &CLAIM_GOOD, &captures);
 
         let username = captures
             .name("username")
@@ -82,6 +85,7 @@ impl ClaimResponse {
         let captures = CLAIM_BAD
             .captures(s)
             .ok_or(ClaimResponseParserError::ExpectedBad)?;
+        diagnostics::record(CLAIM_BAD_NAME, &CLAIM_BAD, &captures);
 
         let username = captures
             .name("username")
@@ -128,16 +132,41 @@ impl FromStr for ClaimResponse {
 
     #[instrument]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = &normalize(s);
+
         if CLAIM_GOOD.is_match(s) {
             ClaimResponse::parse_success(s)
         } else if CLAIM_BAD.is_match(s) {
             ClaimResponse::parse_cooldown(s)
         } else {
+            diagnostics::report(s, patterns::diagnose(s));
+
             Err(ClaimResponseParserError::InvalidMessage)
         }
     }
 }
 
+impl From<ClaimResponse> for ClaimOutcome {
+    fn from(claim: ClaimResponse) -> Self {
+        match claim {
+            ClaimResponse::Success { amount, total, .. } => Self::Claimed {
+                amount: amount.into(),
+                total: total.into(),
+            },
+            ClaimResponse::Cooldown {
+                minutes,
+                seconds,
+                total,
+                ..
+            } => Self::Cooldown {
+                remaining_secs: (minutes.is_some() || seconds.is_some())
+                    .then(|| minutes.unwrap_or(0) * 60 + seconds.unwrap_or(0)),
+                total: Some(total.into()),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,7 +181,38 @@ mod tests {
             ClaimResponse::Cooldown {
                 username: "chronophylos".to_string(),
                 minutes: Some(54),
-                seconds: Some(04),
+                seconds: Some(4),
+                total: 34
+            }
+        );
+    }
+
+    #[test]
+    fn test_success_with_messy_trailer() {
+        let text = "🍃 @chronophylos > Four Leaf Clover 🍀 (+24) | You've got 34 leaves now! | Get more leaves in 1 hour... 🍃\u{FE0F}   \u{E0000}";
+        let claim = text.parse::<ClaimResponse>().unwrap();
+
+        assert_eq!(
+            claim,
+            ClaimResponse::Success {
+                username: "chronophylos".to_string(),
+                amount: 24,
+                total: 34
+            }
+        );
+    }
+
+    #[test]
+    fn test_failure_with_messy_trailer() {
+        let text = "🍃 @chronophylos > FeelsBadMan You need to wait 54:04 minutes until you can get more leaves | You've got 34 leaves 🍃 \u{E0000} ";
+        let claim = text.parse::<ClaimResponse>().unwrap();
+
+        assert_eq!(
+            claim,
+            ClaimResponse::Cooldown {
+                username: "chronophylos".to_string(),
+                minutes: Some(54),
+                seconds: Some(4),
                 total: 34
             }
         );
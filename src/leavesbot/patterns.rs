@@ -1,20 +1,52 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::diagnostics::{self, Component, Diagnosis};
+
+pub static CLAIM_GOOD_NAME: &str = "leavesbot::CLAIM_GOOD";
+pub static CLAIM_BAD_NAME: &str = "leavesbot::CLAIM_BAD";
+
+// https://regex101.com/r/ZQ0SZH/1
+const CLAIM_GOOD_COMPONENTS: &[Component] = &[
+    ("prefix", r"\x{1F343} "),
+    ("username", r"@(?P<username>\w+) > "),
+    ("amount", r".* \((?P<amount>[+-]\d+)\) \| "),
+    ("total", r"You've got (?P<total>-?\d+) leaves now! \| "),
+    ("suffix", r"Get more leaves in 1 hour\.\.\."),
+];
+
+// https://regex101.com/r/wuuDX2/1
+const CLAIM_BAD_COMPONENTS: &[Component] = &[
+    ("prefix", r"\x{1F343} "),
+    ("username", r"@(?P<username>\w+) > "),
+    ("wait", r"FeelsBadMan You need to wait "),
+    (
+        "cooldown",
+        r"(?P<minutes>\d+):(?P<seconds>\d+) minutes until you can get more leaves \| ",
+    ),
+    ("total", r"You've got (?P<total>-?\d+) leaves"),
+];
+
 lazy_static! {
-    // https://regex101.com/r/ZQ0SZH/1
     #[derive(Debug)]
-    pub static ref CLAIM_GOOD: Regex = Regex::new(r#"\x{1F343} @(?P<username>\w+) > .* \((?P<amount>[+-]\d+)\) \| You've got (?P<total>-?\d+) leaves now! \| Get more leaves in 1 hour\.\.\."#).unwrap();
+    pub static ref CLAIM_GOOD: Regex = diagnostics::build(CLAIM_GOOD_COMPONENTS);
 
-    // https://regex101.com/r/wuuDX2/1
     #[derive(Debug)]
-    pub static ref CLAIM_BAD: Regex = Regex::new(r#"\x{1F343} @(?P<username>\w+) > FeelsBadMan You need to wait (?P<minutes>\d+):(?P<seconds>\d+) minutes until you can get more leaves \| You've got (?P<total>-?\d+) leaves"#).unwrap();
+    pub static ref CLAIM_BAD: Regex = diagnostics::build(CLAIM_BAD_COMPONENTS);
 
     // https://regex101.com/r/0Fo0Io/1
     #[derive(Debug)]
     pub static ref GENERIC_ANSWER: Regex = Regex::new(r#"\x{1F343} @(?P<username>\w+) > .*"#).unwrap();
 }
 
+/// Reports which component of the claim patterns failed to match `message`.
+pub fn diagnose(message: &str) -> Option<Diagnosis> {
+    diagnostics::closest(vec![
+        diagnostics::first_failing(CLAIM_GOOD_NAME, CLAIM_GOOD_COMPONENTS, message),
+        diagnostics::first_failing(CLAIM_BAD_NAME, CLAIM_BAD_COMPONENTS, message),
+    ])
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -102,4 +134,22 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn diagnose_mutated_claims() {
+        let tests = [
+            ("@chronophylos > Four Leaf Clover 🍀 (+24) | You've got 34 leaves now! | Get more leaves in 1 hour...", "prefix"),
+            ("🍃 chronophylos > Four Leaf Clover 🍀 (+24) | You've got 34 leaves now! | Get more leaves in 1 hour...", "username"),
+            ("🍃 @chronophylos > Four Leaf Clover 🍀 [+24] | You've got 34 leaves now! | Get more leaves in 1 hour...", "amount"),
+            ("🍃 @chronophylos > Four Leaf Clover 🍀 (+24) | You have 34 leaves now! | Get more leaves in 1 hour...", "total"),
+            ("🍃 @chronophylos > Four Leaf Clover 🍀 (+24) | You've got 34 leaves now! | Get more leaves in 2 hours...", "suffix"),
+            ("🍃 @chronophylos > FeelsBadMan You need to wait 45 minutes until you can get more leaves | You've got 34 leaves", "cooldown"),
+        ];
+
+        for (text, component) in tests.iter() {
+            let diagnosis = diagnose(text).expect("patterns should not match");
+
+            assert_eq!(diagnosis.component, *component, "{}", text);
+        }
+    }
 }
@@ -4,18 +4,108 @@
     clippy::missing_const_for_fn
 )]
 
+mod blackout;
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
 mod bot;
+mod channels;
+mod chat;
+mod chatters;
 mod config;
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+mod cooldown;
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+mod diagnostics;
+mod error;
+mod jitter;
+#[cfg(feature = "leavesbot")]
 mod leavesbot;
+mod normalize;
+#[cfg(feature = "okayegbot")]
 mod okayegbot;
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+mod presence;
+mod quiet;
+mod schedule;
+mod skip;
+mod status;
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+mod suffix;
+#[cfg(feature = "thepositivebot")]
 mod thepositivebot;
 mod timestamp;
 
+pub mod admin;
+pub mod anomaly;
+pub mod board;
+pub mod history;
+pub mod http;
+pub mod metrics;
+pub mod notify;
+pub mod once;
+pub mod refresh;
+pub mod runner;
 pub mod secrettoken;
+pub mod snapshot;
+pub mod state;
+pub mod util;
 
-pub use config::Config;
-pub use leavesbot::LeafBot;
-pub use okayegbot::EgBot;
+pub use blackout::{Blackout, Blackouts};
+pub use bot::{CommunicateOptions, DryRun, MatchKind, MessageLimit, RequestOutcome, TypingDelay};
+pub use channels::{ChannelFailover, Channels};
+pub use chat::{ChatClient, ChatTransport};
+pub use chatters::{ChattersApi, ChattersOptions};
+pub use config::{Account, BotId, BotSettings, Config, EnabledBot, Reload, CONFIG_TEMPLATE};
+pub use error::Error;
+pub use http::HttpOptions;
+pub use jitter::Jitter;
+#[cfg(feature = "leavesbot")]
+pub use leavesbot::{ClaimResponse as ClaimLeaves, LeafBot, LeafBotOptions};
+#[cfg(feature = "okayegbot")]
+pub use okayegbot::{ClaimEgs, EgBot, EgBotOptions, EgStatus};
+pub use quiet::QuietHours;
+pub use schedule::Schedule;
 pub use secrettoken::SecretToken;
-pub use thepositivebot::CookieBot;
+pub use skip::SkipProbability;
+pub use status::{Section, Status};
+pub use suffix::SuffixStrategy;
+#[cfg(feature = "thepositivebot")]
+pub use thepositivebot::{
+    BuyBoosterResponse, BuyCdrResponse, ClaimCookieResponse, CookieBot, CookieBotOptions,
+    CookieStatus, PrestigeRank, Rank,
+};
 pub use timestamp::Timestamp;
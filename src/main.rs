@@ -1,28 +1,73 @@
 #![forbid(unsafe_code)]
 
-use anyhow::{Context, Result};
-use clap::{App, Arg};
-use cookiebot::{Config, CookieBot, EgBot, LeafBot};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::Path,
+    pin::Pin,
+    process,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+#[cfg(any(
+    feature = "thepositivebot",
+    feature = "okayegbot",
+    feature = "leavesbot"
+))]
+use cookiebot::runner::supervise;
+use cookiebot::{
+    admin::Listener,
+    board, history,
+    notify::{self, ChatNotifier},
+    once::{CancellationToken, ClaimOutcome, OnceError},
+    refresh::TokenManager,
+    secrettoken::{self, SharedToken},
+    snapshot::Snapshot,
+    state,
+    util::TokenBucket,
+    BotId, BotSettings, ChattersApi, Config, DryRun, EnabledBot, HttpOptions, SecretToken, Status,
+    Timestamp, CONFIG_TEMPLATE,
+};
+#[cfg(feature = "thepositivebot")]
+use cookiebot::{CookieBot, CookieBotOptions};
+#[cfg(feature = "okayegbot")]
+use cookiebot::{EgBot, EgBotOptions};
+#[cfg(feature = "leavesbot")]
+use cookiebot::{LeafBot, LeafBotOptions};
 use git_version::git_version;
 use metrics_exporter_prometheus::PrometheusBuilder;
-use tokio::select;
+use tokio::{
+    select,
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
+};
 use tracing::{error, info, instrument, warn};
 use tracing_subscriber::EnvFilter;
 
-#[tokio::main]
-#[instrument]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .pretty()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+const DEFAULT_METRICS_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 9000));
 
-    info!("Starting with version: git: {}", git_version!());
+/// Time between two commands to the same target bot, so the accounts running
+/// it do not send them in the same second
+const COMMAND_SPACING: Duration = Duration::from_secs(2);
+
+/// Time `claim-once` may take for each of the cooldown check and the claim
+const CLAIM_ONCE_TIMEOUT: Duration = Duration::from_secs(60);
 
-    PrometheusBuilder::new()
-        .install()
-        .context("could not install Prometheus recorder")?;
+/// Exit status of `claim-once` when the target bot is on cooldown
+const EXIT_COOLDOWN: i32 = 2;
 
+#[tokio::main]
+#[instrument]
+async fn main() -> Result<()> {
     let matches = App::new("cookiebot")
         .arg(
             Arg::with_name("config")
@@ -37,51 +82,1118 @@ async fn main() -> Result<()> {
                 .long("accept-invalid-certs")
                 .help("(Dangerous) Accept invalid certificates"),
         )
+        .arg(
+            Arg::with_name("metrics-addr")
+                .long("metrics-addr")
+                .value_name("ADDR")
+                .help("Serve Prometheus metrics on ADDR [default: metrics_addr from the config or 0.0.0.0:9000]")
+                .takes_value(true)
+                .conflicts_with("no-metrics"),
+        )
+        .arg(
+            Arg::with_name("skip-token-validation")
+                .long("skip-token-validation")
+                .help("Do not check the token with Twitch on startup"),
+        )
+        .arg(
+            Arg::with_name("status-addr")
+                .long("status-addr")
+                .value_name("ADDR")
+                .help("Serve the state of the bots as JSON on ADDR/status [default: status_addr from the config]")
+                .takes_value(true)
+                .conflicts_with("no-status"),
+        )
+        .arg(
+            Arg::with_name("no-status")
+                .long("no-status")
+                .help("Do not serve the state of the bots"),
+        )
+        .arg(
+            Arg::with_name("health-addr")
+                .long("health-addr")
+                .value_name("ADDR")
+                .help("Serve liveness on ADDR/healthz and readiness on ADDR/readyz [default: health_addr from the config]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Write logs as pretty, compact or json lines")
+                .env("LOG_FORMAT")
+                .possible_values(&["pretty", "compact", "json"])
+                .default_value("pretty"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Log the commands the bots would send instead of sending them"),
+        )
+        .arg(
+            Arg::with_name("no-metrics")
+                .long("no-metrics")
+                .help("Do not serve Prometheus metrics"),
+        )
+        .subcommand(
+            SubCommand::with_name("validate-config")
+                .about("Check the config and show what would run, without connecting to chat")
+                .arg(
+                    Arg::with_name("online")
+                        .long("online")
+                        .help("Also check the tokens with Twitch"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("claim-once")
+                .about("Check the cooldown and claim once with one bot, then exit")
+                .after_help("Exits with 0 after a claim, 2 on cooldown and 1 on errors.")
+                .arg(
+                    Arg::with_name("bot")
+                        .long("bot")
+                        .value_name("BOT")
+                        .help("Config section of the bot, e.g. thepositivebot")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("account")
+                        .long("account")
+                        .value_name("USERNAME")
+                        .help("Claim with USERNAME instead of the first account running the bot")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the result as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Show the balances and cooldowns of the accounts, without a token")
+                .arg(
+                    Arg::with_name("username")
+                        .long("username")
+                        .value_name("USERNAME")
+                        .help("Show USERNAME instead of the configured accounts")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the statuses as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Print what the state file knows about the bots, without running them")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Print as status JSON or in the Prometheus text format")
+                        .possible_values(&["json", "prom"])
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::with_name("live")
+                        .long("live")
+                        .help("Also read the balances and cooldowns from the APIs"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Work with the claims recorded in the history")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Print every recorded claim, oldest first")
+                        .arg(
+                            Arg::with_name("csv")
+                                .long("csv")
+                                .help("Print the claims as CSV")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate-config")
+                .about("Write an example config with every field explained")
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write to FILE instead of stdout")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite FILE if it exists"),
+                ),
+        )
         .get_matches();
 
+    init_logging(
+        matches
+            .value_of("log-format")
+            .expect("user set or default log format"),
+    );
+    info!("Starting with version: git: {}", git_version!());
+
+    if let Some(matches) = matches.subcommand_matches("generate-config") {
+        return generate_config(matches.value_of("output"), matches.is_present("force"));
+    }
+
     let config_path = matches
         .value_of("config")
         .expect("user set or default config path");
-    let config = Config::from_path(config_path)?;
 
-    let accept_invalid_certs = matches.is_present("accept-invalid-certs");
+    if let Some(matches) = matches.subcommand_matches("status") {
+        let config = Config::from_path_without_token(config_path)?;
+        return status(&config, matches).await;
+    }
 
-    let cookiebot = CookieBot::new(
-        config.username.clone(),
-        config.token.clone(),
-        config.cookiebot_channel,
-        accept_invalid_certs,
-    );
+    if let Some(matches) = matches.subcommand_matches("snapshot") {
+        let config = Config::from_path_without_token(config_path)?;
+        return snapshot(&config, config_path, matches).await;
+    }
 
-    let egbot = EgBot::new(
-        config.username.clone(),
-        config.token.clone(),
-        config.egbot_channel,
-    );
+    if matches.subcommand_matches("history").is_some() {
+        let config = Config::from_path_without_token(config_path)?;
+        return export_history(&config);
+    }
+
+    let mut config = Config::from_path(config_path)?;
+
+    if let Some(matches) = matches.subcommand_matches("validate-config") {
+        return validate_config(&config, config_path, matches.is_present("online")).await;
+    }
+
+    // shared so a refreshed token reaches every bot
+    let token = SharedToken::new(config.token.clone());
+    let token_manager = match config.token_refresh.clone() {
+        Some(options) => {
+            let manager = TokenManager::new(token.clone(), options, config.http.clone())
+                .context("could not create token manager")?;
+            let lifetime = manager.refresh().await.context("could not refresh token")?;
+
+            Some((manager, lifetime))
+        }
+        None => None,
+    };
+
+    let dry_run = matches.is_present("dry-run") || config.dry_run.enabled;
+    if dry_run {
+        info!("Dry run, not sending any commands");
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("claim-once") {
+        return claim_once(
+            &config,
+            token,
+            sub_matches,
+            dry_run,
+            matches.is_present("accept-invalid-certs"),
+        )
+        .await;
+    }
+
+    let validate = !matches.is_present("skip-token-validation");
+    if !validate {
+        info!("Not validating tokens");
+    }
+    if matches.is_present("no-metrics") {
+        info!("Not serving metrics");
+    } else {
+        let metrics_addr = match matches.value_of("metrics-addr") {
+            Some(addr) => addr
+                .parse()
+                .with_context(|| format!("invalid metrics address: {}", addr))?,
+            None => config.metrics_addr.unwrap_or(DEFAULT_METRICS_ADDR),
+        };
+
+        info!("Serving metrics on {}", metrics_addr);
+        PrometheusBuilder::new()
+            .listen_address(metrics_addr)
+            .install()
+            .context("could not install Prometheus recorder")?;
+        cookiebot::metrics::set_legacy_names(config.legacy_metrics);
+        cookiebot::metrics::init();
+        tokio::spawn(cookiebot::metrics::update_ages_forever());
+    }
+
+    let status_addr = match matches.value_of("status-addr") {
+        Some(addr) => Some(
+            addr.parse()
+                .with_context(|| format!("invalid status address: {}", addr))?,
+        ),
+        None => config.status_addr,
+    };
+    match status_addr {
+        Some(addr) if !matches.is_present("no-status") => {
+            info!("Serving status on {}", addr);
+            let server = board::serve(addr).context("could not serve status")?;
+            tokio::spawn(async move {
+                if let Err(err) = server.await {
+                    error!("Stopped serving status: {}", err);
+                }
+            });
+        }
+        _ => info!("Not serving status"),
+    }
+
+    let health_addr = match matches.value_of("health-addr") {
+        Some(addr) => Some(
+            addr.parse()
+                .with_context(|| format!("invalid health address: {}", addr))?,
+        ),
+        None => config.health_addr,
+    };
+    if let Some(addr) = health_addr {
+        info!("Serving health on {}", addr);
+        let server = board::serve_health(addr).context("could not serve health")?;
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                error!("Stopped serving health: {}", err);
+            }
+        });
+    }
+
+    if let Some(notifications) = &config.notifications {
+        notify::start(notifications.clone(), config.http.clone())
+            .context("could not start notifications")?;
+    }
+
+    // a dry run would save cooldowns and claims it never made
+    if !dry_run {
+        state::load(
+            config
+                .state_file
+                .clone()
+                .unwrap_or_else(|| state::default_path(Path::new(config_path))),
+        )
+        .context("could not keep state")?;
+        if let Some(path) = &config.history {
+            history::open(path).context("could not open history")?;
+        }
+    }
+
+    // cancelled when a bot fails in a way that affects every bot
+    let cancel = CancellationToken::new();
+    let (done, mut finished) = mpsc::unbounded_channel();
+    let mut bots = Bots {
+        // shared so bots in the same channel share chatters lookups
+        chatters: ChattersApi::new(config.chatters.clone()),
+        token,
+        #[cfg(feature = "thepositivebot")]
+        accept_invalid_certs: matches.is_present("accept-invalid-certs"),
+        validate,
+        dry_run,
+        validated: HashSet::new(),
+        pacing: HashMap::new(),
+        message_limiters: HashMap::new(),
+        cancel: cancel.clone(),
+        done,
+        running: HashMap::new(),
+        next_run: 0,
+    };
+
+    // started before the bots, so their first failures are not missed
+    if let Some(user) = &config.notify_user {
+        let message_limiter = bots
+            .message_limiters
+            .entry(config.username.to_lowercase())
+            .or_insert_with(|| Arc::new(config.message_limit.limiter()))
+            .clone();
+        ChatNotifier::new(
+            user,
+            config.notify_via.clone(),
+            &config.username,
+            bots.token.clone(),
+            dry_run,
+        )
+        .with_chat_transport(config.chat_transport)
+        .with_message_limiter(message_limiter)
+        .start();
+    }
+
+    for bot in config.enabled_bots() {
+        bots.start(&bot, &config).await?;
+    }
+    board::loaded();
+
+    if !config.admins.is_empty() {
+        let mut channels: Vec<_> = config
+            .enabled_bots()
+            .iter()
+            .flat_map(|bot| bot.channels.iter().map(str::to_string))
+            .collect();
+        channels.sort();
+        channels.dedup();
+
+        let listener = Listener::new(
+            &config.username,
+            bots.token.clone(),
+            channels,
+            &config.admins,
+            dry_run,
+        )
+        .with_chat_transport(config.chat_transport);
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            if let Err(err) = listener.run(&cancel).await {
+                error!("Stopped listening for commands of the admins: {}", err);
+            }
+        });
+    }
+
+    let token_manager = token_manager.map(|(manager, lifetime)| {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            let result = manager.run(lifetime, &cancel).await;
+            if result.is_err() {
+                // the bots cannot log in again without a token
+                cancel.cancel();
+            }
+
+            result
+        })
+    });
+
+    let mut hangups = Hangups::new()?;
+    let mut fatal = None;
+    loop {
+        // nothing else would end the loop
+        if bots.running.is_empty() && !cancel.is_cancelled() {
+            warn!("no bot is running, stopping");
+            cancel.cancel();
+        }
+
+        select! {
+            Some((id, run, result)) = finished.recv() => {
+                bots.finished(&id, run);
+                match result {
+                    Err(err) if err.is_fatal() => {
+                        fatal.get_or_insert(err);
+                        cancel.cancel();
+                    }
+                    // the other bots keep running
+                    Err(err) => error!("{} stopped: {}", id, err),
+                    Ok(()) => {}
+                }
+            }
+            _ = hangups.recv() => {
+                config = reload_config(config, config_path, &mut bots).await;
+            }
+            _ = cancel.cancelled(), if bots.running.is_empty() => break,
+        }
+    }
+
+    // the bots saved their last claims, which might not be written yet
+    tokio::task::spawn_blocking(state::flush)
+        .await
+        .context("writing the state panicked")?;
+
+    if let Some(task) = token_manager {
+        if let Err(err) = task.await.context("token manager panicked")? {
+            error!("Twitch rejected the refresh token. Get a new one and restart");
+            return Err(err.into());
+        }
+    }
+
+    match fatal {
+        Some(err) => {
+            error!(
+                "Twitch rejected the login. Refresh the token in {} and restart",
+                config_path
+            );
+            Err(err.into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Installs the subscriber writing logs in `format`, filtered by `RUST_LOG`.
+fn init_logging(format: &str) {
+    // stdout is kept for the output of the subcommands, like a config
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr);
+
+    match format {
+        // the fields of the spans, e.g. the bot and channel, become keys
+        "json" => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+        "compact" => builder.compact().init(),
+        _ => builder.pretty().init(),
+    }
+}
+
+type BotRun = Pin<Box<dyn Future<Output = Result<(), cookiebot::Error>> + Send>>;
+
+/// Bot task that reports how it finished
+struct RunningBot {
+    section: &'static str,
+
+    /// Tells the finish of this task apart from earlier runs of the bot
+    run: u64,
+    cancel: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+/// Starts and stops the bots of the running config
+struct Bots {
+    token: SharedToken,
+    chatters: ChattersApi,
+    #[cfg(feature = "thepositivebot")]
+    accept_invalid_certs: bool,
+    validate: bool,
+
+    /// Whether the bots log their commands instead of sending them
+    dry_run: bool,
+
+    /// Accounts whose token was already checked
+    validated: HashSet<String>,
 
-    let leafbot = LeafBot::new(config.username, config.token, config.leavesbot.channel);
+    /// Limiters shared by the accounts running the same bot
+    pacing: HashMap<&'static str, Arc<TokenBucket>>,
 
-    select! {
-        result = cookiebot.run(), if !config.cookiebot_disabled => {
-            if let Err(err) = result {
-                error!("Error running CookieBot: {}", err);
+    /// Limiters shared by the bots running with the same account
+    message_limiters: HashMap<String, Arc<TokenBucket>>,
+
+    /// Cancelled to stop every bot
+    cancel: CancellationToken,
+    done: UnboundedSender<(BotId, u64, Result<(), cookiebot::Error>)>,
+    running: HashMap<BotId, RunningBot>,
+    next_run: u64,
+}
+
+impl Bots {
+    /// Starts `bot` with the shared options of `config`.
+    async fn start(&mut self, bot: &EnabledBot<'_>, config: &Config) -> Result<()> {
+        let id = bot.id();
+        let cancel = self.cancel.child_token();
+        let run = self.next_run;
+        self.next_run += 1;
+
+        let (username, token) = self.account(bot, config.http.clone()).await?;
+        let pacing = self
+            .pacing
+            .entry(bot.section)
+            .or_insert_with(|| Arc::new(TokenBucket::new(1, COMMAND_SPACING)))
+            .clone();
+        let message_limiter = self
+            .message_limiters
+            .entry(username.to_lowercase())
+            .or_insert_with(|| Arc::new(config.message_limit.limiter()))
+            .clone();
+        let dry_run = DryRun {
+            enabled: self.dry_run,
+            ..config.dry_run.clone()
+        };
+        #[cfg(not(feature = "thepositivebot"))]
+        let accept_invalid_certs = false;
+        #[cfg(feature = "thepositivebot")]
+        let accept_invalid_certs = self.accept_invalid_certs;
+
+        let future = AnyBot::new(
+            bot,
+            config,
+            (username, token),
+            self.chatters.clone(),
+            accept_invalid_certs,
+            (pacing, message_limiter),
+            dry_run,
+        )?
+        .supervise(bot.section, id.account.clone(), cancel.clone());
+
+        let done = self.done.clone();
+        let task = {
+            let id = id.clone();
+            tokio::spawn(async move {
+                // the receiver lives as long as main
+                let _ = done.send((id, run, future.await));
+            })
+        };
+        board::started(bot.section, &id.account);
+        self.running.insert(
+            id,
+            RunningBot {
+                section: bot.section,
+                run,
+                cancel,
+                task,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Stops the bot `id` and waits for it to finish.
+    async fn stop(&mut self, id: &BotId) {
+        if let Some(bot) = self.running.remove(id) {
+            board::stopped(bot.section, &id.account);
+            bot.cancel.cancel();
+            if let Err(err) = bot.task.await {
+                error!("{} panicked: {}", id, err);
             }
-            warn!("CookieBot finished running");
         }
-        result = egbot.run(), if !config.egbot_disabled => {
-            if let Err(err) = result {
-                error!("Error running EgBot: {}", err);
+    }
+
+    /// Forgets the bot `id` if `run` is its current run.
+    fn finished(&mut self, id: &BotId, run: u64) {
+        if self.running.get(id).is_some_and(|bot| bot.run == run) {
+            if let Some(bot) = self.running.remove(id) {
+                board::stopped(bot.section, &id.account);
             }
-            warn!("EgBot finished running");
         }
-        result = leafbot.run(), if !config.leavesbot.disabled => {
-            if let Err(err) = result {
-                error!("Error running LeafBot: {}", err);
+    }
+
+    /// Returns the account `bot` claims with, checking its token once.
+    async fn account(
+        &mut self,
+        bot: &EnabledBot<'_>,
+        http: HttpOptions,
+    ) -> Result<(String, SharedToken)> {
+        // the top level token is shared so a refreshed token reaches every bot
+        let token = if bot.shared_token {
+            self.token.clone()
+        } else {
+            SharedToken::new(bot.token.clone())
+        };
+        if self.validate && self.validated.insert(bot.username.to_lowercase()) {
+            validate_token(&token.get(), bot.username, http).await?;
+        }
+
+        Ok((bot.username.to_string(), token))
+    }
+}
+
+/// Bot of any kind, built from its config section
+// one per bot and account, so the size of the variants does not matter
+#[allow(clippy::large_enum_variant)]
+enum AnyBot {
+    #[cfg(feature = "thepositivebot")]
+    Cookie(CookieBot),
+    #[cfg(feature = "okayegbot")]
+    Eg(EgBot),
+    #[cfg(feature = "leavesbot")]
+    Leaf(LeafBot),
+}
+
+impl AnyBot {
+    /// Builds `bot` with the shared options of `config`, claiming as
+    /// `account`.
+    #[cfg_attr(not(feature = "thepositivebot"), allow(unused_variables))]
+    #[cfg_attr(
+        not(any(
+            feature = "thepositivebot",
+            feature = "okayegbot",
+            feature = "leavesbot"
+        )),
+        allow(unreachable_code)
+    )]
+    fn new(
+        bot: &EnabledBot<'_>,
+        config: &Config,
+        (username, token): (String, SharedToken),
+        chatters: ChattersApi,
+        accept_invalid_certs: bool,
+        (pacing, message_limiter): (Arc<TokenBucket>, Arc<TokenBucket>),
+        dry_run: DryRun,
+    ) -> Result<Self> {
+        let blackouts = config.blackouts_for(bot.channels);
+        let jitter = config.claim_jitter_for(bot);
+
+        Ok(match bot.settings {
+            #[cfg(feature = "thepositivebot")]
+            BotSettings::ThePositiveBot(section) => Self::Cookie(
+                CookieBot::with_options(
+                    username,
+                    token,
+                    CookieBotOptions {
+                        config: section.clone(),
+                        blackouts,
+                        http: config.http.clone(),
+                        chatters,
+                        accept_invalid_certs,
+                    },
+                )
+                .context("could not create CookieBot")?
+                .with_pacing(pacing)
+                .with_message_limiter(message_limiter)
+                .with_chat_transport(config.chat_transport)
+                .with_dry_run(dry_run)
+                .with_jitter(jitter)
+                .with_quiet_hours(config.quiet_hours),
+            ),
+            #[cfg(feature = "okayegbot")]
+            BotSettings::OkayegBot(section) => Self::Eg(
+                EgBot::with_options(
+                    username,
+                    token,
+                    EgBotOptions {
+                        config: section.clone(),
+                        blackouts,
+                        http: config.http.clone(),
+                        chatters,
+                    },
+                )
+                .context("could not create EgBot")?
+                .with_pacing(pacing)
+                .with_message_limiter(message_limiter)
+                .with_chat_transport(config.chat_transport)
+                .with_dry_run(dry_run)
+                .with_jitter(jitter)
+                .with_quiet_hours(config.quiet_hours),
+            ),
+            #[cfg(feature = "leavesbot")]
+            BotSettings::LeavesBot(section) => Self::Leaf(
+                LeafBot::with_options(
+                    username,
+                    token,
+                    LeafBotOptions {
+                        config: section.clone(),
+                        blackouts,
+                        http: config.http.clone(),
+                        chatters,
+                    },
+                )
+                .context("could not create LeafBot")?
+                .with_pacing(pacing)
+                .with_message_limiter(message_limiter)
+                .with_chat_transport(config.chat_transport)
+                .with_dry_run(dry_run)
+                .with_jitter(jitter)
+                .with_quiet_hours(config.quiet_hours),
+            ),
+            #[cfg(not(any(
+                feature = "thepositivebot",
+                feature = "okayegbot",
+                feature = "leavesbot"
+            )))]
+            BotSettings::Unused(never, _) => match never {},
+        })
+    }
+
+    /// Runs the bot until `cancel` is cancelled, restarting it after
+    /// failures.
+    #[cfg_attr(
+        not(any(
+            feature = "thepositivebot",
+            feature = "okayegbot",
+            feature = "leavesbot"
+        )),
+        allow(unused_variables)
+    )]
+    fn supervise(
+        self,
+        section: &'static str,
+        account: String,
+        cancel: CancellationToken,
+    ) -> BotRun {
+        match self {
+            #[cfg(feature = "thepositivebot")]
+            Self::Cookie(bot) => Box::pin(async move {
+                supervise("CookieBot", &account, &cancel, || {
+                    reported(section, &account, bot.run())
+                })
+                .await
+            }),
+            #[cfg(feature = "okayegbot")]
+            Self::Eg(bot) => Box::pin(async move {
+                supervise("EgBot", &account, &cancel, || {
+                    reported(section, &account, bot.run())
+                })
+                .await
+            }),
+            #[cfg(feature = "leavesbot")]
+            Self::Leaf(bot) => Box::pin(async move {
+                supervise("LeafBot", &account, &cancel, || {
+                    reported(section, &account, bot.run())
+                })
+                .await
+            }),
+        }
+    }
+
+    /// Checks the cooldown where the target bot has an API for it and claims
+    /// once if it is over.
+    #[cfg_attr(
+        not(any(
+            feature = "thepositivebot",
+            feature = "okayegbot",
+            feature = "leavesbot"
+        )),
+        allow(unused_variables)
+    )]
+    async fn claim_once(&self, cancel: &CancellationToken) -> Result<ClaimOutcome> {
+        let timeout = Some(CLAIM_ONCE_TIMEOUT);
+
+        match *self {
+            #[cfg(feature = "thepositivebot")]
+            Self::Cookie(ref bot) => {
+                if let Some(remaining) = bot
+                    .check_cooldown(timeout, cancel)
+                    .await
+                    .map_err(once_error)?
+                {
+                    return Ok(ClaimOutcome::cooldown(remaining));
+                }
+
+                Ok(bot
+                    .claim_once(timeout, cancel)
+                    .await
+                    .map_err(once_error)?
+                    .into())
             }
-            warn!("LeafBot finished running");
+            #[cfg(feature = "okayegbot")]
+            Self::Eg(ref bot) => {
+                if let Some(remaining) = bot
+                    .check_cooldown(timeout, cancel)
+                    .await
+                    .map_err(once_error)?
+                {
+                    return Ok(ClaimOutcome::cooldown(remaining));
+                }
+
+                Ok(bot
+                    .claim_once(timeout, cancel)
+                    .await
+                    .map_err(once_error)?
+                    .into())
+            }
+            #[cfg(feature = "leavesbot")]
+            Self::Leaf(ref bot) => Ok(bot
+                .claim_once(timeout, cancel)
+                .await
+                .map_err(once_error)?
+                .into()),
+        }
+    }
+}
+
+/// Publishes the error `run` of the bot of `section` fails with on the
+/// status endpoint, keeping the bot unready until it claims again.
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+async fn reported<F, E>(section: &'static str, account: &str, run: F) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    let result = run.await;
+    if let Err(err) = &result {
+        board::failed(section, account, err);
+        board::unhealthy(section, account, err);
+    }
+
+    result
+}
+
+/// Keeps the whole chain of a failed single shot operation.
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+fn once_error<E>(err: OnceError<E>) -> anyhow::Error
+where
+    E: Display + Into<anyhow::Error>,
+{
+    match err {
+        OnceError::Failed(err) => err.into(),
+        err => anyhow!("{}", err),
+    }
+}
+
+/// Resolves on every SIGHUP, never where there is none
+struct Hangups {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl Hangups {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .context("could not listen for SIGHUP")?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Reads the config at `path` again and applies what changed to `bots`.
+///
+/// Returns the config that is running afterwards, which is `config` if the
+/// new one is invalid.
+async fn reload_config(config: Config, path: &str, bots: &mut Bots) -> Config {
+    info!("Reloading {}", path);
+    let new = match Config::from_path(path) {
+        Ok(new) => new,
+        Err(err) => {
+            error!("Keeping the running config: {:#}", err);
+            return config;
+        }
+    };
+
+    let (config, reload) = config.reload(new);
+    for field in &reload.kept {
+        warn!("Changed {} only takes effect after a restart", field);
+    }
+
+    for id in reload.stop.iter().chain(&reload.restart) {
+        info!("Stopping {}", id);
+        bots.stop(id).await;
+    }
+
+    let enabled = config.enabled_bots();
+    for id in reload.restart.iter().chain(&reload.start) {
+        info!("Starting {}", id);
+        let bot = enabled
+            .iter()
+            .find(|bot| bot.id() == *id)
+            .expect("started bots should be enabled");
+        if let Err(err) = bots.start(bot, &config).await {
+            error!("Could not start {}: {:#}", id, err);
+        }
+    }
+
+    config
+}
+
+/// Prints every claim in the history of `config` as CSV.
+fn export_history(config: &Config) -> Result<()> {
+    let path = config
+        .history
+        .as_ref()
+        .context("no history is configured")?;
+
+    history::export_csv(path, std::io::stdout().lock()).context("could not export history")
+}
+
+/// Prints the balances and cooldowns of the accounts in `config` or the one
+/// named by `matches`.
+async fn status(config: &Config, matches: &ArgMatches<'_>) -> Result<()> {
+    let usernames = match matches.value_of("username") {
+        Some(username) => vec![username],
+        None => usernames(config),
+    };
+    let statuses = fetch_statuses(config, usernames).await?;
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string(&statuses)?);
+    } else {
+        for status in &statuses {
+            println!("{}", status);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the configured accounts, each once.
+fn usernames(config: &Config) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    std::iter::once(config.username.as_str())
+        .chain(
+            config
+                .accounts
+                .iter()
+                .map(|account| account.username.as_str()),
+        )
+        .filter(|username| seen.insert(username.to_lowercase()))
+        .collect()
+}
+
+/// Reads the balances and cooldowns of `usernames`.
+async fn fetch_statuses(config: &Config, usernames: Vec<&str>) -> Result<Vec<Status>> {
+    let mut statuses = Vec::new();
+    for username in usernames {
+        statuses.push(Status::fetch(config, &username.to_lowercase()).await?);
+    }
+
+    Ok(statuses)
+}
+
+/// Prints what the state file of `config` knows about the bots in the
+/// format named by `matches`.
+async fn snapshot(config: &Config, config_path: &str, matches: &ArgMatches<'_>) -> Result<()> {
+    let path = config
+        .state_file
+        .clone()
+        .unwrap_or_else(|| state::default_path(Path::new(config_path)));
+    let enabled: Vec<_> = config
+        .enabled_bots()
+        .iter()
+        .map(|bot| (bot.section, bot.username.to_string()))
+        .collect();
+
+    let mut snapshot = Snapshot::new(Utc::now(), state::read_all(&path), &enabled);
+    if matches.is_present("live") {
+        snapshot = snapshot.with_live(fetch_statuses(config, usernames(config)).await?);
+    }
+
+    match matches.value_of("format") {
+        Some("prom") => print!("{}", snapshot.to_prometheus()),
+        _ => println!("{}", serde_json::to_string(&snapshot)?),
+    }
+
+    Ok(())
+}
+
+/// Claims once with the bot named by `matches` and prints the outcome.
+///
+/// Exits with [`EXIT_COOLDOWN`] if the target bot is on cooldown.
+async fn claim_once(
+    config: &Config,
+    token: SharedToken,
+    matches: &ArgMatches<'_>,
+    dry_run: bool,
+    accept_invalid_certs: bool,
+) -> Result<()> {
+    let section = matches.value_of("bot").expect("bot is required");
+    let account = matches.value_of("account");
+
+    let bots = config.enabled_bots();
+    let bot = bots
+        .iter()
+        .find(|bot| {
+            bot.section == section
+                && account.is_none_or(|account| bot.username.eq_ignore_ascii_case(account))
+        })
+        .with_context(|| match account {
+            Some(account) => format!("{} is not enabled for {}", section, account),
+            None => format!("{} is not enabled", section),
+        })?;
+
+    // the top level token was refreshed already
+    let token = if bot.shared_token {
+        token
+    } else {
+        SharedToken::new(bot.token.clone())
+    };
+    let outcome = AnyBot::new(
+        bot,
+        config,
+        (bot.username.to_string(), token),
+        ChattersApi::new(config.chatters.clone()),
+        accept_invalid_certs,
+        (
+            Arc::new(TokenBucket::new(1, COMMAND_SPACING)),
+            Arc::new(config.message_limit.limiter()),
+        ),
+        DryRun {
+            enabled: dry_run,
+            ..config.dry_run.clone()
+        },
+    )?
+    .claim_once(&CancellationToken::new())
+    .await?;
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string(&outcome)?);
+    } else {
+        println!("{}", outcome);
+    }
+
+    if !outcome.is_claimed() {
+        process::exit(EXIT_COOLDOWN);
+    }
+
+    Ok(())
+}
+
+/// Checks with Twitch that `token` belongs to `username` and logs when it
+/// expires.
+async fn validate_token(token: &SecretToken, username: &str, http: HttpOptions) -> Result<()> {
+    let info = secrettoken::validate(token, username, http)
+        .await
+        .with_context(|| format!("could not validate token of {}", username))?;
+
+    let expires = match info.expires_in {
+        0 => "never".to_string(),
+        secs => format!("in {}", Duration::from_secs(secs).as_readable()),
+    };
+    info!(
+        "Token for {} expires {}, scopes: {}",
+        info.login,
+        expires,
+        info.scopes.join(", ")
+    );
+
+    Ok(())
+}
+
+/// Writes the example config to `output` or stdout.
+fn generate_config(output: Option<&str>, force: bool) -> Result<()> {
+    let path = match output {
+        Some(path) => path,
+        None => {
+            print!("{}", CONFIG_TEMPLATE);
+            return Ok(());
         }
-        else => {
-            warn!("no bot is configured to run")
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(path)
+        .with_context(|| match force {
+            false if Path::new(path).exists() => {
+                format!("{} already exists, use --force to overwrite it", path)
+            }
+            _ => format!("Could not create {}", path),
+        })?;
+    file.write_all(CONFIG_TEMPLATE.as_bytes())
+        .with_context(|| format!("Could not write {}", path))?;
+
+    eprintln!("Wrote example config to {}", path);
+    Ok(())
+}
+
+/// Prints what would run with `config`, failing if nothing would.
+async fn validate_config(config: &Config, path: &str, online: bool) -> Result<()> {
+    let bots = config.enabled_bots();
+    if bots.is_empty() {
+        bail!("{} is valid but enables no bot", path);
+    }
+
+    println!("{} is valid, it would run:", path);
+    for bot in &bots {
+        println!("  {} in {} as {}", bot.name, bot.channels, bot.username);
+    }
+
+    if online {
+        let mut validated = HashSet::new();
+        for bot in &bots {
+            if validated.insert(bot.username.to_lowercase()) {
+                validate_token(bot.token, bot.username, config.http.clone()).await?;
+                println!("Token of {} is valid", bot.username);
+            }
         }
     }
 
@@ -0,0 +1,500 @@
+//! Descriptions of the metrics of every bot and the claim metrics they share
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use git_version::git_version;
+use lazy_static::lazy_static;
+use metrics::{
+    counter, gauge, histogram, register_counter, register_gauge, register_histogram, Label, Unit,
+};
+use tokio::time::{interval, Instant};
+
+#[cfg(feature = "leavesbot")]
+use crate::leavesbot;
+#[cfg(feature = "okayegbot")]
+use crate::okayegbot;
+#[cfg(feature = "thepositivebot")]
+use crate::thepositivebot;
+use crate::{bot, channels, cooldown, runner};
+
+static METRIC_TOTAL: &str = "cookiebot.total";
+static METRIC_CLAIMS_ATTEMPTED: &str = "cookiebot.claims.attempted";
+static METRIC_CLAIMS_SKIPPED: &str = "cookiebot.claims.skipped";
+static METRIC_CLAIMS_SUCCEEDED: &str = "cookiebot.claims.succeeded";
+static METRIC_CLAIMS_FAILED: &str = "cookiebot.claims.failed";
+static METRIC_CLAIMS_UNPARSED: &str = "cookiebot.claims.parse_failures";
+static METRIC_CLAIMED_AMOUNT: &str = "cookiebot.claimed";
+static METRIC_BUILD_INFO: &str = "cookiebot.build_info";
+static METRIC_UPTIME: &str = "cookiebot.uptime";
+static METRIC_SINCE_LAST_CLAIM: &str = "cookiebot.since_last_claim";
+
+/// How often the uptime and time since the last claim are updated
+const AGE_INTERVAL: Duration = Duration::from_secs(15);
+
+lazy_static! {
+    /// When the bot of each account started running and last claimed, by bot
+    /// label and account
+    static ref AGES: Mutex<HashMap<(&'static str, String), Ages>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Ages {
+    started: Option<Instant>,
+    last_claim: Option<Instant>,
+}
+
+/// Whether the claim metrics are also emitted under the names of each bot
+/// they had before they were labeled by bot
+static LEGACY_NAMES: AtomicBool = AtomicBool::new(true);
+
+/// Emits the claim metrics under their old names too if `enabled`.
+///
+/// Call this before [`init`], which registers the old names.
+pub fn set_legacy_names(enabled: bool) {
+    LEGACY_NAMES.store(enabled, Ordering::Relaxed);
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn legacy_names() -> bool {
+    LEGACY_NAMES.load(Ordering::Relaxed)
+}
+
+/// Registers every metric with the installed recorder.
+///
+/// Call this once after installing the recorder. The bots only update
+/// metrics, so they can be built and run without one.
+pub fn init() {
+    register_gauge!(
+        METRIC_BUILD_INFO,
+        Unit::Count,
+        "always 1, labeled with the version and git revision"
+    );
+    gauge!(
+        METRIC_BUILD_INFO,
+        1.,
+        "version" => env!("CARGO_PKG_VERSION"),
+        "git" => git_version!()
+    );
+    register_gauge!(
+        METRIC_UPTIME,
+        Unit::Seconds,
+        "time since the bot started running, 0 while it is not"
+    );
+    register_gauge!(
+        METRIC_SINCE_LAST_CLAIM,
+        Unit::Seconds,
+        "time since the last successful claim of the bot"
+    );
+    register_gauge!(
+        METRIC_TOTAL,
+        Unit::Count,
+        "total the target bot reported on the last claim"
+    );
+    register_counter!(
+        METRIC_CLAIMS_ATTEMPTED,
+        Unit::Count,
+        "number of claims sent to chat"
+    );
+    register_counter!(
+        METRIC_CLAIMS_SKIPPED,
+        Unit::Count,
+        "number of claims skipped on purpose"
+    );
+    register_counter!(
+        METRIC_CLAIMS_SUCCEEDED,
+        Unit::Count,
+        "number of successful claims"
+    );
+    register_counter!(
+        METRIC_CLAIMS_FAILED,
+        Unit::Count,
+        "number of claims that hit the cooldown"
+    );
+    register_counter!(
+        METRIC_CLAIMS_UNPARSED,
+        Unit::Count,
+        "number of claim answers that could not be parsed"
+    );
+    register_histogram!(
+        METRIC_CLAIMED_AMOUNT,
+        Unit::Count,
+        "amount gained or lost per claim"
+    );
+
+    bot::register_metrics();
+    channels::register_metrics();
+    cooldown::register_metrics();
+    runner::register_metrics();
+    #[cfg(feature = "thepositivebot")]
+    thepositivebot::register_metrics(legacy_names());
+    #[cfg(feature = "okayegbot")]
+    okayegbot::register_metrics(legacy_names());
+    #[cfg(feature = "leavesbot")]
+    leavesbot::register_metrics(legacy_names());
+}
+
+/// Updates the uptime and the time since the last claim of every bot.
+fn update_ages() {
+    let now = Instant::now();
+    for ((bot, account), ages) in AGES.lock().expect("ages should not be poisoned").iter() {
+        if let Some(started) = ages.started {
+            gauge!(
+                METRIC_UPTIME,
+                (now - started).as_secs_f64(),
+                "bot" => *bot,
+                "account" => account.clone()
+            );
+        }
+        if let Some(last_claim) = ages.last_claim {
+            gauge!(
+                METRIC_SINCE_LAST_CLAIM,
+                (now - last_claim).as_secs_f64(),
+                "bot" => *bot,
+                "account" => account.clone()
+            );
+        }
+    }
+}
+
+/// Keeps the uptime and the time since the last claim of every bot current.
+///
+/// Spawn this once after [`init`], it never returns.
+pub async fn update_ages_forever() {
+    let mut ticks = interval(AGE_INTERVAL);
+    loop {
+        ticks.tick().await;
+        update_ages();
+    }
+}
+
+/// Names a bot emitted its claim metrics under before they were labeled,
+/// still emitted without labels
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug)]
+pub(crate) struct LegacyNames {
+    pub total: &'static str,
+    pub claims_attempted: &'static str,
+    pub claims_skipped: &'static str,
+    pub claims_succeeded: &'static str,
+    pub claims_failed: &'static str,
+    pub claims_unparsed: &'static str,
+    pub claimed_amount: &'static str,
+}
+
+/// Claim metrics of the bot of one account, labeled by bot, channel and
+/// account
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug, Clone)]
+pub(crate) struct ClaimMetrics {
+    bot: &'static str,
+    account: String,
+    legacy: &'static LegacyNames,
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+impl ClaimMetrics {
+    pub fn new(bot: &'static str, account: &str, legacy: &'static LegacyNames) -> Self {
+        Self {
+            bot,
+            account: account.to_lowercase(),
+            legacy,
+        }
+    }
+
+    /// Returns the labels of the metrics of the bot claiming in `channel`.
+    pub fn labels(&self, channel: &str) -> Vec<Label> {
+        vec![
+            Label::new("bot", self.bot),
+            Label::new("channel", channel.to_string()),
+            Label::new("account", self.account.clone()),
+        ]
+    }
+
+    /// Counts the uptime of the bot until the returned guard is dropped.
+    pub fn running(&self) -> Running {
+        let key = (self.bot, self.account.clone());
+        AGES.lock()
+            .expect("ages should not be poisoned")
+            .entry(key.clone())
+            .or_default()
+            .started = Some(Instant::now());
+
+        Running { key }
+    }
+
+    fn increment(&self, name: &'static str, legacy: &'static str, channel: &str) {
+        counter!(name, 1, self.labels(channel));
+        if legacy_names() {
+            counter!(legacy, 1);
+        }
+    }
+
+    pub fn total(&self, channel: &str, total: f64) {
+        gauge!(METRIC_TOTAL, total, self.labels(channel));
+        if legacy_names() {
+            gauge!(self.legacy.total, total);
+        }
+    }
+
+    pub fn attempted(&self, channel: &str) {
+        self.increment(
+            METRIC_CLAIMS_ATTEMPTED,
+            self.legacy.claims_attempted,
+            channel,
+        );
+    }
+
+    pub fn skipped(&self, channel: &str) {
+        self.increment(METRIC_CLAIMS_SKIPPED, self.legacy.claims_skipped, channel);
+    }
+
+    pub fn succeeded(&self, channel: &str, amount: f64) {
+        AGES.lock()
+            .expect("ages should not be poisoned")
+            .entry((self.bot, self.account.clone()))
+            .or_default()
+            .last_claim = Some(Instant::now());
+        self.increment(
+            METRIC_CLAIMS_SUCCEEDED,
+            self.legacy.claims_succeeded,
+            channel,
+        );
+        histogram!(METRIC_CLAIMED_AMOUNT, amount, self.labels(channel));
+        if legacy_names() {
+            histogram!(self.legacy.claimed_amount, amount);
+        }
+    }
+
+    pub fn failed(&self, channel: &str) {
+        self.increment(METRIC_CLAIMS_FAILED, self.legacy.claims_failed, channel);
+    }
+
+    pub fn unparsed(&self, channel: &str) {
+        self.increment(METRIC_CLAIMS_UNPARSED, self.legacy.claims_unparsed, channel);
+    }
+}
+
+/// Stops counting the uptime of a bot when it stops running
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug)]
+pub(crate) struct Running {
+    key: (&'static str, String),
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "thepositivebot",
+        feature = "okayegbot",
+        feature = "leavesbot"
+    )),
+    allow(dead_code)
+)]
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(ages) = AGES
+            .lock()
+            .expect("ages should not be poisoned")
+            .get_mut(&self.key)
+        {
+            ages.started = None;
+        }
+        gauge!(
+            METRIC_UPTIME,
+            0.,
+            "bot" => self.key.0,
+            "account" => self.key.1.clone()
+        );
+    }
+}
+
+/// Records every metric of every test, as metrics only has a global recorder
+#[cfg(test)]
+pub(crate) mod testing {
+    use lazy_static::lazy_static;
+    use metrics_util::{CompositeKey, DebugValue, DebuggingRecorder, Snapshotter};
+
+    lazy_static! {
+        static ref SNAPSHOTTER: Snapshotter = {
+            let recorder = DebuggingRecorder::new();
+            let snapshotter = recorder.snapshotter();
+            recorder
+                .install()
+                .expect("no other recorder should be installed in tests");
+            snapshotter
+        };
+    }
+
+    /// Installs the recorder if it is not yet and returns the metrics with
+    /// the label `account` set to `account`.
+    pub fn recorded(account: &str) -> Vec<(CompositeKey, DebugValue)> {
+        SNAPSHOTTER
+            .snapshot()
+            .into_iter()
+            .filter(|(key, ..)| {
+                key.key()
+                    .labels()
+                    .any(|label| label.key() == "account" && label.value() == account)
+            })
+            .map(|(key, _, _, value)| (key, value))
+            .collect()
+    }
+
+    /// Installs the recorder if it is not yet and returns the value of the
+    /// metric `name` without labels.
+    pub fn unlabeled(name: &str) -> Option<DebugValue> {
+        SNAPSHOTTER
+            .snapshot()
+            .into_iter()
+            .find(|(key, ..)| {
+                key.key().name().to_string() == name && key.key().labels().next().is_none()
+            })
+            .map(|(_, _, _, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics_util::DebugValue;
+
+    use super::*;
+
+    static LEGACY: LegacyNames = LegacyNames {
+        total: "cookiebot.test.total",
+        claims_attempted: "cookiebot.test.claims.attempted",
+        claims_skipped: "cookiebot.test.claims.skipped",
+        claims_succeeded: "cookiebot.test.claims.succeeded",
+        claims_failed: "cookiebot.test.claims.failed",
+        claims_unparsed: "cookiebot.test.claims.parse_failures",
+        claimed_amount: "cookiebot.test.claimed",
+    };
+
+    #[test]
+    fn labels_claims_by_bot_and_channel() {
+        testing::recorded("metrics-test");
+        let metrics = ClaimMetrics::new("thepositivebot", "Metrics-Test", &LEGACY);
+
+        metrics.total("cookies", 728.);
+        let recorded = testing::recorded("metrics-test");
+
+        let labeled = recorded
+            .iter()
+            .find(|(key, _)| key.key().name().to_string() == METRIC_TOTAL)
+            .expect("total should be recorded");
+        let labels: Vec<_> = labeled
+            .0
+            .key()
+            .labels()
+            .map(|label| (label.key(), label.value()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("bot", "thepositivebot"),
+                ("channel", "cookies"),
+                ("account", "metrics-test")
+            ]
+        );
+        // the legacy name is not labeled with the account
+        assert!(!recorded
+            .iter()
+            .any(|(key, _)| key.key().name().to_string() == LEGACY.total));
+    }
+
+    #[test]
+    fn keeps_legacy_names_unlabeled() {
+        // names of no other test, so the values are not shared
+        static UNLABELED: LegacyNames = LegacyNames {
+            total: "cookiebot.unlabeled.total",
+            claims_attempted: "cookiebot.unlabeled.claims.attempted",
+            claims_skipped: "cookiebot.unlabeled.claims.skipped",
+            claims_succeeded: "cookiebot.unlabeled.claims.succeeded",
+            claims_failed: "cookiebot.unlabeled.claims.failed",
+            claims_unparsed: "cookiebot.unlabeled.claims.parse_failures",
+            claimed_amount: "cookiebot.unlabeled.claimed",
+        };
+        let metrics = ClaimMetrics::new("thepositivebot", "legacy-test", &UNLABELED);
+
+        metrics.total("cookies", 728.);
+        metrics.attempted("cookies");
+
+        assert_eq!(
+            testing::unlabeled(UNLABELED.total),
+            Some(DebugValue::Gauge(728.0.into()))
+        );
+        assert_eq!(
+            testing::unlabeled(UNLABELED.claims_attempted),
+            Some(DebugValue::Counter(1))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tracks_uptime_and_last_claim() {
+        let gauge = |name: &str| {
+            testing::recorded("ages-test")
+                .into_iter()
+                .find(|(key, _)| key.key().name().to_string() == name)
+                .map(|(_, value)| match value {
+                    DebugValue::Gauge(value) => value.0,
+                    value => panic!("{} should be a gauge, not {:?}", name, value),
+                })
+        };
+        gauge(METRIC_UPTIME);
+        let metrics = ClaimMetrics::new("thepositivebot", "ages-test", &LEGACY);
+
+        let running = metrics.running();
+        tokio::time::advance(Duration::from_secs(30)).await;
+        metrics.succeeded("cookies", 14.);
+        tokio::time::advance(Duration::from_secs(60)).await;
+        update_ages();
+        assert_eq!(gauge(METRIC_UPTIME), Some(90.));
+        assert_eq!(gauge(METRIC_SINCE_LAST_CLAIM), Some(60.));
+
+        drop(running);
+        tokio::time::advance(Duration::from_secs(60)).await;
+        update_ages();
+        assert_eq!(gauge(METRIC_UPTIME), Some(0.));
+        assert_eq!(gauge(METRIC_SINCE_LAST_CLAIM), Some(120.));
+    }
+}
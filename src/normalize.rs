@@ -0,0 +1,47 @@
+/// Returns true for characters that carry no visible meaning in a chat
+/// message: tag characters (U+E0000–U+E007F), which bots append to get around
+/// the duplicate message filter, and emoji variation selectors.
+const fn is_invisible(c: char) -> bool {
+    matches!(c,
+        '\u{E0000}'..='\u{E007F}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{E0100}'..='\u{E01EF}')
+}
+
+/// Normalizes a chat message before it is matched against any pattern.
+///
+/// Strips invisible characters and trailing whitespace.
+pub fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !is_invisible(*c))
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn strips_trailing_whitespace() {
+        assert_eq!(normalize("Total egs: 92 🥚  \t "), "Total egs: 92 🥚");
+    }
+
+    #[test]
+    fn strips_tag_characters() {
+        assert_eq!(normalize("!cookie\u{E0000}"), "!cookie");
+        assert_eq!(normalize("leaves 🍃 \u{E0000} \u{E007F}"), "leaves 🍃");
+    }
+
+    #[test]
+    fn strips_variation_selectors() {
+        assert_eq!(normalize("🍪\u{FE0F} "), "🍪");
+        assert_eq!(normalize("🥚\u{E0100}"), "🥚");
+    }
+
+    #[test]
+    fn keeps_leading_text() {
+        assert_eq!(normalize(" @chronophylos"), " @chronophylos");
+    }
+}
@@ -0,0 +1,562 @@
+//! Notifications about failures and milestones, posted to a Discord webhook
+//! or sent to a user in chat
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::sleep,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    anomaly::{self, Anomaly, AnomalyOptions},
+    bot,
+    chat::{ChatClient, ChatTransport},
+    http,
+    once::ClaimOutcome,
+    secrettoken::SharedToken,
+    util::{ExponentialBackoff, SlidingWindowCounter, TokenBucket},
+    HttpOptions, Timestamp,
+};
+
+/// How often a notification is posted before it is dropped
+const DELIVERY_ATTEMPTS: u32 = 3;
+const DELIVERY_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How many messages the user is sent in `CHAT_WINDOW`, so a bot failing
+/// over and over does not spam them
+const CHAT_LIMIT: usize = 3;
+const CHAT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    /// Feed the tasks sending notifications, if any were started
+    static ref QUEUES: Mutex<Vec<UnboundedSender<Event>>> = Mutex::new(Vec::new());
+}
+
+/// URL of a Discord webhook, which contains its token
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct WebhookUrl(String);
+
+impl From<&str> for WebhookUrl {
+    fn from(url: &str) -> Self {
+        Self(url.to_string())
+    }
+}
+
+impl fmt::Debug for WebhookUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Url::parse(&self.0) {
+            Ok(url) => write!(f, "\"{}/***\"", url.origin().ascii_serialization()),
+            Err(_) => write!(f, "\"***\""),
+        }
+    }
+}
+
+/// Kinds of events that can be notified about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum EventKind {
+    /// The target bot left the channel and the bot waits for it
+    Suspended,
+
+    /// The account is banned from the channel
+    Banned,
+
+    /// Chat rejected the login, which stops every bot
+    AuthFailed,
+
+    /// A bot stopped after an error restarting cannot help
+    Stopped,
+
+    /// An account prestiged
+    Prestiged,
+
+    /// The total of an account crossed one of the milestones
+    Milestone,
+
+    /// The bots crossed one of the thresholds of `anomalies`
+    Anomaly,
+}
+
+/// Where notifications are sent and which
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NotificationOptions {
+    pub discord_webhook: WebhookUrl,
+
+    /// Events to notify about, all if unset
+    #[serde(default = "all_events")]
+    pub events: Vec<EventKind>,
+
+    /// Totals to notify about when an account reaches them
+    #[serde(default)]
+    pub milestones: Vec<i64>,
+
+    /// When the bots behave unusually, nothing is looked for if unset
+    #[serde(default)]
+    pub anomalies: Option<AnomalyOptions>,
+}
+
+fn all_events() -> Vec<EventKind> {
+    vec![
+        EventKind::Suspended,
+        EventKind::Banned,
+        EventKind::AuthFailed,
+        EventKind::Stopped,
+        EventKind::Prestiged,
+        EventKind::Milestone,
+        EventKind::Anomaly,
+    ]
+}
+
+/// Something that happened to a bot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Suspended {
+        bot: &'static str,
+        account: String,
+        chatter: String,
+        suspension: Duration,
+    },
+    Banned {
+        bot: &'static str,
+        account: String,
+        channel: String,
+    },
+    AuthFailed {
+        bot: &'static str,
+        account: String,
+        error: String,
+    },
+    Stopped {
+        bot: &'static str,
+        account: String,
+        error: String,
+    },
+    Prestiged {
+        account: String,
+        rank: String,
+    },
+
+    /// A claim, which may cross a milestone
+    Claimed {
+        bot: &'static str,
+        account: String,
+        outcome: ClaimOutcome,
+    },
+
+    Anomaly(Anomaly),
+}
+
+impl Event {
+    const fn kind(&self) -> EventKind {
+        match self {
+            Self::Suspended { .. } => EventKind::Suspended,
+            Self::Banned { .. } => EventKind::Banned,
+            Self::AuthFailed { .. } => EventKind::AuthFailed,
+            Self::Stopped { .. } => EventKind::Stopped,
+            Self::Prestiged { .. } => EventKind::Prestiged,
+            Self::Claimed { .. } => EventKind::Milestone,
+            Self::Anomaly(_) => EventKind::Anomaly,
+        }
+    }
+
+    /// Returns whether the event needs the owner to step in.
+    const fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Banned { .. } | Self::AuthFailed { .. } | Self::Stopped { .. }
+        )
+    }
+
+    /// Returns what happened, or `None` for claims, which are only told
+    /// about when they reach a milestone.
+    fn describe(&self) -> Option<String> {
+        match self {
+            Self::Suspended {
+                bot,
+                account,
+                chatter,
+                suspension,
+            } => Some(format!(
+                "{} of {} is suspended for {}: {} is not in chat",
+                bot,
+                account,
+                suspension.as_readable(),
+                chatter
+            )),
+            Self::Banned {
+                bot,
+                account,
+                channel,
+            } => Some(format!(
+                "{} of {} is banned from #{}",
+                bot, account, channel
+            )),
+            Self::AuthFailed {
+                bot,
+                account,
+                error,
+            } => Some(format!(
+                "Every bot stopped, {} of {} could not log in: {}",
+                bot, account, error
+            )),
+            Self::Stopped {
+                bot,
+                account,
+                error,
+            } => Some(format!("{} of {} stopped: {}", bot, account, error)),
+            Self::Prestiged { account, rank } => Some(format!("{} prestiged to {}", account, rank)),
+            Self::Claimed { .. } => None,
+            Self::Anomaly(anomaly) => Some(anomaly.to_string()),
+        }
+    }
+}
+
+/// Turns events into the messages to post
+#[derive(Debug)]
+struct Notifier {
+    options: NotificationOptions,
+
+    /// Last total by bot label and account
+    totals: HashMap<(&'static str, String), i64>,
+}
+
+impl Notifier {
+    fn new(options: NotificationOptions) -> Self {
+        Self {
+            options,
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Returns the message for `event`, or `None` if it is not notified
+    /// about.
+    ///
+    /// The first total seen of an account only sets where milestones are
+    /// counted from.
+    fn message(&mut self, event: &Event) -> Option<String> {
+        if !self.options.events.contains(&event.kind()) {
+            return None;
+        }
+
+        match event {
+            Event::Claimed {
+                bot,
+                account,
+                outcome,
+            } => {
+                let total = match outcome {
+                    ClaimOutcome::Claimed { total, .. } => *total,
+                    ClaimOutcome::Cooldown { total, .. } => (*total)?,
+                };
+                let previous = self.totals.insert((bot, account.clone()), total)?;
+                let milestone = self
+                    .options
+                    .milestones
+                    .iter()
+                    .filter(|milestone| previous < **milestone && **milestone <= total)
+                    .max()?;
+
+                Some(format!(
+                    "{} of {} reached {} with a total of {}",
+                    bot, account, milestone, total
+                ))
+            }
+            event => event.describe(),
+        }
+    }
+}
+
+/// Posts `content` to the webhook, retrying a few times on failure.
+async fn deliver(client: &http::Client, webhook: &WebhookUrl, content: &str) {
+    let mut backoff = ExponentialBackoff::new(DELIVERY_RETRY_DELAY, DELIVERY_RETRY_DELAY * 4);
+
+    loop {
+        let request = client
+            .post(webhook.0.as_str())
+            .json(&json!({ "content": content }));
+
+        match client.send(request).await {
+            Ok(_) => {
+                debug!("Sent notification: {}", content);
+                return;
+            }
+            Err(err) if backoff.attempt() + 1 < DELIVERY_ATTEMPTS => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "Could not send notification: {}. Retrying in {}",
+                    err,
+                    delay.as_readable()
+                );
+                sleep(delay).await;
+            }
+            Err(err) => {
+                warn!("Dropping notification {:?}: {}", content, err);
+                return;
+            }
+        }
+    }
+}
+
+/// Starts posting notifications about the events in `options` to its
+/// webhook.
+///
+/// Notifications are posted by a task of their own, so a slow webhook never
+/// holds up a bot.
+pub fn start(options: NotificationOptions, http: HttpOptions) -> Result<(), bot::Error> {
+    let client = bot::build_client(http, false)?;
+    let webhook = options.discord_webhook.clone();
+    if let Some(anomalies) = options.anomalies {
+        anomaly::start(anomalies);
+    }
+    let mut notifier = Notifier::new(options);
+    info!("Sending notifications to {:?}", webhook);
+    let (sender, mut receiver) = unbounded_channel::<Event>();
+
+    tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            if let Some(content) = notifier.message(&event) {
+                deliver(&client, &webhook, &content).await;
+            }
+        }
+    });
+
+    QUEUES
+        .lock()
+        .expect("notification queues should not be poisoned")
+        .push(sender);
+
+    Ok(())
+}
+
+/// How the user is told about fatal errors in chat
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub enum NotifyVia {
+    #[default]
+    Whisper,
+
+    /// Mention the user in the channel
+    Channel(String),
+}
+
+/// Tells a user in chat when a bot fails in a way only they can fix
+#[derive(Debug)]
+pub struct ChatNotifier {
+    user: String,
+    via: NotifyVia,
+    username: String,
+    token: SharedToken,
+    transport: ChatTransport,
+    message_limiter: Option<Arc<TokenBucket>>,
+    sent: SlidingWindowCounter,
+    dry_run: bool,
+}
+
+impl ChatNotifier {
+    /// Notifies `user` via `via`, writing as `username` with `token`.
+    pub fn new(
+        user: &str,
+        via: NotifyVia,
+        username: &str,
+        token: SharedToken,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            user: user.to_lowercase(),
+            via,
+            username: username.to_lowercase(),
+            token,
+            transport: ChatTransport::default(),
+            message_limiter: None,
+            sent: SlidingWindowCounter::new(CHAT_WINDOW),
+            dry_run,
+        }
+    }
+
+    /// Connects to chat over `transport`.
+    pub const fn with_chat_transport(mut self, transport: ChatTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Shares `limiter` with the bots writing as the same account.
+    pub fn with_message_limiter(mut self, limiter: Arc<TokenBucket>) -> Self {
+        self.message_limiter = Some(limiter);
+        self
+    }
+
+    /// Returns the channel to write to and the message telling the user
+    /// about `event`, if it is fatal.
+    fn message(&self, event: &Event) -> Option<(String, String)> {
+        if !event.is_fatal() {
+            return None;
+        }
+        let text = event.describe()?;
+
+        Some(match &self.via {
+            NotifyVia::Whisper => (self.username.clone(), format!("/w {} {}", self.user, text)),
+            NotifyVia::Channel(channel) => {
+                (channel.to_lowercase(), format!("@{} {}", self.user, text))
+            }
+        })
+    }
+
+    async fn notify(&mut self, event: &Event) {
+        let (channel, message) = match self.message(event) {
+            Some(message) => message,
+            None => return,
+        };
+        if !self.sent.try_record(CHAT_LIMIT) {
+            warn!(
+                "Not telling {} about {:?}, told them too often",
+                self.user, event
+            );
+            return;
+        }
+
+        if self.dry_run {
+            info!("Dry run: would send '{}' to #{}", message, channel);
+            return;
+        }
+        if let Some(limiter) = &self.message_limiter {
+            // without a deadline the limiter always hands out a token
+            let _ = limiter.acquire(None).await;
+        }
+
+        // a new connection picks up a refreshed token
+        let (_, client) = self.transport.connect(
+            self.username.clone(),
+            self.token.get().expose_secret().to_string(),
+        );
+        client.join(channel.clone());
+        match client.say(channel, message).await {
+            Ok(()) => debug!("Told {} about {:?}", self.user, event),
+            Err(err) => warn!("Could not tell {} about {:?}: {}", self.user, event, err),
+        }
+    }
+
+    /// Starts telling the user about fatal errors.
+    pub fn start(mut self) {
+        let (sender, mut receiver) = unbounded_channel::<Event>();
+        info!("Telling {} about fatal errors", self.user);
+
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                self.notify(&event).await;
+            }
+        });
+
+        QUEUES
+            .lock()
+            .expect("notification queues should not be poisoned")
+            .push(sender);
+    }
+}
+
+/// Queues `event` for every notifier that was started.
+pub fn send(event: Event) {
+    for queue in QUEUES
+        .lock()
+        .expect("notification queues should not be poisoned")
+        .iter()
+    {
+        // the tasks only end with the runtime
+        let _ = queue.send(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{secrettoken::Token, SecretToken};
+
+    fn notifier(events: Vec<EventKind>) -> Notifier {
+        Notifier::new(NotificationOptions {
+            discord_webhook: "https://discord.com/api/webhooks/1/secret".into(),
+            events,
+            milestones: vec![1000, 5000],
+            anomalies: None,
+        })
+    }
+
+    fn claimed(total: i64) -> Event {
+        Event::Claimed {
+            bot: "thepositivebot",
+            account: "chronophylos".to_string(),
+            outcome: ClaimOutcome::Claimed { amount: 10, total },
+        }
+    }
+
+    #[test]
+    fn notifies_crossed_milestones() {
+        let mut notifier = notifier(all_events());
+
+        assert_eq!(notifier.message(&claimed(990)), None);
+        assert_eq!(
+            notifier.message(&claimed(1002)),
+            Some("thepositivebot of chronophylos reached 1000 with a total of 1002".to_string())
+        );
+        assert_eq!(notifier.message(&claimed(1012)), None);
+    }
+
+    #[test]
+    fn skips_unselected_events() {
+        let mut notifier = notifier(vec![EventKind::Prestiged]);
+        let stopped = Event::Stopped {
+            bot: "okayegbot",
+            account: "chronophylos".to_string(),
+            error: "Invalid configuration".to_string(),
+        };
+
+        assert_eq!(notifier.message(&stopped), None);
+        assert!(notifier
+            .message(&Event::Prestiged {
+                account: "chronophylos".to_string(),
+                rank: "P2: Default".to_string(),
+            })
+            .is_some());
+    }
+
+    #[test]
+    fn mentions_user_on_fatal_events() {
+        let notifier = ChatNotifier::new(
+            "Chronophylos",
+            NotifyVia::Channel("Cookies".to_string()),
+            "cookiebot",
+            SharedToken::new(SecretToken::new(Token::new("token"))),
+            true,
+        );
+        let banned = Event::Banned {
+            bot: "okayegbot",
+            account: "chronophylos".to_string(),
+            channel: "cookies".to_string(),
+        };
+
+        assert_eq!(
+            notifier.message(&banned),
+            Some((
+                "cookies".to_string(),
+                "@chronophylos okayegbot of chronophylos is banned from #cookies".to_string()
+            ))
+        );
+        assert_eq!(notifier.message(&claimed(5000)), None);
+    }
+
+    #[test]
+    fn hides_webhook_token() {
+        let webhook = WebhookUrl::from("https://discord.com/api/webhooks/1/secret");
+
+        assert_eq!(format!("{:?}", webhook), "\"https://discord.com/***\"");
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use tracing::{debug, instrument};
+
+use crate::http::{self, RetryError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not send request: {0}")]
+    SendRequest(#[source] RetryError),
+
+    #[error("Could not read response: {0}")]
+    ReadResponse(#[source] reqwest::Error),
+
+    #[error("Could not deserialize response: {0}")]
+    DeserializeResponse(#[source] serde_json::Error),
+
+    #[error("Request returned bad status code: {0}")]
+    BadStatusCode(#[source] RetryError),
+}
+
+impl From<Error> for crate::Error {
+    fn from(err: Error) -> Self {
+        Self::Http(Box::new(err))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct UserResponse {
+    pub userid: u64,
+    pub username: String,
+    pub egs: i32,
+    /// When the user last claimed
+    #[serde(default, deserialize_with = "deserialize_cooldown")]
+    pub cooldown: Option<DateTime<Utc>>,
+}
+
+/// Reads a timestamp, treating `null`, `"none"` and `""` as no cooldown.
+fn deserialize_cooldown<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(s) if s.is_empty() || s.eq_ignore_ascii_case("none") => Ok(None),
+        Some(s) => s.parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+/// Returns the remaining cooldown after claiming at `last_used` or `None` if
+/// egs can be claimed.
+pub fn remaining(last_used: DateTime<Utc>, claim_cooldown: Duration) -> Option<Duration> {
+    let now = Utc::now();
+
+    debug!(
+        "Server reported cooldown as {}, current time is {}",
+        last_used, now
+    );
+
+    // a last use in the future means our clock is behind
+    let elapsed = (now - last_used).to_std().unwrap_or_default();
+
+    claim_cooldown.checked_sub(elapsed)
+}
+
+/// Egs of a user and the time until they can claim again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EgStatus {
+    pub egs: i32,
+    pub cooldown_secs: Option<u64>,
+}
+
+/// Client for api.okayeg.com
+#[derive(Debug, Clone)]
+pub struct Okayeg {
+    base: String,
+}
+
+impl Okayeg {
+    pub fn new<S>(base_url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            base: base_url.into(),
+        }
+    }
+
+    /// Returns the profile of `username` or `None` if okayeg does not know
+    /// the user yet.
+    #[instrument(skip(self, client))]
+    pub async fn user(
+        &self,
+        client: &http::Client,
+        username: &str,
+    ) -> Result<Option<UserResponse>, Error> {
+        let request = client
+            .get(format!("{}/user", self.base))
+            .query(&[("username", username)]);
+
+        let body = match client.send(request).await {
+            Ok(response) => response.bytes().await.map_err(Error::ReadResponse)?,
+            Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => return Ok(None),
+            Err(err) if err.is_status() => return Err(Error::BadStatusCode(err)),
+            Err(err) => return Err(Error::SendRequest(err)),
+        };
+
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+
+        serde_json::from_slice(&body).map_err(Error::DeserializeResponse)
+    }
+
+    /// Returns the egs of `username` and their remaining cooldown or `None`
+    /// if okayeg does not know the user yet.
+    pub async fn status(
+        &self,
+        client: &http::Client,
+        username: &str,
+        claim_cooldown: Duration,
+    ) -> Result<Option<EgStatus>, Error> {
+        Ok(self.user(client, username).await?.map(|user| EgStatus {
+            egs: user.egs,
+            cooldown_secs: user
+                .cooldown
+                .and_then(|last_used| remaining(last_used, claim_cooldown))
+                .map(|remaining| remaining.as_secs()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn user_with_cooldown(cooldown: serde_json::Value) -> UserResponse {
+        serde_json::from_value(json!({
+            "userid": 54946241,
+            "username": "chronophylos",
+            "egs": 42,
+            "cooldown": cooldown,
+        }))
+        .expect("user should deserialize")
+    }
+
+    #[test]
+    fn deserialize_missing_cooldown() {
+        for cooldown in &[json!(null), json!("none"), json!("")] {
+            assert_eq!(user_with_cooldown(cooldown.clone()).cooldown, None);
+        }
+    }
+
+    #[test]
+    fn deserialize_cooldown() {
+        assert_eq!(
+            user_with_cooldown(json!("2021-03-01T12:00:00.000Z")).cooldown,
+            Some(Utc.ymd(2021, 3, 1).and_hms(12, 0, 0))
+        );
+    }
+
+    #[tokio::test]
+    async fn status_of_known_user() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .and(query_param("username", "chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "userid": 54946241,
+                "username": "chronophylos",
+                "egs": 42,
+                "cooldown": "2021-03-01T12:00:00Z",
+            })))
+            .mount(&server)
+            .await;
+
+        let status = Okayeg::new(server.uri())
+            .status(
+                &http::Client::default(),
+                "chronophylos",
+                Duration::from_secs(3600),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            status,
+            Some(EgStatus {
+                egs: 42,
+                cooldown_secs: None
+            })
+        );
+    }
+}
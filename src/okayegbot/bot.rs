@@ -1,215 +1,562 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use chrono::{DateTime, Utc};
-use lazy_static::lazy_static;
-use secrecy::ExposeSecret;
-use serde::Deserialize;
+use metrics::{register_counter, register_gauge, register_histogram, Unit};
 use tokio::{sync::mpsc::UnboundedReceiver, time::sleep};
-use tracing::{debug, error, info, instrument, trace, warn};
-use twitch_irc::{
-    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, TCPTransport,
-    TwitchIRCClient,
-};
+use tracing::{error, info, instrument, trace, warn};
+use twitch_irc::message::ServerMessage;
 
 use crate::{
-    bot::{self, Bot},
-    SecretToken, Timestamp,
+    admin, board,
+    bot::{self, Bot, DryRun},
+    chat::{ChatClient, ChatTransport},
+    chatters::ChattersApi,
+    cooldown, history, http,
+    metrics::{ClaimMetrics, LegacyNames},
+    notify,
+    once::{run_once, CancellationToken, OnceError},
+    presence::Presence,
+    secrettoken::SharedToken,
+    state,
+    suffix::AntiDuplicate,
+    util::ExponentialBackoff,
+    util::TokenBucket,
+    Blackouts, ChannelFailover, Channels, CommunicateOptions, Error, HttpOptions, Jitter,
+    QuietHours, Schedule, Timestamp,
 };
 
 use super::{
-    parser::{ClaimEgs, ClaimEgsParserError},
+    api::{self, Okayeg},
+    config::Config,
+    parser::ClaimEgs,
     patterns::GENERIC_ANSWER,
 };
 
 static OKAYEG_BOT_USER_ID: &str = "75501168";
+static BOT_LABEL: &str = "okayegbot";
+
+/// How often looking up the chatters may fail in a row before giving up
+const CHATTERS_RETRIES: u32 = 3;
+
+/// Names of the claim metrics before they were labeled by bot
+static LEGACY_METRICS: LegacyNames = LegacyNames {
+    total: "cookiebot.egs.total",
+    claims_attempted: "cookiebot.egs.claims.attempted",
+    claims_skipped: "cookiebot.egs.claims.skipped",
+    claims_succeeded: "cookiebot.egs.claims.succeeded",
+    claims_failed: "cookiebot.egs.claims.failed",
+    claims_unparsed: "cookiebot.egs.claims.parse_failures",
+    claimed_amount: "cookiebot.egs.claimed",
+};
 
-lazy_static! {
-    static ref CLAIM_EGS_COOLDOWN: chrono::Duration = chrono::Duration::hours(1);
-}
-
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("Could not communicate with target bot: {0}")]
-    Communication(#[source] bot::Error),
-
-    #[error("Could not parse claim egs message: {0}")]
-    ParseClaimEgs(#[from] ClaimEgsParserError),
-
-    #[error("Could not get client: {0}")]
-    GetClient(#[source] bot::Error),
-
-    #[error("Could not send request: {0}")]
-    SendRequest(#[source] reqwest::Error),
-
-    #[error("Could not deserialize response: {0}")]
-    DeserializeResponse(#[source] reqwest::Error),
-
-    #[error("Could not check chatters: {0}")]
-    CheckChatters(#[source] bot::Error),
+/// Registers the eg metrics with the recorder, with the names before
+/// they were labeled by bot if `legacy` is set.
+pub(crate) fn register_metrics(legacy: bool) {
+    if !legacy {
+        return;
+    }
 
-    #[error("Request returned bad status code: {0}")]
-    BadStatusCode(#[source] reqwest::Error),
+    register_gauge!(LEGACY_METRICS.total, Unit::Count, "total number of egs");
+    register_counter!(
+        LEGACY_METRICS.claims_attempted,
+        Unit::Count,
+        "number of eg claims sent to chat"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_skipped,
+        Unit::Count,
+        "number of eg claims skipped on purpose"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_succeeded,
+        Unit::Count,
+        "number of successful eg claims"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_failed,
+        Unit::Count,
+        "number of eg claims that hit the cooldown"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_unparsed,
+        Unit::Count,
+        "number of eg claim answers that could not be parsed"
+    );
+    register_histogram!(
+        LEGACY_METRICS.claimed_amount,
+        Unit::Count,
+        "egs gained per claim"
+    );
 }
 
-#[derive(Debug, Deserialize)]
-struct UserResponse {
-    userid: u64,
-    username: String,
-    egs: i32,
-    cooldown: DateTime<Utc>,
+/// Everything an [`EgBot`] is built from besides its account
+#[derive(Debug, Clone, Default)]
+pub struct EgBotOptions {
+    /// The okayegbot section, which also holds the channels, schedule and
+    /// communication options
+    pub config: Config,
+    pub blackouts: Blackouts,
+    pub http: HttpOptions,
+    pub chatters: ChattersApi,
 }
 
 #[derive(Debug)]
 pub struct EgBot {
     username: String,
-    token: SecretToken,
-    channel: String,
+    metrics: ClaimMetrics,
+    token: SharedToken,
+    channels: ChannelFailover,
+    schedule: Schedule,
+    blackouts: Blackouts,
+    communicate: CommunicateOptions,
+    config: Config,
+    api: Okayeg,
+    chatters: ChattersApi,
+    presence: Presence,
+    anti_duplicate: AntiDuplicate,
+    pacing: Option<Arc<TokenBucket>>,
+    message_limiter: Option<Arc<TokenBucket>>,
+    chat_transport: ChatTransport,
+    dry_run: Option<DryRun>,
+    jitter: Jitter,
+    quiet_hours: Option<QuietHours>,
+    client: http::Client,
 }
 
 impl EgBot {
-    pub fn new(username: String, token: SecretToken, channel: String) -> Self {
-        Self {
+    pub const CLAIM_COOLDOWN: Duration = Duration::from_secs(3600);
+    pub const API_BASE: &'static str = "https://api.okayeg.com";
+
+    /// Creates the bot of the account `username` logging in with `token`.
+    pub fn with_options(
+        username: String,
+        token: SharedToken,
+        options: EgBotOptions,
+    ) -> Result<Self, bot::Error> {
+        let EgBotOptions {
+            config,
+            blackouts,
+            http,
+            chatters,
+        } = options;
+        let client = bot::build_client(http, false)?;
+        let login = username.to_lowercase();
+
+        Ok(Self {
+            metrics: ClaimMetrics::new(BOT_LABEL, &login, &LEGACY_METRICS),
             username,
             token,
-            channel,
-        }
+            channels: ChannelFailover::new(BOT_LABEL, &login, config.channel.clone()),
+            schedule: config.schedule,
+            blackouts,
+            communicate: config.communicate,
+            api: Okayeg::new(config.api_base.clone()),
+            anti_duplicate: AntiDuplicate::new(config.duplicate_suffix.clone()),
+            config,
+            chatters,
+            presence: Presence::default(),
+            pacing: None,
+            message_limiter: None,
+            chat_transport: ChatTransport::default(),
+            dry_run: None,
+            jitter: Jitter::default(),
+            quiet_hours: None,
+            client,
+        })
+    }
+
+    /// Creates the bot from positional arguments, which override the
+    /// channels, schedule and communication options of `config`.
+    ///
+    /// Kept for compatibility, prefer [`with_options`](Self::with_options).
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        username: String,
+        token: SharedToken,
+        channels: Channels,
+        schedule: Schedule,
+        blackouts: Blackouts,
+        communicate: CommunicateOptions,
+        config: Config,
+        http: HttpOptions,
+        chatters: ChattersApi,
+    ) -> Result<Self, bot::Error> {
+        Self::with_options(
+            username,
+            token,
+            EgBotOptions {
+                config: Config {
+                    channel: channels,
+                    schedule,
+                    communicate,
+                    ..config
+                },
+                blackouts,
+                http,
+                chatters,
+            },
+        )
     }
 
-    #[instrument]
+    /// Spaces the commands of the bot with those of the other accounts
+    /// sharing `pacing`.
+    pub fn with_pacing(mut self, pacing: Arc<TokenBucket>) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
+    /// Keeps the messages of the bot within the limit of its account, shared
+    /// with the other bots of the account through `limiter`.
+    pub fn with_message_limiter(mut self, limiter: Arc<TokenBucket>) -> Self {
+        self.message_limiter = Some(limiter);
+        self
+    }
+
+    /// Connects to chat over `transport`.
+    pub const fn with_chat_transport(mut self, transport: ChatTransport) -> Self {
+        self.chat_transport = transport;
+        self
+    }
+
+    /// Logs the commands of the bot instead of sending them if `dry_run` is
+    /// enabled.
+    pub fn with_dry_run(mut self, dry_run: DryRun) -> Self {
+        self.dry_run = Some(dry_run).filter(|dry_run| dry_run.enabled);
+        self
+    }
+
+    /// Adds a random delay from `jitter` to every wait before a claim.
+    pub const fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Keeps the bot from claiming during `quiet_hours`.
+    pub const fn with_quiet_hours(mut self, quiet_hours: Option<QuietHours>) -> Self {
+        self.quiet_hours = quiet_hours;
+        self
+    }
+
+    #[instrument(skip(self), fields(bot = BOT_LABEL, username = %self.get_login()))]
     pub async fn run(&self) -> Result<(), Error> {
         info!("Running EgBot");
+        let _running = self.metrics.running();
+        info!("Claim schedule: {}", self.schedule);
+        info!("Claim jitter: {}", self.jitter);
+        if let Some(quiet_hours) = &self.quiet_hours {
+            info!("Quiet hours: {}", quiet_hours);
+        }
+        self.log_login_correction();
+
+        for blackout in self.blackouts.describe_upcoming(3) {
+            info!("Upcoming blackout: {}", blackout);
+        }
+
+        self.resume_saved_state().await;
+
+        // twitch_irc reconnects and rejoins on its own, so the connection is
+        // only rebuilt if the message stream closes
+        let (mut incoming_messages, mut client) = self.login().await?;
+
+        let mut cooldown_retry =
+            ExponentialBackoff::new(Duration::from_secs(10), Duration::from_secs(10))
+                .with_factor(1);
+        let mut chatters_retry =
+            ExponentialBackoff::new(Duration::from_secs(10), Duration::from_secs(60));
+        let mut claim_retry =
+            ExponentialBackoff::new(Duration::from_secs(15), Duration::from_secs(15))
+                .with_factor(1)
+                .with_jitter(0.5);
+
+        // the cooldown in a failed claim is authoritative, so the API is not
+        // asked again after waiting for it
+        let mut waited_for_claim_cooldown = false;
 
         loop {
-            match self.get_cooldown().await {
-                Ok(Some(cooldown)) => {
-                    info!("Eg cooldown: {}", cooldown.as_readable());
-                    sleep(cooldown).await
-                }
-                Ok(None) => {
-                    trace!("cooldown not active")
+            if !std::mem::take(&mut waited_for_claim_cooldown) {
+                match self.get_cooldown().await {
+                    Ok(Some(remaining)) => {
+                        info!("Eg cooldown: {}", remaining.as_readable());
+                        board::ready(BOT_LABEL, &self.get_login());
+                        cooldown::set(BOT_LABEL, &self.get_login(), remaining);
+                        self.wait_for(self.cooldown_delay(remaining)).await
+                    }
+                    Ok(None) => {
+                        trace!("cooldown not active");
+                        board::ready(BOT_LABEL, &self.get_login());
+
+                        let wait = self.claim_delay(Duration::from_secs(0));
+                        if wait > Duration::from_secs(0) {
+                            self.wait_for(wait).await
+                        }
+                    }
+                    Err(err) => {
+                        error!("Could not get cooldown: {:?}", err);
+
+                        sleep(cooldown_retry.next_delay()).await;
+                        continue;
+                    }
                 }
-                Err(err) => {
-                    error!("Could not get cooldown: {:?}", err);
+                cooldown_retry.reset();
+            }
 
-                    sleep(Duration::from_secs(10)).await;
-                    continue;
-                }
+            if self.config.skip_probability.should_skip() {
+                self.metrics.skipped(self.get_channel());
+                self.wait_for(self.cooldown_delay(self.config.claim_cooldown))
+                    .await;
+                continue;
             }
 
-            if !self
-                .check_chatters("okayegbot")
+            self.wait_for_quiet_hours().await;
+            if let Err(err) = self
+                .wait_for_presence(&mut incoming_messages, "okayegbot")
                 .await
-                .map_err(Error::CheckChatters)?
             {
+                if chatters_retry.attempt() >= CHATTERS_RETRIES {
+                    return Err(Error::CheckChatters(err));
+                }
+
+                let delay = chatters_retry.next_delay();
                 warn!(
-                    "OkayegBOT is not in #{}. Suspending bot for 30 minutes",
-                    self.channel
+                    "Could not check chatters: {}. Retrying in {}",
+                    err,
+                    delay.as_readable()
                 );
-                sleep(Duration::from_secs(60 * 30)).await;
+                sleep(delay).await;
                 continue;
             }
-
-            // login to chat server
-            let config = ClientConfig::new_simple(StaticLoginCredentials::new(
-                self.username.clone(),
-                Some(self.token.expose_secret().to_string()),
-            ));
-            let (mut incoming_messages, client) =
-                TwitchIRCClient::<TCPTransport, StaticLoginCredentials>::new(config);
-
-            client.join(self.channel.clone());
+            chatters_retry.reset();
 
             info!("Claiming egs");
-            match self.claim_egs(&client, &mut incoming_messages).await? {
+            admin::wait_while_paused(BOT_LABEL).await;
+            cooldown::set(BOT_LABEL, &self.get_login(), Duration::from_secs(0));
+            let response = match self.claim_egs(&client, &mut incoming_messages).await {
+                Ok(response) => response,
+                Err(Error::Communication(err)) if err.suspension().is_some() => {
+                    self.wait_out(&err).await;
+                    continue;
+                }
+                Err(Error::Communication(bot::Error::ConnectionLost)) => {
+                    let (new_incoming_messages, new_client) = self.reconnect().await?;
+                    incoming_messages = new_incoming_messages;
+                    client = new_client;
+                    continue;
+                }
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => {
+                    let delay = claim_retry.next_delay();
+                    warn!(
+                        "Could not claim egs: {}. Retrying in {}",
+                        err,
+                        delay.as_readable()
+                    );
+                    sleep(delay).await;
+                    continue;
+                }
+            };
+
+            board::claimed(BOT_LABEL, &self.get_login(), response.clone().into());
+            history::record(history::Claim::new(
+                BOT_LABEL,
+                &self.get_login(),
+                response.clone().into(),
+            ));
+            notify::send(notify::Event::Claimed {
+                bot: BOT_LABEL,
+                account: self.get_login(),
+                outcome: response.clone().into(),
+            });
+            match response {
                 ClaimEgs::Success {
                     username: _,
                     amount,
                     total,
                 } => {
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.metrics.succeeded(self.get_channel(), amount as f64);
+
                     info!("Claimed {} egs for a total of {} egs", amount, total);
 
-                    self.wait_for(Duration::from_secs(3600)).await
+                    self.wait_for(self.cooldown_delay(self.config.claim_cooldown))
+                        .await
                 }
                 ClaimEgs::Failure {
                     username: _,
+                    hours,
                     minutes,
                     seconds,
-                    total: _,
+                    total,
                 } => {
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.metrics.failed(self.get_channel());
+
                     warn!("Could not claim egs since cooldown is active");
                     let secs = seconds.unwrap_or(0);
                     let mins = minutes.unwrap_or(0);
+                    let hours = hours.unwrap_or(0);
 
-                    self.wait_for(Duration::from_secs(secs + mins * 60)).await
+                    self.wait_for(
+                        self.cooldown_delay(Duration::from_secs(secs + mins * 60 + hours * 3600)),
+                    )
+                    .await;
+                    waited_for_claim_cooldown = true;
                 }
             }
         }
     }
 
+    /// Restores the total and waits out the cooldown saved before the last
+    /// restart.
+    async fn resume_saved_state(&self) {
+        let saved = match state::saved(BOT_LABEL, &self.get_login()) {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        if let Some(total) = saved.total {
+            self.metrics.total(self.get_channel(), total as f64);
+        }
+        if let Some(remaining) = saved.remaining() {
+            info!("Saved cooldown, waiting for {}", remaining.as_readable());
+            board::ready(BOT_LABEL, &self.get_login());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), remaining).await;
+        }
+    }
+
+    /// Waits until the quiet hours are over, plus jitter.
+    async fn wait_for_quiet_hours(&self) {
+        if let Some(remaining) = self.quiet_hours.as_ref().and_then(QuietHours::remaining) {
+            let wait = self.jitter.add_to(remaining);
+            info!("Quiet hours, waiting for {}", wait.as_readable());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), wait).await;
+        }
+    }
+
+    /// Returns how long to wait before claiming when the cooldown expires in
+    /// `cooldown`, following the schedule, adding jitter and skipping
+    /// blackouts.
+    fn claim_delay(&self, cooldown: Duration) -> Duration {
+        self.blackouts
+            .defer(self.jitter.add_to(self.schedule.wait_time(cooldown)))
+    }
+
+    /// Returns how long to wait for a cooldown expiring in `remaining`,
+    /// including the safety margin.
+    fn cooldown_delay(&self, remaining: Duration) -> Duration {
+        self.claim_delay(remaining + self.config.safety_margin)
+    }
+
     async fn wait_for(&self, duration: Duration) {
         info!("Waiting for {}", duration.as_readable());
-        sleep(duration).await;
+        cooldown::sleep(BOT_LABEL, &self.get_login(), duration).await;
+    }
+
+    /// Claims egs once over a new chat connection.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use cookiebot::{once::CancellationToken, secrettoken::Token, EgBot, SecretToken};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let bot = EgBot::with_options(
+    ///     "chronophylos".to_string(),
+    ///     SecretToken::new(Token::new("token")).into(),
+    ///     Default::default(),
+    /// )?;
+    ///
+    /// let response = bot
+    ///     .claim_once(Some(Duration::from_secs(30)), &CancellationToken::new())
+    ///     .await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn claim_once(
+        &self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<ClaimEgs, OnceError<Error>> {
+        run_once(
+            async {
+                let (mut incoming_messages, client) = self.login().await?;
+                self.claim_egs(&client, &mut incoming_messages).await
+            },
+            timeout,
+            cancel,
+        )
+        .await
+    }
+
+    /// Returns the remaining eg cooldown or `None` if egs can be claimed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use cookiebot::{once::CancellationToken, secrettoken::Token, EgBot, SecretToken};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let bot = EgBot::with_options(
+    /// #     "chronophylos".to_string(),
+    /// #     SecretToken::new(Token::new("token")).into(),
+    /// #     Default::default(),
+    /// # )?;
+    /// if let Ok(None) = bot.check_cooldown(None, &CancellationToken::new()).await {
+    ///     println!("egs are ready");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_cooldown(
+        &self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Duration>, OnceError<Error>> {
+        run_once(self.get_cooldown(), timeout, cancel).await
     }
 
     #[instrument(skip(self, client, incoming_messages))]
     async fn claim_egs(
         &self,
-        client: &TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
+        client: &dyn ChatClient,
         incoming_messages: &mut UnboundedReceiver<ServerMessage>,
     ) -> Result<ClaimEgs, Error> {
-        self.communicate(client, incoming_messages, "=eg")
-            .await
-            .map_err(Error::Communication)?
-            .parse()
-            .map_err(Error::ParseClaimEgs)
-    }
+        self.metrics.attempted(self.get_channel());
 
-    async fn get_user_cooldown(&self) -> Result<DateTime<Utc>, Error> {
-        let client = self.get_client().map_err(Error::GetClient)?;
+        let message = self.communicate(client, incoming_messages, "=eg").await?;
 
-        let response: UserResponse = client
-            .get("https://api.okayeg.com/user")
-            .query(&[("username", &self.username)])
-            .send()
-            .await
-            .map_err(Error::SendRequest)?
-            .error_for_status()
-            .map_err(Error::BadStatusCode)?
-            .json()
-            .await
-            .map_err(Error::DeserializeResponse)?;
+        message.parse().map_err(|err| {
+            self.metrics.unparsed(self.get_channel());
+            warn!("Could not parse claim reply {:?}: {}", message, err);
+            Error::parse(&message, err)
+        })
+    }
+
+    /// Returns when the user last claimed or `None` if okayeg does not know
+    /// the user yet or has no cooldown for them.
+    async fn get_user_cooldown(&self) -> Result<Option<DateTime<Utc>>, Error> {
+        let user = self.api.user(self.get_client(), &self.get_login()).await?;
 
-        Ok(response.cooldown)
+        Ok(user.and_then(|response| {
+            self.metrics.total(self.get_channel(), response.egs as f64);
+            response.cooldown
+        }))
     }
 
     async fn get_cooldown(&self) -> Result<Option<Duration>, Error> {
-        let last_used = self.get_user_cooldown().await?;
-        let now = Utc::now();
-
-        debug!(
-            "Server reported cooldown as {}, current time is {}",
-            last_used, now
-        );
+        let last_used = match self.get_user_cooldown().await? {
+            Some(last_used) => last_used,
+            None => {
+                info!("okayeg knows no cooldown for {}", self.get_login());
+                return Ok(None);
+            }
+        };
 
-        match last_used
-            .checked_add_signed(*CLAIM_EGS_COOLDOWN)
-            .expect("Time should not overflow")
-            .signed_duration_since(now)
-            .to_std()
-        {
-            Ok(duration) => Ok(Some(duration)),
-            Err(_) => Ok(None),
-        }
+        Ok(api::remaining(last_used, self.config.claim_cooldown))
     }
 }
 
 impl Bot for EgBot {
-    fn accepts_invalid_certs(&self) -> bool {
-        false
-    }
-
-    fn get_channel(&self) -> &str {
-        &self.channel
+    fn get_channels(&self) -> &ChannelFailover {
+        &self.channels
     }
 
     fn get_bot_id(&self) -> &str {
@@ -220,7 +567,181 @@ impl Bot for EgBot {
         &self.username
     }
 
+    fn get_token(&self) -> &SharedToken {
+        &self.token
+    }
+
+    fn get_communicate_options(&self) -> CommunicateOptions {
+        self.communicate
+    }
+
     fn get_generic_answer(&self) -> &regex::Regex {
         &GENERIC_ANSWER
     }
+
+    fn get_chatters_api(&self) -> &ChattersApi {
+        &self.chatters
+    }
+
+    fn get_presence(&self) -> &Presence {
+        &self.presence
+    }
+
+    fn get_anti_duplicate(&self) -> &AntiDuplicate {
+        &self.anti_duplicate
+    }
+
+    fn get_pacing(&self) -> Option<&TokenBucket> {
+        self.pacing.as_deref()
+    }
+
+    fn get_message_limiter(&self) -> Option<&TokenBucket> {
+        self.message_limiter.as_deref()
+    }
+
+    fn get_chat_transport(&self) -> ChatTransport {
+        self.chat_transport
+    }
+
+    fn get_dry_run(&self) -> Option<&DryRun> {
+        self.dry_run.as_ref()
+    }
+
+    fn get_client(&self) -> &http::Client {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        chat::testing::{privmsg, ScriptedChat},
+        secrettoken::Token,
+        SecretToken,
+    };
+
+    fn test_bot(server: &MockServer) -> EgBot {
+        EgBot::with_options(
+            "Chronophylos".to_string(),
+            SecretToken::new(Token::new("token")).into(),
+            EgBotOptions {
+                config: Config {
+                    api_base: server.uri(),
+                    ..Config::default()
+                },
+                ..EgBotOptions::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn claims_egs_in_chat() {
+        let server = MockServer::start().await;
+        let (chat, mut receiver) = ScriptedChat::new();
+        chat.answer_next(&[&privmsg(
+            OKAYEG_BOT_USER_ID,
+            "okayegbot",
+            "okayegbot",
+            "@chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total egs: 92 🥚",
+        )]);
+
+        let claim = test_bot(&server)
+            .claim_egs(&chat, &mut receiver)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            claim,
+            ClaimEgs::Success {
+                amount: 1,
+                total: 92,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn gets_cooldown_from_configured_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .and(query_param("username", "chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "userid": 54946241,
+                "username": "chronophylos",
+                "egs": 42,
+                "cooldown": "2021-03-01T12:00:00Z",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            test_bot(&server).get_user_cooldown().await.unwrap(),
+            Some(Utc.ymd(2021, 3, 1).and_hms(12, 0, 0))
+        );
+    }
+
+    #[tokio::test]
+    async fn remaining_cooldown_uses_configured_interval() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "userid": 54946241,
+                "username": "chronophylos",
+                "egs": 42,
+                "cooldown": Utc::now() - chrono::Duration::minutes(10),
+            })))
+            .mount(&server)
+            .await;
+
+        let mut bot = test_bot(&server);
+        bot.config.claim_cooldown = Duration::from_secs(30 * 60);
+
+        let remaining = bot
+            .get_cooldown()
+            .await
+            .unwrap()
+            .expect("cooldown should be active");
+
+        assert!(remaining <= Duration::from_secs(20 * 60));
+        assert!(remaining > Duration::from_secs(19 * 60));
+    }
+
+    #[tokio::test]
+    async fn claims_for_unknown_users() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(test_bot(&server).get_cooldown().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn claims_without_user_in_body() {
+        for body in &["", "null"] {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path("/user"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(*body))
+                .mount(&server)
+                .await;
+
+            assert_eq!(test_bot(&server).get_cooldown().await.unwrap(), None);
+        }
+    }
 }
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::EgBot;
+use crate::{
+    Channels, CommunicateOptions, Jitter, Schedule, SecretToken, SkipProbability, SuffixStrategy,
+};
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub disabled: bool,
+    /// Channels to claim in, in order of preference
+    pub channel: Channels,
+    pub schedule: Schedule,
+
+    /// Random delay before every claim instead of the top level
+    /// `claim_jitter`
+    pub claim_jitter: Option<Jitter>,
+
+    /// Chance of skipping a claim on purpose and waiting another cooldown
+    pub skip_probability: SkipProbability,
+    pub communicate: CommunicateOptions,
+    pub duplicate_suffix: SuffixStrategy,
+
+    /// Base URL of the OkayegBOT API
+    pub api_base: String,
+
+    /// Account to claim egs with instead of the top level `username`
+    pub username: Option<String>,
+
+    /// Token of the account instead of the top level `token`, never refreshed
+    pub token: Option<SecretToken>,
+
+    /// Time between two eg claims
+    #[serde(with = "humantime_serde")]
+    pub claim_cooldown: Duration,
+
+    /// Added to every wait for the cooldown to make up for clock skew
+    #[serde(with = "humantime_serde")]
+    pub safety_margin: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            channel: "okayegbot".into(),
+            schedule: Schedule::default(),
+            claim_jitter: None,
+            skip_probability: SkipProbability::default(),
+            communicate: CommunicateOptions::default(),
+            duplicate_suffix: SuffixStrategy::default(),
+            api_base: EgBot::API_BASE.to_string(),
+            username: None,
+            token: None,
+            claim_cooldown: EgBot::CLAIM_COOLDOWN,
+            safety_margin: Duration::from_secs(5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_partial_section() {
+        let config: Config =
+            ron::de::from_str("(claim_cooldown: \"30m\")").expect("config should parse");
+
+        assert_eq!(config.claim_cooldown, Duration::from_secs(30 * 60));
+        assert_eq!(config.safety_margin, Config::default().safety_margin);
+        assert!(config.username.is_none());
+        assert!(config.token.is_none());
+    }
+}
@@ -1,5 +1,11 @@
+mod api;
 mod bot;
+mod config;
 mod parser;
 mod patterns;
 
-pub use bot::EgBot;
+pub use api::{EgStatus, Okayeg};
+pub(crate) use bot::register_metrics;
+pub use bot::{EgBot, EgBotOptions};
+pub use config::Config;
+pub use parser::ClaimEgs;
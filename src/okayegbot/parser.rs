@@ -1,6 +1,8 @@
 use tracing::instrument;
 
-use super::patterns::{CLAIM_BAD, CLAIM_GOOD};
+use crate::{diagnostics, normalize::normalize, once::ClaimOutcome};
+
+use super::patterns::{self, CLAIM_BAD, CLAIM_BAD_NAME, CLAIM_GOOD, CLAIM_GOOD_NAME};
 use std::str::FromStr;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -12,6 +14,7 @@ pub enum ClaimEgs {
     },
     Failure {
         username: String,
+        hours: Option<u64>,
         minutes: Option<u64>,
         seconds: Option<u64>,
         total: i32,
@@ -42,6 +45,7 @@ impl ClaimEgs {
         let captures = CLAIM_GOOD
             .captures(s)
             .ok_or(ClaimEgsParserError::InvalidMessage)?;
+        diagnostics::record(CLAIM_GOOD_NAME, &CLAIM_GOOD, &captures);
 
         let username = captures
             .name("username")
@@ -75,6 +79,7 @@ impl ClaimEgs {
         let captures = CLAIM_BAD
             .captures(s)
             .ok_or(ClaimEgsParserError::InvalidMessage)?;
+        diagnostics::record(CLAIM_BAD_NAME, &CLAIM_BAD, &captures);
 
         let username = captures
             .name("username")
@@ -82,6 +87,15 @@ impl ClaimEgs {
             .as_str()
             .to_string();
 
+        let hours = captures
+            .name("hours")
+            .map(|m| {
+                m.as_str()
+                    .parse()
+                    .map_err(ClaimEgsParserError::ParseIntError)
+            })
+            .transpose()?;
+
         let minutes = captures
             .name("minutes")
             .map(|m| {
@@ -109,6 +123,7 @@ impl ClaimEgs {
 
         Ok(Self::Failure {
             username,
+            hours,
             minutes,
             seconds,
             total,
@@ -121,16 +136,43 @@ impl FromStr for ClaimEgs {
 
     #[instrument]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = &normalize(s);
+
         if CLAIM_GOOD.is_match(s) {
             ClaimEgs::parse_success(s)
         } else if CLAIM_BAD.is_match(s) {
             ClaimEgs::parse_failure(s)
         } else {
+            diagnostics::report(s, patterns::diagnose(s));
+
             Err(ClaimEgsParserError::InvalidMessage)
         }
     }
 }
 
+impl From<ClaimEgs> for ClaimOutcome {
+    fn from(claim: ClaimEgs) -> Self {
+        match claim {
+            ClaimEgs::Success { amount, total, .. } => Self::Claimed {
+                amount: amount.into(),
+                total: total.into(),
+            },
+            ClaimEgs::Failure {
+                hours,
+                minutes,
+                seconds,
+                total,
+                ..
+            } => Self::Cooldown {
+                remaining_secs: (hours.is_some() || minutes.is_some() || seconds.is_some()).then(
+                    || hours.unwrap_or(0) * 3600 + minutes.unwrap_or(0) * 60 + seconds.unwrap_or(0),
+                ),
+                total: Some(total.into()),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +187,7 @@ mod tests {
             claim_egs,
             ClaimEgs::Failure {
                 username: "chronophylos".to_string(),
+                hours: None,
                 minutes: Some(10),
                 seconds: Some(56),
                 total: 60
@@ -152,6 +195,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn failure_outcome_adds_up_cooldown() {
+        let text = "@chronophylos nam1Sadeg no eg. come back in 1 hour, 3 minutes Total egs: 60";
+        let outcome = ClaimOutcome::from(text.parse::<ClaimEgs>().unwrap());
+
+        assert_eq!(
+            outcome,
+            ClaimOutcome::Cooldown {
+                remaining_secs: Some(3780),
+                total: Some(60)
+            }
+        );
+    }
+
+    #[test]
+    fn test_failure_with_hours() {
+        let text = "@chronophylos nam1Sadeg no eg. come back in 1 hour, 3 minutes Total egs: 60";
+        let claim_egs = text.parse::<ClaimEgs>().unwrap();
+
+        assert_eq!(
+            claim_egs,
+            ClaimEgs::Failure {
+                username: "chronophylos".to_string(),
+                hours: Some(1),
+                minutes: Some(3),
+                seconds: None,
+                total: 60
+            }
+        );
+    }
+
+    #[test]
+    fn test_failure_with_minutes_only() {
+        let text = "@chronophylos nam1Sadeg no eg. come back in 50 minutes, Total egs: 60";
+        let claim_egs = text.parse::<ClaimEgs>().unwrap();
+
+        assert_eq!(
+            claim_egs,
+            ClaimEgs::Failure {
+                username: "chronophylos".to_string(),
+                hours: None,
+                minutes: Some(50),
+                seconds: None,
+                total: 60
+            }
+        );
+    }
+
     #[test]
     fn test_success() {
         let text = "@chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total egs: 92 🥚 ";
@@ -198,4 +289,36 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_success_with_messy_trailer() {
+        let text = "@chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total egs: 92 🥚\u{FE0F} \u{E0000}  ";
+        let claim_egs = text.parse::<ClaimEgs>().unwrap();
+
+        assert_eq!(
+            claim_egs,
+            ClaimEgs::Success {
+                username: "chronophylos".to_string(),
+                amount: 1,
+                total: 92
+            }
+        );
+    }
+
+    #[test]
+    fn test_failure_with_messy_trailer() {
+        let text = "@chronophylos nam1Sadeg no eg. come back in 10 minutes, 56 seconds Total egs: 60 \u{E0000}";
+        let claim_egs = text.parse::<ClaimEgs>().unwrap();
+
+        assert_eq!(
+            claim_egs,
+            ClaimEgs::Failure {
+                username: "chronophylos".to_string(),
+                hours: None,
+                minutes: Some(10),
+                seconds: Some(56),
+                total: 60
+            }
+        );
+    }
 }
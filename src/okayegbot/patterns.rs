@@ -1,18 +1,48 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::diagnostics::{self, Component, Diagnosis};
+
+pub static CLAIM_GOOD_NAME: &str = "okayegbot::CLAIM_GOOD";
+pub static CLAIM_BAD_NAME: &str = "okayegbot::CLAIM_BAD";
+
+// https://regex101.com/r/6gc79V/4
+const CLAIM_GOOD_COMPONENTS: &[Component] = &[
+    ("username", r"@(?P<username>\w+) \| "),
+    ("message", r"[^\|]* \| "),
+    ("amount", r"(?P<amount>[+-]\d+) +egs \| "),
+    ("total", r"Total egs: (?P<total>-?\d+) 🥚"),
+];
+
+// https://regex101.com/r/g4FpOL/1/
+const CLAIM_BAD_COMPONENTS: &[Component] = &[
+    ("username", r"@(?P<username>\w+) "),
+    ("prefix", r"nam1Sadeg no eg. come back in "),
+    ("hours", r"((?P<hours>\d+) hours?, )?"),
+    ("minutes", r"(?P<minutes>\d+) minutes?,?"),
+    ("seconds", r"( (?P<seconds>\d+) seconds?)? "),
+    ("total", r"Total egs: (?P<total>\d+)"),
+];
+
 lazy_static! {
-    // https://regex101.com/r/6gc79V/4
     #[derive(Debug)]
-    pub static ref CLAIM_GOOD: Regex = Regex::new(r#"@(?P<username>\w+) \| [^\|]* \| (?P<amount>[+-]\d+) +egs \| Total egs: (?P<total>-?\d+) 🥚"#).unwrap();
+    pub static ref CLAIM_GOOD: Regex = diagnostics::build(CLAIM_GOOD_COMPONENTS);
 
-    // https://regex101.com/r/g4FpOL/1/
     #[derive(Debug)]
-    pub static ref CLAIM_BAD: Regex = Regex::new(r#"@(?P<username>\w+) nam1Sadeg no eg. come back in (?P<minutes>\d+) minutes?,( (?P<seconds>\d+) seconds?)? Total egs: (?P<total>\d+)"#).unwrap();
+    pub static ref CLAIM_BAD: Regex = diagnostics::build(CLAIM_BAD_COMPONENTS);
 
-    // https://regex101.com/r/GaJODf/1/
+    /// Any answer to a claim, so replies to commands others ran on us are
+    /// skipped
     #[derive(Debug)]
-    pub static ref GENERIC_ANSWER: Regex = Regex::new(r#"@(?P<username>\w+) .*"#).unwrap();
+    pub static ref GENERIC_ANSWER: Regex = Regex::new(r"^@(?P<username>\w+) (\| .* \| [+-]\d+ +egs \||nam1Sadeg no eg\.)").unwrap();
+}
+
+/// Reports which component of the claim patterns failed to match `message`.
+pub fn diagnose(message: &str) -> Option<Diagnosis> {
+    diagnostics::closest(vec![
+        diagnostics::first_failing(CLAIM_GOOD_NAME, CLAIM_GOOD_COMPONENTS, message),
+        diagnostics::first_failing(CLAIM_BAD_NAME, CLAIM_BAD_COMPONENTS, message),
+    ])
 }
 
 #[cfg(test)]
@@ -172,11 +202,51 @@ mod test {
     }
 
     #[test]
-    fn generic_answer1() {
-        let tests = [(
+    fn claim_bad_with_hours() {
+        let text = "@chronophylos nam1Sadeg no eg. come back in 1 hour, 3 minutes Total egs: 30";
+        let captures = CLAIM_BAD.captures(text).expect("regex should match");
+
+        assert_eq!(captures.name("hours").unwrap().as_str(), "1", "wrong hours");
+        assert_eq!(
+            captures.name("minutes").unwrap().as_str(),
+            "3",
+            "wrong minutes"
+        );
+        assert!(
+            captures.name("seconds").is_none(),
+            "seconds should not exist"
+        );
+        assert_eq!(
+            captures.name("total").unwrap().as_str(),
+            "30",
+            "wrong total eg amount"
+        );
+    }
+
+    #[test]
+    fn claim_bad_without_hours() {
+        for text in &[
+            "@chronophylos nam1Sadeg no eg. come back in 56 minutes, 42 seconds Total egs: 30",
             "@chronophylos nam1Sadeg no eg. come back in 50 minutes, Total egs: 30",
-            "chronophylos",
-        )];
+        ] {
+            let captures = CLAIM_BAD.captures(text).expect("regex should match");
+
+            assert!(captures.name("hours").is_none(), "hours should not exist");
+        }
+    }
+
+    #[test]
+    fn generic_answer1() {
+        let tests = [
+            (
+                "@chronophylos nam1Sadeg no eg. come back in 50 minutes, Total egs: 30",
+                "chronophylos",
+            ),
+            (
+                "@chronophylos | is this a YOLK? nam1Okayeg | +1  egs | Total egs: 92 🥚 ",
+                "chronophylos",
+            ),
+        ];
 
         for (text, username) in tests.iter() {
             let captures = GENERIC_ANSWER.captures(text).expect("regex should match");
@@ -188,4 +258,61 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn generic_answer_skips_other_replies() {
+        let tests = [
+            "@chronophylos someone gives you a big hug nam1Okayeg",
+            "@chronophylos you have 92 egs 🥚",
+            "someone -> @chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total egs: 92 🥚",
+        ];
+
+        for text in tests.iter() {
+            assert!(!GENERIC_ANSWER.is_match(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn diagnose_mutated_claims() {
+        let tests = [
+            (
+                "chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total egs: 92 🥚",
+                "username",
+            ),
+            (
+                "@chronophylos | is this a YOLK? nam1Okayeg | 1 egs | Total egs: 92 🥚",
+                "amount",
+            ),
+            (
+                "@chronophylos | is this a YOLK? nam1Okayeg | +1 egs | Total eggs: 92 🥚",
+                "total",
+            ),
+            (
+                "@chronophylos nam1Sadeg no egs. come back in 56 minutes, 42 seconds Total egs: 30",
+                "prefix",
+            ),
+            (
+                "@chronophylos nam1Sadeg no eg. come back in 56 mins, 42 seconds Total egs: 30",
+                "minutes",
+            ),
+            (
+                "@chronophylos nam1Sadeg no eg. come back in 56 minutes, 42 seconds Total eggs: 30",
+                "total",
+            ),
+        ];
+
+        for (text, component) in tests.iter() {
+            let diagnosis = diagnose(text).expect("patterns should not match");
+
+            assert_eq!(diagnosis.component, *component, "{}", text);
+        }
+    }
+
+    #[test]
+    fn diagnose_matching_claim() {
+        assert!(
+            diagnose("@chronophylos nam1Sadeg no eg. come back in 50 minutes, Total egs: 30")
+                .is_none()
+        );
+    }
 }
@@ -0,0 +1,184 @@
+use std::{
+    fmt::{self, Display},
+    future::Future,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::select;
+pub use tokio_util::sync::CancellationToken;
+
+use crate::Timestamp;
+
+/// Error returned by the single shot operations of the bots
+#[derive(Debug, thiserror::Error)]
+pub enum OnceError<E>
+where
+    E: Display,
+{
+    #[error("Operation was cancelled")]
+    Cancelled,
+
+    #[error("Operation timed out after {0}")]
+    TimedOut(String),
+
+    #[error("{0}")]
+    Failed(E),
+}
+
+/// Result of a single claim, whatever the bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ClaimOutcome {
+    Claimed {
+        amount: i64,
+        total: i64,
+    },
+
+    /// The target bot is on cooldown, for `remaining_secs` if it said so
+    Cooldown {
+        remaining_secs: Option<u64>,
+        total: Option<i64>,
+    },
+}
+
+impl ClaimOutcome {
+    /// Cooldown of `remaining` reported by an API instead of the target bot
+    pub const fn cooldown(remaining: Duration) -> Self {
+        Self::Cooldown {
+            remaining_secs: Some(remaining.as_secs()),
+            total: None,
+        }
+    }
+
+    pub const fn is_claimed(&self) -> bool {
+        matches!(self, Self::Claimed { .. })
+    }
+}
+
+impl Display for ClaimOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Claimed { amount, total } => write!(f, "Claimed {}, {} in total", amount, total),
+            Self::Cooldown {
+                remaining_secs,
+                total,
+            } => {
+                write!(f, "On cooldown")?;
+                if let Some(secs) = remaining_secs {
+                    write!(f, " for {}", Duration::from_secs(*secs).as_readable())?;
+                }
+                if let Some(total) = total {
+                    write!(f, ", {} in total", total)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Runs `operation` until it completes, `timeout` elapses or `cancel` is
+/// cancelled, whichever happens first.
+pub async fn run_once<F, T, E>(
+    operation: F,
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+) -> Result<T, OnceError<E>>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    let operation = async {
+        match timeout {
+            Some(duration) => tokio::time::timeout(duration, operation)
+                .await
+                .map_err(|_elapsed| OnceError::TimedOut(duration.as_readable()))?,
+            None => operation.await,
+        }
+        .map_err(OnceError::Failed)
+    };
+
+    select! {
+        biased;
+
+        _ = cancel.cancelled() => Err(OnceError::Cancelled),
+        result = operation => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn passes_result_through() {
+        let result: Result<u32, OnceError<String>> =
+            run_once(async { Ok(7) }, None, &CancellationToken::new()).await;
+
+        assert_eq!(result.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn wraps_failure() {
+        let result: Result<(), _> = run_once(
+            async { Err("no".to_string()) },
+            None,
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(OnceError::Failed(err)) if err == "no"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_mid_operation() {
+        let cancel = CancellationToken::new();
+        let child = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            child.cancel();
+        });
+
+        let result: Result<(), OnceError<String>> = run_once(pending(), None, &cancel).await;
+
+        assert!(matches!(result, Err(OnceError::Cancelled)));
+    }
+
+    #[test]
+    fn prints_outcome() {
+        let claimed = ClaimOutcome::Claimed {
+            amount: 14,
+            total: 65,
+        };
+        let cooldown = ClaimOutcome::Cooldown {
+            remaining_secs: Some(90),
+            total: Some(65),
+        };
+
+        assert_eq!(claimed.to_string(), "Claimed 14, 65 in total");
+        assert_eq!(cooldown.to_string(), "On cooldown for 1m 30s, 65 in total");
+        assert_eq!(
+            serde_json::to_string(&claimed).unwrap(),
+            r#"{"outcome":"claimed","amount":14,"total":65}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&cooldown).unwrap(),
+            r#"{"outcome":"cooldown","remaining_secs":90,"total":65}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let result: Result<(), OnceError<String>> = run_once(
+            pending(),
+            Some(Duration::from_millis(10)),
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(OnceError::TimedOut(_))));
+    }
+}
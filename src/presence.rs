@@ -0,0 +1,93 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+use twitch_irc::message::ServerMessage;
+
+/// Remembers when the target bot last wrote in the channel
+#[derive(Debug, Default)]
+pub struct Presence {
+    last_seen: Mutex<Option<Instant>>,
+}
+
+impl Presence {
+    /// Records `message` if it was sent by `bot_id` in `channel`.
+    pub fn observe(&self, message: &ServerMessage, bot_id: &str, channel: &str) {
+        if let ServerMessage::Privmsg(msg) = message {
+            if msg.sender.id == bot_id && msg.channel_login == channel {
+                *self
+                    .last_seen
+                    .lock()
+                    .expect("presence should not be poisoned") = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Forgets when the target bot was last seen, e.g. after changing
+    /// channels.
+    pub fn forget(&self) {
+        *self
+            .last_seen
+            .lock()
+            .expect("presence should not be poisoned") = None;
+    }
+
+    /// Returns whether the target bot wrote within the last `window`.
+    pub fn seen_within(&self, window: Duration) -> bool {
+        self.last_seen
+            .lock()
+            .expect("presence should not be poisoned")
+            .is_some_and(|at| at.elapsed() < window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use twitch_irc::message::IRCMessage;
+
+    use super::*;
+
+    fn privmsg(user_id: &str, channel: &str) -> ServerMessage {
+        let raw = format!(
+            "@badge-info=;badges=;color=;display-name=ThePositiveBot;emotes=;id=1;room-id=1;tmi-sent-ts=1614600000000;user-id={} :thepositivebot!thepositivebot@thepositivebot.tmi.twitch.tv PRIVMSG #{} :[Cookies] someone -> Sugar cookie!",
+            user_id, channel
+        );
+
+        ServerMessage::try_from(IRCMessage::parse(&raw).unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn remembers_target_bot() {
+        let presence = Presence::default();
+        assert!(!presence.seen_within(Duration::from_secs(600)));
+
+        presence.observe(
+            &privmsg("425363834", "thepositivebot"),
+            "425363834",
+            "thepositivebot",
+        );
+        assert!(presence.seen_within(Duration::from_secs(600)));
+
+        tokio::time::advance(Duration::from_secs(600)).await;
+        assert!(!presence.seen_within(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn ignores_other_users_and_channels() {
+        let presence = Presence::default();
+
+        presence.observe(
+            &privmsg("54946241", "thepositivebot"),
+            "425363834",
+            "thepositivebot",
+        );
+        presence.observe(
+            &privmsg("425363834", "okayegbot"),
+            "425363834",
+            "thepositivebot",
+        );
+
+        assert!(!presence.seen_within(Duration::from_secs(600)));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::blackout::{deserialize_time, resolve};
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuietHoursError {
+    #[error("Quiet hours from {0} to {0} would never end")]
+    NeverEnd(String),
+}
+
+/// A daily window in which no bot claims
+///
+/// Windows where `to` is before `from` end on the next day. Times are local
+/// to `timezone` or the system timezone without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct QuietHours {
+    #[serde(deserialize_with = "deserialize_time")]
+    pub from: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub to: NaiveTime,
+    #[serde(default, deserialize_with = "deserialize_timezone")]
+    pub timezone: Option<Tz>,
+}
+
+impl Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {}",
+            self.from.format("%H:%M"),
+            self.to.format("%H:%M")
+        )?;
+
+        match self.timezone {
+            Some(timezone) => write!(f, " {}", timezone),
+            None => write!(f, " local time"),
+        }
+    }
+}
+
+impl QuietHours {
+    pub fn validate(&self) -> Result<(), QuietHoursError> {
+        if self.from == self.to {
+            return Err(QuietHoursError::NeverEnd(
+                self.from.format("%H:%M").to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns how long the quiet hours last from now on, or `None` outside
+    /// of them.
+    pub fn remaining(&self) -> Option<Duration> {
+        match self.timezone {
+            Some(timezone) => self.remaining_at(Utc::now().with_timezone(&timezone)),
+            None => self.remaining_at(Local::now()),
+        }
+    }
+
+    fn remaining_at<Z: TimeZone>(&self, now: DateTime<Z>) -> Option<Duration> {
+        let tz = now.timezone();
+        let today = now.naive_local().date();
+
+        // a window wrapping midnight that began yesterday may still last
+        [today.pred(), today]
+            .iter()
+            .map(|date| {
+                let end_date = if self.to > self.from {
+                    *date
+                } else {
+                    date.succ()
+                };
+
+                (
+                    resolve(&tz, date.and_time(self.from)),
+                    resolve(&tz, end_date.and_time(self.to)),
+                )
+            })
+            .find(|(start, end)| *start <= now && now < *end)
+            .and_then(|(_, end)| end.signed_duration_since(now).to_std().ok())
+    }
+}
+
+fn deserialize_timezone<'de, D>(deserializer: D) -> Result<Option<Tz>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    s.parse()
+        .map(Some)
+        .map_err(|_| de::Error::custom(format!("invalid timezone: {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn quiet_hours(from: &str, to: &str) -> QuietHours {
+        QuietHours {
+            from: NaiveTime::parse_from_str(from, "%H:%M").unwrap(),
+            to: NaiveTime::parse_from_str(to, "%H:%M").unwrap(),
+            timezone: None,
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn minutes(m: u64) -> Duration {
+        Duration::from_secs(m * 60)
+    }
+
+    #[test]
+    fn claims_outside_window() {
+        let quiet = quiet_hours("01:30", "08:00");
+
+        assert_eq!(quiet.remaining_at(at("2021-03-06T01:00:00Z")), None);
+        assert_eq!(quiet.remaining_at(at("2021-03-06T08:00:00Z")), None);
+    }
+
+    #[test]
+    fn waits_until_end_of_window() {
+        let quiet = quiet_hours("01:30", "08:00");
+
+        assert_eq!(
+            quiet.remaining_at(at("2021-03-06T01:30:00Z")),
+            Some(minutes(390))
+        );
+        assert_eq!(
+            quiet.remaining_at(at("2021-03-06T07:00:00Z")),
+            Some(minutes(60))
+        );
+    }
+
+    #[test]
+    fn window_wrapping_midnight() {
+        let quiet = quiet_hours("23:00", "06:00");
+
+        assert_eq!(
+            quiet.remaining_at(at("2021-03-06T23:30:00Z")),
+            Some(minutes(390))
+        );
+        assert_eq!(
+            quiet.remaining_at(at("2021-03-07T05:00:00Z")),
+            Some(minutes(60))
+        );
+        assert_eq!(quiet.remaining_at(at("2021-03-07T12:00:00Z")), None);
+    }
+
+    #[test]
+    fn window_in_timezone() {
+        let quiet = quiet_hours("01:30", "08:00");
+
+        // 01:00 UTC is 02:00 in Berlin
+        assert_eq!(
+            quiet.remaining_at(at("2021-03-06T01:00:00Z").with_timezone(&Berlin)),
+            Some(minutes(360))
+        );
+    }
+
+    #[test]
+    fn deserialize_with_timezone() {
+        let quiet: QuietHours =
+            ron::de::from_str(r#"(from: "01:30", to: "08:00", timezone: "Europe/Berlin")"#)
+                .unwrap();
+
+        assert_eq!(quiet.timezone, Some(Berlin));
+        assert_eq!(quiet.to_string(), "01:30 - 08:00 Europe/Berlin");
+        assert!(quiet_hours("08:00", "08:00").validate().is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Keeps the chat token fresh with a refresh token
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+use reqwest::StatusCode;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use tokio::{
+    select,
+    time::{sleep_until, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{
+    bot,
+    http::{self, RetryError},
+    once::CancellationToken,
+    secrettoken::{SharedToken, Token},
+    HttpOptions, SecretToken, Timestamp,
+};
+
+pub static TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+
+/// How long before it expires the token is refreshed
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Lifetime assumed if Twitch does not say how long a token lasts
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Delay before a failed refresh is tried again
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Credentials of the application the token was issued to
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshOptions {
+    pub client_id: String,
+    pub client_secret: SecretToken,
+
+    /// Refresh token to start with if `state_file` does not exist yet
+    pub refresh_token: SecretToken,
+
+    /// File the rotated refresh token is kept in
+    #[serde(default = "default_state_file")]
+    pub state_file: PathBuf,
+}
+
+fn default_state_file() -> PathBuf {
+    PathBuf::from("cookiebot.token")
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshResponse {
+    access_token: Token,
+    refresh_token: Token,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not build request client: {0}")]
+    BuildClient(#[source] bot::Error),
+
+    #[error("Could not send refresh request: {0}")]
+    SendRequest(#[source] RetryError),
+
+    #[error("Twitch rejected the refresh token: {0}")]
+    Rejected(#[source] RetryError),
+
+    #[error("Could not deserialize refresh response: {0}")]
+    DeserializeResponse(#[source] reqwest::Error),
+
+    #[error("Could not read refresh token from {0}: {1}")]
+    ReadState(PathBuf, #[source] io::Error),
+
+    #[error("Could not save refresh token to {0}: {1}")]
+    WriteState(PathBuf, #[source] io::Error),
+}
+
+impl Error {
+    /// Returns whether refreshing again cannot help.
+    pub const fn is_fatal(&self) -> bool {
+        matches!(self, Self::Rejected(_))
+    }
+}
+
+/// Refreshes the token all bots share before it expires
+#[derive(Debug)]
+pub struct TokenManager {
+    token: SharedToken,
+    options: RefreshOptions,
+    refresh_token: Mutex<SecretToken>,
+    client: http::Client,
+    url: String,
+}
+
+impl TokenManager {
+    pub fn new(
+        token: SharedToken,
+        options: RefreshOptions,
+        http: HttpOptions,
+    ) -> Result<Self, Error> {
+        Self::with_url(token, options, http, TOKEN_URL)
+    }
+
+    fn with_url<S>(
+        token: SharedToken,
+        options: RefreshOptions,
+        http: HttpOptions,
+        url: S,
+    ) -> Result<Self, Error>
+    where
+        S: Into<String>,
+    {
+        let client = bot::build_client(http, false).map_err(Error::BuildClient)?;
+
+        // the refresh token in the config is only valid until the first refresh
+        let refresh_token = match fs::read_to_string(&options.state_file) {
+            Ok(saved) => SecretToken::new(Token::new(saved)),
+            Err(err) if err.kind() == ErrorKind::NotFound => options.refresh_token.clone(),
+            Err(err) => return Err(Error::ReadState(options.state_file, err)),
+        };
+
+        token.manage();
+
+        Ok(Self {
+            token,
+            options,
+            refresh_token: Mutex::new(refresh_token),
+            client,
+            url: url.into(),
+        })
+    }
+
+    /// Swaps in a new token and saves the new refresh token.
+    ///
+    /// Returns how long the new token lasts.
+    pub async fn refresh(&self) -> Result<Duration, Error> {
+        let refresh_token = self
+            .refresh_token
+            .lock()
+            .expect("refresh token should not be poisoned")
+            .clone();
+        let request = self.client.post(&self.url).form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.expose_secret().as_str()),
+            ("client_id", self.options.client_id.as_str()),
+            (
+                "client_secret",
+                self.options.client_secret.expose_secret().as_str(),
+            ),
+        ]);
+
+        let response: RefreshResponse = match self.client.send(request).await {
+            Ok(response) => response.json().await.map_err(Error::DeserializeResponse)?,
+            Err(err)
+                if matches!(
+                    err.status(),
+                    Some(StatusCode::BAD_REQUEST) | Some(StatusCode::UNAUTHORIZED)
+                ) =>
+            {
+                return Err(Error::Rejected(err))
+            }
+            Err(err) => return Err(Error::SendRequest(err)),
+        };
+
+        self.save(&response.refresh_token)?;
+        *self
+            .refresh_token
+            .lock()
+            .expect("refresh token should not be poisoned") =
+            SecretToken::new(response.refresh_token);
+        self.token.set(SecretToken::new(response.access_token));
+
+        let lifetime = response
+            .expires_in
+            .map_or(DEFAULT_LIFETIME, Duration::from_secs);
+        info!("Refreshed token, it expires in {}", lifetime.as_readable());
+
+        Ok(lifetime)
+    }
+
+    fn save(&self, refresh_token: &Token) -> Result<(), Error> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options
+            .open(&self.options.state_file)
+            .and_then(|mut file| file.write_all(refresh_token.as_bytes()))
+            .map_err(|err| Error::WriteState(self.options.state_file.clone(), err))
+    }
+
+    /// Refreshes the token shortly before it expires, or when a bot reports
+    /// it rejected, until `cancel` is cancelled.
+    ///
+    /// `lifetime` is how long the current token lasts. Returns early if a
+    /// refresh is rejected, since trying again cannot help.
+    pub async fn run(&self, lifetime: Duration, cancel: &CancellationToken) -> Result<(), Error> {
+        let mut due = Instant::now() + lifetime.saturating_sub(REFRESH_MARGIN);
+
+        loop {
+            select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = sleep_until(due) => {}
+                _ = self.token.refresh_requested() => info!("Token was rejected, refreshing it"),
+            }
+
+            due = match self.refresh().await {
+                Ok(lifetime) => Instant::now() + lifetime.saturating_sub(REFRESH_MARGIN),
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => {
+                    warn!(
+                        "Could not refresh token: {}. Retrying in {}",
+                        err,
+                        RETRY_DELAY.as_readable()
+                    );
+                    Instant::now() + RETRY_DELAY
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_string_contains, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn options(name: &str) -> RefreshOptions {
+        let state_file = env::temp_dir().join(name);
+        let _ = fs::remove_file(&state_file);
+
+        RefreshOptions {
+            client_id: "client".to_string(),
+            client_secret: SecretToken::new(Token::new("secret")),
+            refresh_token: SecretToken::new(Token::new("refresh1")),
+            state_file,
+        }
+    }
+
+    #[tokio::test]
+    async fn swaps_token_and_saves_refresh_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("refresh_token=refresh1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "access2",
+                "refresh_token": "refresh2",
+                "expires_in": 14400,
+                "scope": ["chat:read", "chat:edit"],
+                "token_type": "bearer",
+            })))
+            .mount(&server)
+            .await;
+
+        let options = options("cookiebot-test-refresh");
+        let state_file = options.state_file.clone();
+        let token = SharedToken::new(SecretToken::new(Token::new("access1")));
+        let manager =
+            TokenManager::with_url(token.clone(), options, HttpOptions::default(), server.uri())
+                .unwrap();
+
+        let lifetime = manager.refresh().await.unwrap();
+        let saved = fs::read_to_string(&state_file).unwrap();
+        fs::remove_file(&state_file).unwrap();
+
+        assert_eq!(lifetime, Duration::from_secs(14400));
+        assert_eq!(token.get().expose_secret().as_str(), "access2");
+        assert_eq!(saved, "refresh2");
+        assert!(token.request_refresh());
+    }
+
+    #[tokio::test]
+    async fn rejected_refresh_token_is_fatal() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "status": 400,
+                "message": "Invalid refresh token",
+            })))
+            .mount(&server)
+            .await;
+
+        let manager = TokenManager::with_url(
+            SharedToken::new(SecretToken::new(Token::new("access1"))),
+            options("cookiebot-test-refresh-rejected"),
+            HttpOptions::default(),
+            server.uri(),
+        )
+        .unwrap();
+
+        let err = manager.refresh().await.unwrap_err();
+        assert!(err.is_fatal());
+    }
+}
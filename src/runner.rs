@@ -0,0 +1,194 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use metrics::{increment_counter, register_counter, Unit};
+use tokio::{
+    select,
+    time::{sleep, Instant},
+};
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    notify::{self, Event},
+    once::CancellationToken,
+    Error, Timestamp,
+};
+
+static METRIC_RESTARTS: &str = "cookiebot.restarts";
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// Returns how long to wait before the restart after `failures` consecutive
+/// failures.
+fn backoff(failures: u32) -> Duration {
+    INITIAL_BACKOFF
+        .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .map_or(MAX_BACKOFF, |duration| duration.min(MAX_BACKOFF))
+}
+
+/// Registers the restart metrics with the recorder.
+pub(crate) fn register_metrics() {
+    register_counter!(
+        METRIC_RESTARTS,
+        Unit::Count,
+        "number of times a bot was restarted after an error"
+    );
+}
+
+/// Error a bot stops with
+pub trait BotError: Display {
+    /// Returns whether restarting cannot help, so every bot should stop.
+    fn is_fatal(&self) -> bool;
+
+    /// Returns whether restarting the bot may help.
+    fn is_retryable(&self) -> bool {
+        !self.is_fatal()
+    }
+}
+
+impl BotError for Error {
+    fn is_fatal(&self) -> bool {
+        Error::is_fatal(self)
+    }
+
+    fn is_retryable(&self) -> bool {
+        Error::is_retryable(self)
+    }
+}
+
+/// Runs the bot of `account` and restarts it with exponential backoff
+/// whenever it fails.
+///
+/// Returns once the bot finishes without an error or `cancel` is cancelled.
+/// A fatal error cancels `cancel`, so the other bots stop too, and is
+/// returned. An error restarting cannot help stops only this bot.
+#[instrument(skip(cancel, run))]
+pub async fn supervise<F, Fut, E>(
+    name: &'static str,
+    account: &str,
+    cancel: &CancellationToken,
+    mut run: F,
+) -> Result<(), E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: BotError,
+{
+    let mut failures = 0;
+
+    loop {
+        let started = Instant::now();
+
+        let result = select! {
+            _ = cancel.cancelled() => return Ok(()),
+            result = run() => result,
+        };
+
+        match result {
+            Ok(()) => {
+                warn!("{} finished running", name);
+                return Ok(());
+            }
+            Err(err) if err.is_fatal() => {
+                error!("Stopping all bots after fatal error in {}: {}", name, err);
+                notify::send(Event::AuthFailed {
+                    bot: name,
+                    account: account.to_string(),
+                    error: err.to_string(),
+                });
+                cancel.cancel();
+                return Err(err);
+            }
+            Err(err) if !err.is_retryable() => {
+                error!("Stopping {} after error: {}", name, err);
+                notify::send(Event::Stopped {
+                    bot: name,
+                    account: account.to_string(),
+                    error: err.to_string(),
+                });
+                return Err(err);
+            }
+            Err(err) => error!("Error running {}: {}", name, err),
+        }
+
+        // a bot that ran for a while before failing starts over with a short backoff
+        if started.elapsed() > MAX_BACKOFF {
+            failures = 0;
+        }
+        failures += 1;
+
+        let duration = backoff(failures);
+        info!("Restarting {} in {}", name, duration.as_readable());
+        select! {
+            _ = cancel.cancelled() => return Ok(()),
+            _ = sleep(duration) => {}
+        }
+
+        increment_counter!(METRIC_RESTARTS, "bot" => name, "account" => account.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot;
+
+    #[test]
+    fn backoff_doubles_until_cap() {
+        assert_eq!(backoff(1), Duration::from_secs(10));
+        assert_eq!(backoff(2), Duration::from_secs(20));
+        assert_eq!(backoff(3), Duration::from_secs(40));
+        assert_eq!(backoff(7), Duration::from_secs(640));
+        assert_eq!(backoff(8), MAX_BACKOFF);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fatal_error_stops_everything() {
+        let cancel = CancellationToken::new();
+        let mut runs = 0;
+
+        let result = supervise("test", "chronophylos", &cancel, || {
+            runs += 1;
+            async { Err(Error::from(bot::Error::AuthenticateChat)) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(runs, 1);
+        assert!(cancel.is_cancelled());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_until_cancelled() {
+        let cancel = CancellationToken::new();
+        let mut runs = 0;
+
+        let result = supervise("test", "chronophylos", &cancel, || {
+            runs += 1;
+            if runs == 3 {
+                cancel.cancel();
+            }
+            async { Err(Error::from(bot::Error::ConnectionLost)) }
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn config_error_stops_only_this_bot() {
+        let cancel = CancellationToken::new();
+        let mut runs = 0;
+
+        let result = supervise("test", "chronophylos", &cancel, || {
+            runs += 1;
+            async { Err(Error::from(bot::Error::NoChattersSource)) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(runs, 1);
+        assert!(!cancel.is_cancelled());
+    }
+}
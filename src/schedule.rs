@@ -0,0 +1,206 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::Timestamp;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("Schedule interval {interval} is shorter than the claim cooldown of {cooldown}")]
+    IntervalTooShort { interval: String, cooldown: String },
+}
+
+/// When a bot should claim once its cooldown has expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Schedule {
+    /// Claim as soon as the cooldown expires
+    #[default]
+    Reactive,
+
+    /// Claim on the first slot after the cooldown expires
+    ///
+    /// Slots start every day at `anchor` (local time) and repeat every
+    /// `interval`.
+    Aligned {
+        #[serde(deserialize_with = "deserialize_time")]
+        anchor: NaiveTime,
+        #[serde(with = "humantime_serde")]
+        interval: Duration,
+    },
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reactive => write!(f, "reactive"),
+            Self::Aligned { anchor, interval } => write!(
+                f,
+                "aligned to {} every {}",
+                anchor.format("%H:%M"),
+                interval.as_readable()
+            ),
+        }
+    }
+}
+
+impl Schedule {
+    /// Rejects schedules that would try to claim before the cooldown expires.
+    pub fn validate(&self, cooldown: Duration) -> Result<(), ScheduleError> {
+        match self {
+            Self::Aligned { interval, .. } if *interval < cooldown => {
+                Err(ScheduleError::IntervalTooShort {
+                    interval: interval.as_readable(),
+                    cooldown: cooldown.as_readable(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns how long to wait before claiming when the cooldown expires in
+    /// `cooldown`.
+    pub fn wait_time(&self, cooldown: Duration) -> Duration {
+        self.wait_time_at(Local::now(), cooldown)
+    }
+
+    fn wait_time_at<Tz: TimeZone>(&self, now: DateTime<Tz>, cooldown: Duration) -> Duration {
+        match self {
+            Self::Reactive => cooldown,
+            Self::Aligned { anchor, interval } => {
+                let earliest = now.clone() + to_chrono(cooldown);
+
+                next_slot(*anchor, *interval, earliest)
+                    .signed_duration_since(now)
+                    .to_std()
+                    .unwrap_or(cooldown)
+            }
+        }
+    }
+}
+
+/// Returns the first slot at or after `earliest`.
+fn next_slot<Tz: TimeZone>(
+    anchor: NaiveTime,
+    interval: Duration,
+    earliest: DateTime<Tz>,
+) -> DateTime<Tz> {
+    let tz = earliest.timezone();
+    let anchor_on = |date: chrono::NaiveDate| {
+        let naive = date.and_time(anchor);
+        tz.from_local_datetime(&naive)
+            .earliest()
+            // the anchor does not exist on this day because of a DST gap
+            .unwrap_or_else(|| tz.from_utc_datetime(&naive))
+    };
+
+    let date = earliest.naive_local().date();
+    let mut base = anchor_on(date);
+    if base > earliest {
+        base = anchor_on(date.pred());
+    }
+    let next_base = anchor_on(base.naive_local().date().succ());
+
+    let interval = to_chrono(interval);
+    let mut slot = base;
+    while slot < earliest {
+        slot = slot + interval;
+    }
+
+    slot.min(next_base)
+}
+
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).expect("duration should fit")
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    NaiveTime::parse_from_str(&s, "%H:%M").map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn aligned(anchor: &str, interval: &str) -> Schedule {
+        Schedule::Aligned {
+            anchor: NaiveTime::parse_from_str(anchor, "%H:%M").unwrap(),
+            interval: humantime::parse_duration(interval).unwrap(),
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn reactive_waits_for_cooldown() {
+        let wait =
+            Schedule::Reactive.wait_time_at(at("2021-03-01T10:00:00Z"), Duration::from_secs(90));
+
+        assert_eq!(wait, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn aligned_waits_for_next_slot() {
+        let schedule = aligned("00:05", "2h");
+
+        // cooldown expires at 11:30, next slot is 12:05
+        let wait = schedule.wait_time_at(at("2021-03-01T10:00:00Z"), Duration::from_secs(90 * 60));
+
+        assert_eq!(wait, Duration::from_secs(125 * 60));
+    }
+
+    #[test]
+    fn aligned_claims_on_slot() {
+        let schedule = aligned("00:05", "2h");
+
+        let wait = schedule.wait_time_at(at("2021-03-01T10:05:00Z"), Duration::from_secs(0));
+
+        assert_eq!(wait, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn aligned_restarts_at_anchor_each_day() {
+        let schedule = aligned("00:05", "5h");
+
+        // slots are 20:05 and 00:05 the next day, not 01:05
+        let wait = schedule.wait_time_at(at("2021-03-01T22:00:00Z"), Duration::from_secs(0));
+
+        assert_eq!(wait, Duration::from_secs(2 * 3600 + 5 * 60));
+    }
+
+    #[test]
+    fn aligned_before_anchor() {
+        let schedule = aligned("06:00", "2h");
+
+        // the last anchor was yesterday at 06:00, so slots are at even hours
+        let wait = schedule.wait_time_at(at("2021-03-01T03:00:00Z"), Duration::from_secs(0));
+
+        assert_eq!(wait, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn rejects_short_interval() {
+        let cooldown = Duration::from_secs(2 * 3600);
+
+        assert!(aligned("00:05", "1h").validate(cooldown).is_err());
+        assert!(aligned("00:05", "2h").validate(cooldown).is_ok());
+        assert!(Schedule::Reactive.validate(cooldown).is_ok());
+    }
+
+    #[test]
+    fn deserialize_aligned() {
+        let schedule: Schedule =
+            ron::de::from_str(r#"Aligned(anchor: "00:05", interval: "2h")"#).unwrap();
+
+        assert_eq!(schedule, aligned("00:05", "2h"));
+    }
+}
@@ -1,17 +1,57 @@
-use secrecy::{CloneableSecret, DebugSecret, Secret, SerializableSecret};
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use reqwest::{header::AUTHORIZATION, StatusCode};
+use secrecy::{CloneableSecret, DebugSecret, ExposeSecret, Secret, SerializableSecret};
 use serde::{Deserialize, Serialize};
-use std::ops::Deref;
+use tokio::sync::Notify;
+use tracing::debug;
 use zeroize::Zeroize;
 
+use crate::{
+    bot,
+    http::{self, RetryError},
+    HttpOptions,
+};
+
+static OAUTH_PREFIX: &str = "oauth:";
+
 #[derive(Debug, Clone, Zeroize, Deserialize, Serialize)]
+#[serde(from = "RawToken")]
 pub struct Token(String);
 
+/// Token as written in the config
+#[derive(Deserialize)]
+struct RawToken(String);
+
 impl Token {
+    /// Creates a token, stripping surrounding whitespace and the `oauth:`
+    /// prefix some tools add.
     pub fn new<S>(s: S) -> Self
     where
         S: ToString,
     {
-        Self(s.to_string())
+        let s = s.to_string();
+        let trimmed = s.trim();
+
+        match trimmed.strip_prefix(OAUTH_PREFIX) {
+            Some(raw) => {
+                debug!("Token has the {} prefix, removing it", OAUTH_PREFIX);
+                Self(raw.to_string())
+            }
+            None => Self(trimmed.to_string()),
+        }
+    }
+}
+
+impl From<RawToken> for Token {
+    fn from(RawToken(s): RawToken) -> Self {
+        Self::new(s)
     }
 }
 
@@ -34,3 +74,232 @@ impl CloneableSecret for Token {}
 impl SerializableSecret for Token {}
 
 pub type SecretToken = Secret<Token>;
+
+/// Token shared by all bots, swapped when it is refreshed
+#[derive(Debug, Clone)]
+pub struct SharedToken {
+    token: Arc<RwLock<SecretToken>>,
+
+    /// Whether a `TokenManager` refreshes the token
+    managed: Arc<AtomicBool>,
+    refresh: Arc<Notify>,
+}
+
+impl SharedToken {
+    pub fn new(token: SecretToken) -> Self {
+        Self {
+            token: Arc::new(RwLock::new(token)),
+            managed: Arc::default(),
+            refresh: Arc::default(),
+        }
+    }
+
+    /// Returns the current token.
+    pub fn get(&self) -> SecretToken {
+        self.token
+            .read()
+            .expect("token should not be poisoned")
+            .clone()
+    }
+
+    pub(crate) fn set(&self, token: SecretToken) {
+        *self.token.write().expect("token should not be poisoned") = token;
+    }
+
+    pub(crate) fn manage(&self) {
+        self.managed.store(true, Ordering::SeqCst);
+    }
+
+    /// Asks for a new token after the current one was rejected.
+    ///
+    /// Returns whether the token is refreshed automatically.
+    pub fn request_refresh(&self) -> bool {
+        let managed = self.managed.load(Ordering::SeqCst);
+        if managed {
+            self.refresh.notify_one();
+        }
+
+        managed
+    }
+
+    pub(crate) async fn refresh_requested(&self) {
+        self.refresh.notified().await
+    }
+}
+
+impl From<SecretToken> for SharedToken {
+    fn from(token: SecretToken) -> Self {
+        Self::new(token)
+    }
+}
+
+pub static VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+/// What Twitch knows about a token
+#[derive(Debug, Deserialize)]
+pub struct TokenInfo {
+    pub client_id: String,
+    pub login: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+
+    /// Seconds until the token expires, zero if it does not expire
+    pub expires_in: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ValidateError {
+    #[error("Could not build request client: {0}")]
+    BuildClient(#[source] bot::Error),
+
+    #[error("Could not send validate request: {0}")]
+    SendRequest(#[source] RetryError),
+
+    #[error("Token is invalid or expired")]
+    Invalid,
+
+    #[error("Could not deserialize token info: {0}")]
+    DeserializeResponse(#[source] reqwest::Error),
+
+    #[error("Token belongs to {actual}, not {expected}")]
+    WrongAccount { expected: String, actual: String },
+}
+
+/// Checks with Twitch that `token` is valid and belongs to `username`.
+pub async fn validate(
+    token: &SecretToken,
+    username: &str,
+    options: HttpOptions,
+) -> Result<TokenInfo, ValidateError> {
+    let client = bot::build_client(options, false).map_err(ValidateError::BuildClient)?;
+
+    validate_at(&client, VALIDATE_URL, token, username).await
+}
+
+async fn validate_at(
+    client: &http::Client,
+    url: &str,
+    token: &SecretToken,
+    username: &str,
+) -> Result<TokenInfo, ValidateError> {
+    let request = client.get(url).header(
+        AUTHORIZATION,
+        format!("OAuth {}", token.expose_secret().as_str()),
+    );
+
+    let info: TokenInfo = match client.send(request).await {
+        Ok(response) => response
+            .json()
+            .await
+            .map_err(ValidateError::DeserializeResponse)?,
+        Err(err) if err.status() == Some(StatusCode::UNAUTHORIZED) => {
+            return Err(ValidateError::Invalid)
+        }
+        Err(err) => return Err(ValidateError::SendRequest(err)),
+    };
+
+    if !info.login.eq_ignore_ascii_case(username) {
+        return Err(ValidateError::WrongAccount {
+            expected: username.to_string(),
+            actual: info.login,
+        });
+    }
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    async fn validate_with(
+        status: u16,
+        body: serde_json::Value,
+    ) -> Result<TokenInfo, ValidateError> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("Authorization", "OAuth token"))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body))
+            .mount(&server)
+            .await;
+
+        validate_at(
+            &http::Client::default(),
+            &server.uri(),
+            &SecretToken::new(Token::new("token")),
+            "Chronophylos",
+        )
+        .await
+    }
+
+    #[test]
+    fn strips_oauth_prefix() {
+        assert_eq!(
+            *Token::new("oauth:2kjhlsdhf27hlkajhsd2"),
+            "2kjhlsdhf27hlkajhsd2"
+        );
+        assert_eq!(
+            *Token::new(" 2kjhlsdhf27hlkajhsd2\n"),
+            "2kjhlsdhf27hlkajhsd2"
+        );
+
+        let token: Token = ron::de::from_str("(\"oauth:2kjhlsdhf27hlkajhsd2\")").unwrap();
+        assert_eq!(*token, "2kjhlsdhf27hlkajhsd2");
+    }
+
+    #[tokio::test]
+    async fn accepts_own_token() {
+        let info = validate_with(
+            200,
+            json!({
+                "client_id": "client",
+                "login": "chronophylos",
+                "scopes": ["chat:read", "chat:edit"],
+                "user_id": "54946241",
+                "expires_in": 5520838,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(info.user_id, "54946241");
+        assert_eq!(info.scopes, ["chat:read", "chat:edit"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_token() {
+        let result = validate_with(
+            401,
+            json!({ "status": 401, "message": "invalid access token" }),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ValidateError::Invalid)));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_account() {
+        let result = validate_with(
+            200,
+            json!({
+                "client_id": "client",
+                "login": "someone",
+                "scopes": [],
+                "user_id": "1",
+                "expires_in": 0,
+            }),
+        )
+        .await;
+
+        assert!(
+            matches!(result, Err(ValidateError::WrongAccount { actual, .. }) if actual == "someone")
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use rand::Rng;
+use serde::Deserialize;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum SkipProbabilityError {
+    #[error("Skip probability {0} is not between 0 and 1")]
+    OutOfRange(f64),
+}
+
+/// Chance that a bot skips a claim on purpose and waits another cooldown
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(transparent)]
+pub struct SkipProbability(f64);
+
+impl From<f64> for SkipProbability {
+    fn from(probability: f64) -> Self {
+        Self(probability)
+    }
+}
+
+impl SkipProbability {
+    pub fn validate(&self) -> Result<(), SkipProbabilityError> {
+        if !(0.0..=1.0).contains(&self.0) {
+            return Err(SkipProbabilityError::OutOfRange(self.0));
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether to skip the next claim.
+    pub fn should_skip(&self) -> bool {
+        self.should_skip_with(&mut rand::thread_rng())
+    }
+
+    /// Returns whether to skip the next claim, deciding with `rng`.
+    pub fn should_skip_with<R: Rng>(&self, rng: &mut R) -> bool {
+        let skip = self.0 > 0.0 && rng.gen_bool(self.0.min(1.0));
+        if skip {
+            info!("Skipping this claim on purpose");
+        }
+
+        skip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn never_skips_by_default() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert!((0..1000).all(|_| !SkipProbability::default().should_skip_with(&mut rng)));
+    }
+
+    #[test]
+    fn skips_at_configured_rate() {
+        let probability: SkipProbability = ron::de::from_str("0.05").unwrap();
+        let mut rng = StdRng::seed_from_u64(828);
+
+        let skipped = (0..10_000)
+            .filter(|_| probability.should_skip_with(&mut rng))
+            .count();
+
+        assert!((400..600).contains(&skipped), "skipped {}", skipped);
+    }
+
+    #[test]
+    fn rejects_probability_above_one() {
+        assert_eq!(
+            SkipProbability::from(1.5)
+                .validate()
+                .unwrap_err()
+                .to_string(),
+            "Skip probability 1.5 is not between 0 and 1"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! What is known about the bots without running them, as JSON or in the
+//! Prometheus text format for the textfile collector of node_exporter
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{board::BotStatus, state::Saved, Status};
+
+/// Labels of the bots that save a state, known even if compiled out
+static BOTS: &[&str] = &["thepositivebot", "okayegbot", "leavesbot"];
+
+/// States of the bots and, if they were read, the balances of the accounts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+
+    /// States in the shape of the status endpoint, from the state file
+    pub bots: Vec<BotStatus>,
+
+    /// Balances and cooldowns read from the APIs of the target bots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live: Option<Vec<Status>>,
+}
+
+impl Snapshot {
+    /// Builds the snapshot from the `saved` states of the state file, by bot
+    /// label and account, marking the bots of `enabled` as enabled.
+    ///
+    /// Enabled bots that saved nothing yet are included as well.
+    pub fn new(
+        taken_at: DateTime<Utc>,
+        saved: Vec<(String, String, Saved)>,
+        enabled: &[(&'static str, String)],
+    ) -> Self {
+        let mut bots: Vec<BotStatus> = saved
+            .into_iter()
+            .filter_map(|(bot, account, saved)| {
+                let bot = BOTS.iter().find(|known| **known == bot)?;
+                Some(BotStatus::restored(bot, &account, saved))
+            })
+            .collect();
+
+        for (bot, account) in enabled {
+            let account = account.to_lowercase();
+            match bots
+                .iter_mut()
+                .find(|status| status.bot == *bot && status.account == account)
+            {
+                Some(status) => status.enabled = true,
+                None => bots.push(BotStatus {
+                    enabled: true,
+                    ..BotStatus::restored(bot, &account, Saved::default())
+                }),
+            }
+        }
+        bots.sort_by(|a, b| (a.bot, &a.account).cmp(&(b.bot, &b.account)));
+
+        Self {
+            taken_at,
+            bots,
+            live: None,
+        }
+    }
+
+    /// Adds the balances and cooldowns read from the APIs.
+    pub fn with_live(mut self, statuses: Vec<Status>) -> Self {
+        self.live = Some(statuses);
+        self
+    }
+
+    /// Returns the snapshot in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut exposition = Exposition::default();
+
+        exposition.family(
+            "cookiebot_snapshot_timestamp_seconds",
+            "When the snapshot was taken, as a Unix timestamp",
+            vec![(vec![], self.taken_at.timestamp() as f64)],
+        );
+        exposition.family(
+            "cookiebot_enabled",
+            "Whether the config enables the bot",
+            self.bots
+                .iter()
+                .map(|status| (labels(status), if status.enabled { 1. } else { 0. }))
+                .collect(),
+        );
+        exposition.family(
+            "cookiebot_total",
+            "Total the target bot reported on the last claim",
+            self.bots
+                .iter()
+                .filter_map(|status| Some((labels(status), status.total? as f64)))
+                .collect(),
+        );
+        exposition.family(
+            "cookiebot_next_claim_timestamp_seconds",
+            "When the bot may claim next, as a Unix timestamp",
+            self.bots
+                .iter()
+                .filter_map(|status| Some((labels(status), status.next_claim?.timestamp() as f64)))
+                .collect(),
+        );
+        exposition.family(
+            "cookiebot_cooldown_seconds",
+            "Seconds left of the saved cooldown when the snapshot was taken",
+            self.bots
+                .iter()
+                .map(|status| {
+                    let remaining = Saved {
+                        next_claim: status.next_claim,
+                        total: status.total,
+                    }
+                    .remaining_at(self.taken_at)
+                    .unwrap_or_default();
+
+                    (labels(status), remaining.as_secs() as f64)
+                })
+                .collect(),
+        );
+
+        if let Some(statuses) = &self.live {
+            live(&mut exposition, statuses);
+        }
+
+        exposition.out
+    }
+}
+
+fn labels(status: &BotStatus) -> Vec<(&str, &str)> {
+    vec![("bot", status.bot), ("account", &status.account)]
+}
+
+/// Adds the balances and cooldowns read from the APIs to `exposition`.
+#[cfg_attr(
+    not(any(feature = "thepositivebot", feature = "okayegbot")),
+    allow(unused_variables)
+)]
+fn live(exposition: &mut Exposition, statuses: &[Status]) {
+    #[allow(unused_mut)]
+    let mut balances = Vec::new();
+    #[allow(unused_mut)]
+    let mut cooldowns = Vec::new();
+    #[allow(unused_mut)]
+    let mut up = Vec::new();
+
+    for status in statuses {
+        let account = status.username.as_str();
+
+        #[cfg(feature = "thepositivebot")]
+        {
+            let labels = vec![("bot", "thepositivebot"), ("account", account)];
+            match &status.cookies {
+                crate::status::Section::Read(cookies) => {
+                    balances.push((labels.clone(), cookies.cookies as f64));
+                    cooldowns.push((labels.clone(), cookies.cooldown_secs.unwrap_or(0) as f64));
+                    up.push((labels, 1.));
+                }
+                crate::status::Section::Failed { .. } => up.push((labels, 0.)),
+            }
+        }
+
+        #[cfg(feature = "okayegbot")]
+        {
+            let labels = vec![("bot", "okayegbot"), ("account", account)];
+            match &status.egs {
+                crate::status::Section::Read(egs) => {
+                    if let Some(egs) = egs {
+                        balances.push((labels.clone(), egs.egs as f64));
+                        cooldowns.push((labels.clone(), egs.cooldown_secs.unwrap_or(0) as f64));
+                    }
+                    up.push((labels, 1.));
+                }
+                crate::status::Section::Failed { .. } => up.push((labels, 0.)),
+            }
+        }
+    }
+
+    exposition.family(
+        "cookiebot_live_up",
+        "Whether the API of the target bot answered",
+        up,
+    );
+    exposition.family(
+        "cookiebot_live_total",
+        "Total the API of the target bot reported",
+        balances,
+    );
+    exposition.family(
+        "cookiebot_live_cooldown_seconds",
+        "Seconds until the API of the target bot allows a claim",
+        cooldowns,
+    );
+}
+
+/// Metrics in the Prometheus text exposition format
+#[derive(Debug, Default)]
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    /// Adds the gauge `name` with a sample per labels and value, leaving out
+    /// gauges without samples.
+    fn family(&mut self, name: &str, help: &str, samples: Vec<(Vec<(&str, &str)>, f64)>) {
+        if samples.is_empty() {
+            return;
+        }
+
+        // writing to a string never fails
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} gauge", name);
+        for (labels, value) in samples {
+            let _ = write!(self.out, "{}", name);
+            if !labels.is_empty() {
+                let labels: Vec<_> = labels
+                    .iter()
+                    .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                    .collect();
+                let _ = write!(self.out, "{{{}}}", labels.join(","));
+            }
+            let _ = writeln!(self.out, " {}", value);
+        }
+    }
+}
+
+/// Escapes a label value for the text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::new(
+            at("2021-03-06T11:30:00Z"),
+            vec![
+                (
+                    "thepositivebot".to_string(),
+                    "chronophylos".to_string(),
+                    Saved {
+                        next_claim: Some(at("2021-03-06T12:00:00Z")),
+                        total: Some(728),
+                    },
+                ),
+                (
+                    "okayegbot".to_string(),
+                    "chronophylos".to_string(),
+                    Saved {
+                        next_claim: Some(at("2021-03-06T11:00:00Z")),
+                        total: Some(92),
+                    },
+                ),
+                (
+                    "unknownbot".to_string(),
+                    "chronophylos".to_string(),
+                    Saved::default(),
+                ),
+            ],
+            &[
+                ("thepositivebot", "Chronophylos".to_string()),
+                ("leavesbot", "chronophylos".to_string()),
+            ],
+        )
+    }
+
+    #[test]
+    fn matches_golden_json() {
+        assert_eq!(
+            serde_json::to_string_pretty(&snapshot()).unwrap() + "\n",
+            include_str!("../tests/golden/snapshot.json")
+        );
+    }
+
+    #[test]
+    fn matches_golden_exposition() {
+        assert_eq!(
+            snapshot().to_prometheus(),
+            include_str!("../tests/golden/snapshot.prom")
+        );
+    }
+
+    #[cfg(all(feature = "thepositivebot", feature = "okayegbot"))]
+    #[test]
+    fn matches_golden_live_exposition() {
+        use crate::{status::Section, CookieStatus};
+
+        let snapshot =
+            Snapshot::new(at("2021-03-06T11:30:00Z"), vec![], &[]).with_live(vec![Status {
+                username: "chronophylos".to_string(),
+                cookies: Section::Read(CookieStatus {
+                    cookies: 728,
+                    rank: "default".to_string(),
+                    prestige: 1,
+                    cooldown_secs: Some(1800),
+                }),
+                egs: Section::Failed {
+                    error: "Request returned bad status code".to_string(),
+                },
+            }]);
+
+        assert_eq!(
+            snapshot.to_prometheus(),
+            include_str!("../tests/golden/snapshot-live.prom")
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
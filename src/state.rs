@@ -0,0 +1,263 @@
+//! Cooldowns and totals the bots keep across restarts
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+/// Saved states by bot label and account
+type Bots = BTreeMap<String, BTreeMap<String, Saved>>;
+
+lazy_static! {
+    /// State of the bots, if a state file was loaded
+    static ref STATE: Mutex<Option<State>> = Mutex::new(None);
+}
+
+/// What a bot of an account knew when it last ran
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Saved {
+    /// When the bot may claim next
+    pub next_claim: Option<DateTime<Utc>>,
+
+    /// Total the target bot reported on the last claim
+    pub total: Option<i64>,
+}
+
+impl Saved {
+    /// Returns how long to wait before claiming, or `None` if the saved
+    /// cooldown is over.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_at(Utc::now())
+    }
+
+    pub(crate) fn remaining_at(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.next_claim
+            .and_then(|next_claim| next_claim.signed_duration_since(now).to_std().ok())
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+    }
+}
+
+/// Returns the state file kept next to the config at `config_path`.
+pub fn default_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name("cookiebot.state.ron")
+}
+
+/// Saved states kept in a file
+///
+/// Changes are written by a thread of their own, so the bots never wait on
+/// the disk.
+#[derive(Debug)]
+pub struct State {
+    bots: Mutex<Bots>,
+    writer: Option<(Sender<Bots>, JoinHandle<()>)>,
+}
+
+impl State {
+    /// Reads the state file at `path` and saves every later change to it.
+    ///
+    /// A missing or unreadable file is treated as empty.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let bots = read(&path);
+        let (sender, receiver) = channel::<Bots>();
+
+        let thread = thread::Builder::new()
+            .name("state".to_string())
+            .spawn(move || {
+                while let Ok(bots) = receiver.recv() {
+                    // only the latest state is worth writing
+                    let bots = receiver.try_iter().last().unwrap_or(bots);
+
+                    if let Err(err) = write(&path, &bots) {
+                        warn!("Could not save state to {}: {}", path.display(), err);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            bots: Mutex::new(bots),
+            writer: Some((sender, thread)),
+        })
+    }
+
+    /// Returns what the bot with the label `bot` of `account` saved.
+    pub fn saved(&self, bot: &str, account: &str) -> Option<Saved> {
+        self.bots
+            .lock()
+            .expect("state should not be poisoned")
+            .get(bot)?
+            .get(&account.to_lowercase())
+            .copied()
+    }
+
+    /// Saves `saved` for the bot with the label `bot` of `account`.
+    pub fn save(&self, bot: &str, account: &str, saved: Saved) {
+        let mut bots = self.bots.lock().expect("state should not be poisoned");
+        let entry = bots
+            .entry(bot.to_string())
+            .or_default()
+            .entry(account.to_lowercase())
+            .or_default();
+        if *entry == saved {
+            return;
+        }
+        *entry = saved;
+
+        if let Some((writer, _)) = &self.writer {
+            // the thread only ends once the state is dropped
+            let _ = writer.send(bots.clone());
+        }
+        debug!("Saved state of {} for {}", bot, account);
+    }
+}
+
+impl Drop for State {
+    /// Waits for the last change to be written.
+    fn drop(&mut self) {
+        if let Some((writer, thread)) = self.writer.take() {
+            drop(writer);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Reads the state file at `path`, treating a missing or unreadable one as
+/// empty.
+fn read(path: &Path) -> Bots {
+    match fs::read_to_string(path) {
+        Ok(content) => ron::de::from_str(&content).unwrap_or_else(|err| {
+            warn!("Ignoring invalid state file {}: {}", path.display(), err);
+            Bots::new()
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Bots::new(),
+        Err(err) => {
+            warn!("Ignoring unreadable state file {}: {}", path.display(), err);
+            Bots::new()
+        }
+    }
+}
+
+/// Returns the bot label, account and saved state of every bot in the state
+/// file at `path`, without saving any later change.
+pub fn read_all(path: &Path) -> Vec<(String, String, Saved)> {
+    read(path)
+        .into_iter()
+        .flat_map(|(bot, accounts)| {
+            accounts
+                .into_iter()
+                .map(move |(account, saved)| (bot.clone(), account, saved))
+        })
+        .collect()
+}
+
+/// Reads the state file at `path` and saves every later change to it.
+///
+/// A missing or unreadable file is treated as empty.
+pub fn load(path: PathBuf) -> io::Result<()> {
+    let state = State::load(path.clone())?;
+    info!("Keeping state in {}", path.display());
+
+    *STATE.lock().expect("state should not be poisoned") = Some(state);
+
+    Ok(())
+}
+
+/// Waits for the last change to be written and stops saving.
+///
+/// The state is kept in a static, which is never dropped, so this has to be
+/// called before exiting.
+pub fn flush() {
+    let state = STATE.lock().expect("state should not be poisoned").take();
+
+    // outside the lock, as the writer might take a while
+    drop(state);
+}
+
+/// Returns what the bot with the label `bot` of `account` saved.
+pub fn saved(bot: &str, account: &str) -> Option<Saved> {
+    STATE
+        .lock()
+        .expect("state should not be poisoned")
+        .as_ref()
+        .and_then(|state| state.saved(bot, account))
+}
+
+/// Saves `saved` for the bot with the label `bot` of `account`, if a state
+/// file was loaded.
+pub fn save(bot: &str, account: &str, saved: Saved) {
+    if let Some(state) = STATE.lock().expect("state should not be poisoned").as_ref() {
+        state.save(bot, account, saved);
+    }
+}
+
+/// Writes `bots` to a temporary file first, so a crash never leaves half a
+/// state file behind.
+fn write(path: &Path, bots: &Bots) -> io::Result<()> {
+    let content = ron::ser::to_string_pretty(bots, Default::default())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let temporary = path.with_extension("tmp");
+
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn stale_cooldown_is_over() {
+        let saved = Saved {
+            next_claim: Some(at("2021-03-06T12:00:00Z")),
+            total: Some(728),
+        };
+
+        assert_eq!(
+            saved.remaining_at(at("2021-03-06T11:30:00Z")),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(saved.remaining_at(at("2021-03-06T12:00:00Z")), None);
+        assert_eq!(saved.remaining_at(at("2021-03-07T12:00:00Z")), None);
+    }
+
+    #[test]
+    fn keeps_state_across_loads() {
+        let path = env::temp_dir().join(format!("cookiebot-state-{}.ron", std::process::id()));
+        let saved = Saved {
+            next_claim: Some(at("2021-03-06T12:00:00Z")),
+            total: Some(728),
+        };
+
+        fs::write(&path, "(this is not a state file").unwrap();
+        let state = State::load(path.clone()).unwrap();
+        assert_eq!(state.saved("thepositivebot", "chronophylos"), None);
+
+        state.save("thepositivebot", "ChronoPhylos", saved);
+        // waits for the write
+        drop(state);
+        let loaded = State::load(path.clone())
+            .unwrap()
+            .saved("thepositivebot", "chronophylos");
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Some(saved));
+    }
+}
@@ -0,0 +1,160 @@
+//! Balances and cooldowns read from the public APIs of the target bots
+
+use std::fmt::{self, Display};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+#[cfg(feature = "okayegbot")]
+use crate::okayegbot::{EgStatus, Okayeg};
+#[cfg(feature = "thepositivebot")]
+use crate::thepositivebot::{CookieStatus, RoaringIron};
+use crate::{bot, Config};
+
+/// Part of a status or why it could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Section<T> {
+    Read(T),
+    Failed { error: String },
+}
+
+impl<T> Section<T> {
+    #[cfg(any(feature = "thepositivebot", feature = "okayegbot"))]
+    fn new<E>(result: Result<T, E>) -> Self
+    where
+        E: Display,
+    {
+        match result {
+            Ok(value) => Self::Read(value),
+            Err(err) => Self::Failed {
+                error: err.to_string(),
+            },
+        }
+    }
+}
+
+/// Balances and cooldowns of an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Status {
+    pub username: String,
+    #[cfg(feature = "thepositivebot")]
+    pub cookies: Section<CookieStatus>,
+
+    /// `None` if okayeg does not know the user yet
+    #[cfg(feature = "okayegbot")]
+    pub egs: Section<Option<EgStatus>>,
+}
+
+impl Status {
+    /// Reads the status of `username` from the APIs configured in `config`.
+    ///
+    /// An API that fails only fails its section.
+    #[cfg_attr(
+        not(any(feature = "thepositivebot", feature = "okayegbot")),
+        allow(unused_variables)
+    )]
+    pub async fn fetch(config: &Config, username: &str) -> Result<Self> {
+        let client =
+            bot::build_client(config.http.clone(), false).context("could not build client")?;
+
+        #[cfg(feature = "thepositivebot")]
+        let roaringiron = RoaringIron::with_bases(
+            &config.thepositivebot.user_api_base,
+            &config.thepositivebot.cooldown_api_base,
+        );
+        #[cfg(feature = "thepositivebot")]
+        let cookies = roaringiron.status(&client, username);
+        #[cfg(feature = "okayegbot")]
+        let okayeg = Okayeg::new(&config.okayegbot.api_base);
+        #[cfg(feature = "okayegbot")]
+        let egs = okayeg.status(&client, username, config.okayegbot.claim_cooldown);
+
+        #[cfg(all(feature = "thepositivebot", feature = "okayegbot"))]
+        let (cookies, egs) = tokio::join!(cookies, egs);
+        #[cfg(all(feature = "thepositivebot", not(feature = "okayegbot")))]
+        let cookies = cookies.await;
+        #[cfg(all(feature = "okayegbot", not(feature = "thepositivebot")))]
+        let egs = egs.await;
+
+        Ok(Self {
+            username: username.to_string(),
+            #[cfg(feature = "thepositivebot")]
+            cookies: Section::new(cookies),
+            #[cfg(feature = "okayegbot")]
+            egs: Section::new(egs),
+        })
+    }
+}
+
+#[cfg(any(feature = "thepositivebot", feature = "okayegbot"))]
+fn cooldown(secs: Option<u64>) -> String {
+    use std::time::Duration;
+
+    use crate::Timestamp;
+
+    match secs {
+        Some(secs) if secs > 0 => format!("ready in {}", Duration::from_secs(secs).as_readable()),
+        _ => "ready".to_string(),
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.username)?;
+
+        #[cfg(feature = "thepositivebot")]
+        match &self.cookies {
+            Section::Read(cookies) => write!(
+                f,
+                "\n  cookies: {} ({}, prestige {}), {}",
+                cookies.cookies,
+                cookies.rank,
+                cookies.prestige,
+                cooldown(cookies.cooldown_secs)
+            )?,
+            Section::Failed { error } => write!(f, "\n  cookies: {}", error)?,
+        }
+
+        #[cfg(feature = "okayegbot")]
+        match &self.egs {
+            Section::Read(Some(egs)) => {
+                write!(f, "\n  egs: {}, {}", egs.egs, cooldown(egs.cooldown_secs))?
+            }
+            Section::Read(None) => write!(f, "\n  egs: unknown to okayeg, ready")?,
+            Section::Failed { error } => write!(f, "\n  egs: {}", error)?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "thepositivebot", feature = "okayegbot"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_sections() {
+        let status = Status {
+            username: "chronophylos".to_string(),
+            cookies: Section::Read(CookieStatus {
+                cookies: 728,
+                rank: "default".to_string(),
+                prestige: 1,
+                cooldown_secs: Some(7037),
+            }),
+            egs: Section::Failed {
+                error: "Request returned bad status code".to_string(),
+            },
+        };
+
+        assert_eq!(
+            status.to_string(),
+            "chronophylos\n  cookies: 728 (default, prestige 1), ready in 1h 57m 17s\n  egs: Request returned bad status code"
+        );
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            r#"{"username":"chronophylos","cookies":{"cookies":728,"rank":"default","prestige":1,"cooldown_secs":7037},"egs":{"error":"Request returned bad status code"}}"#
+        );
+    }
+}
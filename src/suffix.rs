@@ -0,0 +1,172 @@
+use std::sync::Mutex;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+use tracing::trace;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum SuffixStrategyError {
+    #[error("Spaces suffix needs a max of at least 1")]
+    NoSpaces,
+    #[error("Custom suffix is empty")]
+    EmptySuffix,
+}
+
+/// Invisible characters Twitch keeps at the end of a message
+static INVISIBLE_SUFFIXES: &[char] = &['\u{E0000}', '\u{E0001}', '\u{E0020}', '\u{E007F}'];
+
+/// How a command is changed, so Twitch does not drop it as a duplicate of the
+/// one sent before
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub enum SuffixStrategy {
+    /// Alternates between an invisible character and no suffix
+    #[default]
+    Alternate,
+
+    /// Appends a random invisible character or nothing
+    Invisible,
+
+    /// Appends up to `max` spaces
+    Spaces { max: usize },
+
+    /// Alternates between the suffix and no suffix
+    Custom(String),
+}
+
+impl SuffixStrategy {
+    pub const fn validate(&self) -> Result<(), SuffixStrategyError> {
+        match self {
+            Self::Spaces { max: 0 } => Err(SuffixStrategyError::NoSpaces),
+            Self::Custom(suffix) if suffix.is_empty() => Err(SuffixStrategyError::EmptySuffix),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns every way `message` may be sent, the preferred first.
+    fn variants(&self, message: &str) -> Vec<String> {
+        match self {
+            Self::Alternate => vec![format!("{}\u{E0000}", message), message.to_string()],
+            Self::Invisible => std::iter::once(message.to_string())
+                .chain(
+                    INVISIBLE_SUFFIXES
+                        .iter()
+                        .map(|suffix| format!("{}{}", message, suffix)),
+                )
+                .collect(),
+            Self::Spaces { max } => (0..=*max)
+                .map(|spaces| format!("{}{}", message, " ".repeat(spaces)))
+                .collect(),
+            Self::Custom(suffix) => vec![format!("{}{}", message, suffix), message.to_string()],
+        }
+    }
+
+    /// Returns `message` changed so it differs from `previous`, drawing from
+    /// `rng` for the random strategies.
+    pub fn apply_with<R: Rng>(&self, message: &str, previous: Option<&str>, rng: &mut R) -> String {
+        let variants: Vec<_> = self
+            .variants(message)
+            .into_iter()
+            .filter(|variant| Some(variant.as_str()) != previous)
+            .collect();
+
+        let chosen = match self {
+            Self::Alternate | Self::Custom(_) => variants.first(),
+            Self::Invisible | Self::Spaces { .. } => variants.choose(rng),
+        };
+
+        chosen
+            .cloned()
+            // only a strategy that fails validation has no other variant
+            .unwrap_or_else(|| format!("{}\u{E0000}", message))
+    }
+}
+
+/// Strategy of a bot together with the last message it sent
+#[derive(Debug, Default)]
+pub struct AntiDuplicate {
+    strategy: SuffixStrategy,
+    last_sent: Mutex<Option<String>>,
+}
+
+impl AntiDuplicate {
+    pub const fn new(strategy: SuffixStrategy) -> Self {
+        Self {
+            strategy,
+            last_sent: Mutex::new(None),
+        }
+    }
+
+    /// Returns `message` as it should be sent next and remembers it.
+    pub fn next(&self, message: &str) -> String {
+        let mut last_sent = self
+            .last_sent
+            .lock()
+            .expect("last sent message should not be poisoned");
+
+        let variant =
+            self.strategy
+                .apply_with(message, last_sent.as_deref(), &mut rand::thread_rng());
+        trace!("Sending {:?} using {:?}", variant, self.strategy);
+        *last_sent = Some(variant.clone());
+
+        variant
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn sends(strategy: &SuffixStrategy, count: usize) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(830);
+        let mut previous: Option<String> = None;
+
+        (0..count)
+            .map(|_| {
+                let sent = strategy.apply_with("!cookie", previous.as_deref(), &mut rng);
+                previous = Some(sent.clone());
+                sent
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_sends_differ() {
+        let strategies = [
+            SuffixStrategy::Alternate,
+            SuffixStrategy::Invisible,
+            SuffixStrategy::Spaces { max: 1 },
+            SuffixStrategy::Custom(" :)".to_string()),
+        ];
+
+        for strategy in &strategies {
+            let sent = sends(strategy, 100);
+
+            assert!(
+                sent.windows(2).all(|pair| pair[0] != pair[1]),
+                "{:?} repeated a message",
+                strategy
+            );
+            assert!(sent.iter().all(|message| message.starts_with("!cookie")));
+        }
+    }
+
+    #[test]
+    fn alternate_starts_with_suffix() {
+        assert_eq!(
+            sends(&SuffixStrategy::Alternate, 3),
+            vec!["!cookie\u{E0000}", "!cookie", "!cookie\u{E0000}"]
+        );
+    }
+
+    #[test]
+    fn deserialize_strategies() {
+        let strategies: Vec<SuffixStrategy> =
+            ron::de::from_str(r#"[Alternate, Invisible, Spaces(max: 3), Custom(" :)")]"#).unwrap();
+
+        assert_eq!(strategies[2], SuffixStrategy::Spaces { max: 3 });
+        assert!(SuffixStrategy::Spaces { max: 0 }.validate().is_err());
+    }
+}
@@ -0,0 +1,366 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Deserializer, Serialize,
+};
+use tracing::{debug, instrument};
+
+use super::rank::Rank;
+use crate::http::{self, RetryError};
+
+pub static API_BASE: &str = "https://api.roaringiron.com";
+/// Format of the dates in profiles without the trailing zone name
+static PROFILE_DATE_FORMAT: &str = "%a %b %d %Y %H:%M:%S GMT%z";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not send request to api.roaringiron.com: {0}")]
+    SendRequest(#[source] RetryError),
+
+    #[error("api.roaringiron.com returned an error: {0}")]
+    Status(#[source] RetryError),
+
+    #[error("Could not deserialize json response: {0}")]
+    DeserializeResponse(#[source] reqwest::Error),
+}
+
+impl From<Error> for crate::Error {
+    fn from(err: Error) -> Self {
+        Self::Http(Box::new(err))
+    }
+}
+
+impl From<RetryError> for Error {
+    fn from(err: RetryError) -> Self {
+        if err.is_status() {
+            Self::Status(err)
+        } else {
+            Self::SendRequest(err)
+        }
+    }
+}
+
+// {
+//     "can_claim": false,
+//     "interval_formatted": "2 hours",
+//     "interval_unformatted": 7200,
+//     "seconds_left": 7037.756,
+//     "time_left_formatted": "1 hr, 57 mins, and 18 secs",
+//     "time_left_unformatted": "01:57:17"
+// }
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub struct CooldownResponse {
+    pub can_claim: bool,
+    pub seconds_left: f32,
+}
+
+impl CooldownResponse {
+    /// Returns the remaining cooldown or `None` if a claim is possible.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.can_claim {
+            None
+        } else {
+            Some(Duration::from_secs_f32(self.seconds_left))
+        }
+    }
+}
+
+/*
+{
+  "id": "25790355",
+  "username": "chronophylos",
+  "twitchID": "54946241",
+  "firstseen": "Sun Aug 02 2020 21:16:28 GMT+0000 (Coordinated Universal Time)",
+  "lastseen": "Thu Nov 12 2020 11:08:12 GMT+0000 (Coordinated Universal Time)",
+  "cookies": 728,
+  "rank": "default",
+  "prestige": 1,
+  "active": "false",
+  "cooldownreset_cooldown": "Thu Nov 12 2020 11:08:02 GMT+0000 (Coordinated Universal Time)",
+  "booster_cooldown": "none",
+  "tip_cooldown": "none"
+}
+*/
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserResponse {
+    pub cookies: u32,
+    pub rank: Rank,
+    pub prestige: u32,
+    /// When the booster can be bought again or `None` if it is available
+    #[serde(deserialize_with = "deserialize_profile_date")]
+    pub booster_cooldown: Option<DateTime<Utc>>,
+}
+
+/// Reads a date like `Thu Nov 12 2020 11:08:02 GMT+0000 (Coordinated
+/// Universal Time)`, treating `"none"` as no date.
+fn deserialize_profile_date<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    if s == "none" {
+        return Ok(None);
+    }
+
+    let date = s.split(" (").next().unwrap_or_default();
+
+    DateTime::parse_from_str(date, PROFILE_DATE_FORMAT)
+        .map(|date| Some(date.with_timezone(&Utc)))
+        .map_err(|err| de::Error::custom(format!("invalid date {:?}: {}", s, err)))
+}
+
+/// Cookies of a user and the time until they can claim again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CookieStatus {
+    pub cookies: u32,
+    pub rank: String,
+    pub prestige: u32,
+    pub cooldown_secs: Option<u64>,
+}
+
+/// Client for api.roaringiron.com
+#[derive(Debug)]
+pub struct RoaringIron {
+    user_base: String,
+    cooldown_base: String,
+}
+
+impl Default for RoaringIron {
+    fn default() -> Self {
+        Self::new(API_BASE)
+    }
+}
+
+impl RoaringIron {
+    pub fn new<S>(base_url: S) -> Self
+    where
+        S: Into<String>,
+    {
+        let base_url = base_url.into();
+
+        Self::with_bases(base_url.clone(), base_url)
+    }
+
+    /// Creates a client that asks different hosts for profiles and
+    /// cooldowns.
+    pub fn with_bases<U, C>(user_base: U, cooldown_base: C) -> Self
+    where
+        U: Into<String>,
+        C: Into<String>,
+    {
+        Self {
+            user_base: user_base.into(),
+            cooldown_base: cooldown_base.into(),
+        }
+    }
+
+    async fn get<T>(&self, client: &http::Client, url: String) -> Result<T, Error>
+    where
+        T: DeserializeOwned + std::fmt::Debug,
+    {
+        let response = client
+            .send(client.get(url))
+            .await?
+            .json()
+            .await
+            .map_err(Error::DeserializeResponse)?;
+
+        debug!("Got response from api.roaringiron.com: {:?}", response);
+
+        Ok(response)
+    }
+
+    #[instrument(skip(self, client))]
+    pub async fn user(&self, client: &http::Client, username: &str) -> Result<UserResponse, Error> {
+        self.get(client, format!("{}/user/{}", self.user_base, username))
+            .await
+    }
+
+    #[instrument(skip(self, client))]
+    pub async fn cooldown(
+        &self,
+        client: &http::Client,
+        username: &str,
+    ) -> Result<CooldownResponse, Error> {
+        self.get(
+            client,
+            format!("{}/cooldown/{}", self.cooldown_base, username),
+        )
+        .await
+    }
+
+    /// Returns the user profile together with the cookie cooldown.
+    ///
+    /// The cooldown cannot be derived from the profile, which only knows
+    /// when the user was last seen and the cooldowns of the shop, so both
+    /// are requested at the same time.
+    #[instrument(skip(self, client))]
+    pub async fn profile_with_cooldown(
+        &self,
+        client: &http::Client,
+        username: &str,
+    ) -> Result<(UserResponse, CooldownResponse), Error> {
+        let (user, cooldown) =
+            tokio::join!(self.user(client, username), self.cooldown(client, username));
+
+        Ok((user?, cooldown?))
+    }
+
+    /// Returns the cookies of `username` and their remaining cooldown.
+    pub async fn status(
+        &self,
+        client: &http::Client,
+        username: &str,
+    ) -> Result<CookieStatus, Error> {
+        let (user, cooldown) = self.profile_with_cooldown(client, username).await?;
+
+        Ok(CookieStatus {
+            cookies: user.cookies,
+            rank: user.rank.to_string(),
+            prestige: user.prestige,
+            cooldown_secs: cooldown.remaining().map(|remaining| remaining.as_secs()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::HttpOptions;
+
+    fn profile() -> serde_json::Value {
+        json!({
+            "cookies": 728,
+            "rank": "default",
+            "prestige": 1,
+            "booster_cooldown": "none",
+        })
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("<html>Bad Gateway</html>"))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = http::Client::new(
+            reqwest::Client::new(),
+            HttpOptions {
+                max_attempts: 2,
+                retry_delay: Duration::from_millis(10),
+                ..HttpOptions::default()
+            },
+        );
+        let err = RoaringIron::new(server.uri())
+            .user(&client, "chronophylos")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Status(RetryError { attempts: 2, .. })));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = RoaringIron::new(server.uri())
+            .user(&http::Client::default(), "chronophylos")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Status(RetryError { attempts: 1, .. })));
+    }
+
+    #[test]
+    fn deserialize_booster_cooldown() {
+        let mut body = profile();
+        body["booster_cooldown"] =
+            json!("Thu Nov 12 2020 11:08:02 GMT+0000 (Coordinated Universal Time)");
+
+        let user: UserResponse = serde_json::from_value(body).unwrap();
+
+        assert_eq!(
+            user.booster_cooldown,
+            Some(Utc.ymd(2020, 11, 12).and_hms(11, 8, 2))
+        );
+    }
+
+    #[test]
+    fn deserialize_available_booster() {
+        let user: UserResponse = serde_json::from_value(profile()).unwrap();
+
+        assert_eq!(user.booster_cooldown, None);
+    }
+
+    #[test]
+    fn deserialize_unknown_rank() {
+        let mut body = profile();
+        body["rank"] = json!("mythic");
+
+        let user: UserResponse = serde_json::from_value(body).unwrap();
+
+        assert_eq!(user.rank, Rank::Unknown("mythic".to_string()));
+    }
+
+    #[tokio::test]
+    async fn requests_profile_and_cooldown_concurrently() {
+        let delay = Duration::from_secs(2);
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(profile())
+                    .set_delay(delay),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({
+                        "can_claim": false,
+                        "seconds_left": 120.0,
+                    }))
+                    .set_delay(delay),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // one after the other would take twice the delay
+        let (user, cooldown) = tokio::time::timeout(
+            delay + delay / 2,
+            RoaringIron::new(server.uri())
+                .profile_with_cooldown(&http::Client::default(), "chronophylos"),
+        )
+        .await
+        .expect("requests should be sent at the same time")
+        .unwrap();
+
+        assert_eq!(user.cookies, 728);
+        assert_eq!(cooldown.remaining(), Some(Duration::from_secs(120)));
+    }
+}
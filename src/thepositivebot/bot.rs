@@ -1,149 +1,353 @@
-use std::{borrow::Cow, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
-use metrics::{gauge, register_gauge, Unit};
+use chrono::{DateTime, Utc};
+use metrics::{gauge, register_counter, register_gauge, register_histogram, Unit};
 use regex::Regex;
-use secrecy::ExposeSecret;
-use serde::Deserialize;
-use tokio::{sync::mpsc::UnboundedReceiver, time::sleep};
+use tokio::{sync::mpsc::UnboundedReceiver, time};
 use tracing::{debug, info, instrument, warn};
-use twitch_irc::{
-    login::StaticLoginCredentials, message::ServerMessage, ClientConfig, TCPTransport,
-    TwitchIRCClient,
-};
+use twitch_irc::message::ServerMessage;
 
 use crate::{
-    bot::{self, Bot},
-    SecretToken, Timestamp,
+    admin, board,
+    bot::{self, Bot, DryRun},
+    chat::{ChatClient, ChatTransport},
+    chatters::ChattersApi,
+    cooldown, history, http,
+    metrics::{legacy_names, ClaimMetrics, LegacyNames},
+    notify,
+    once::{run_once, CancellationToken, OnceError},
+    presence::Presence,
+    secrettoken::SharedToken,
+    state,
+    suffix::AntiDuplicate,
+    util::TokenBucket,
+    Blackouts, ChannelFailover, Channels, CommunicateOptions, Error, HttpOptions, Jitter,
+    QuietHours, Schedule, Timestamp,
 };
 
 use super::{
+    api::{CooldownResponse, RoaringIron},
+    buybooster::BuyBoosterResponse,
+    buycdr::BuyCdrResponse,
     claimcookie::ClaimCookieResponse,
-    patterns::{BUY_CDR_BAD, BUY_CDR_GOOD, GENERIC_ANSWER, PRESTIGE_BAD, PRESTIGE_GOOD},
+    config::Config,
+    patterns::{
+        BOOSTER_BAD, BOOSTER_GOOD, BUY_CDR_BAD, BUY_CDR_GOOD, CLAIM_GOOD, GENERIC_ANSWER,
+        PRESTIGE_BAD, PRESTIGE_GOOD,
+    },
     rank::Rank,
 };
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("Error: {0}")]
-    AnyhowError(#[from] anyhow::Error),
-
-    #[error("Could not check chatters: {0}")]
-    CheckChattersError(#[source] bot::Error),
-}
-
-static COOLDOWN_API: &str = "https://api.roaringiron.com/cooldown";
-static METRIC_TOTAL_COOKIES: &str = "cookiebot.cookies.total";
 static METRIC_PRESTIGE: &str = "cookiebot.prestige";
 static POSITIVE_BOT_USER_ID: &str = "425363834";
+static BOT_LABEL: &str = "thepositivebot";
 
-// {
-//     "can_claim": false,
-//     "interval_formatted": "2 hours",
-//     "interval_unformatted": 7200,
-//     "seconds_left": 7037.756,
-//     "time_left_formatted": "1 hr, 57 mins, and 18 secs",
-//     "time_left_unformatted": "01:57:17"
-// }
-#[derive(Debug, Copy, Clone, Deserialize)]
-struct CooldownResponse {
-    can_claim: bool,
-    seconds_left: f32,
-}
+/// How long to wait before claiming again while the cooldown API is down
+const UNKNOWN_COOLDOWN_RETRY: Duration = Duration::from_secs(10 * 60);
 
-/*
-{
-  "id": "25790355",
-  "username": "chronophylos",
-  "twitchID": "54946241",
-  "firstseen": "Sun Aug 02 2020 21:16:28 GMT+0000 (Coordinated Universal Time)",
-  "lastseen": "Thu Nov 12 2020 11:08:12 GMT+0000 (Coordinated Universal Time)",
-  "cookies": 728,
-  "rank": "default",
-  "prestige": 1,
-  "active": "false",
-  "cooldownreset_cooldown": "Thu Nov 12 2020 11:08:02 GMT+0000 (Coordinated Universal Time)",
-  "booster_cooldown": "none",
-  "tip_cooldown": "none"
+/// Names of the claim metrics before they were labeled by bot
+static LEGACY_METRICS: LegacyNames = LegacyNames {
+    total: "cookiebot.cookies.total",
+    claims_attempted: "cookiebot.cookies.claims.attempted",
+    claims_skipped: "cookiebot.cookies.claims.skipped",
+    claims_succeeded: "cookiebot.cookies.claims.succeeded",
+    claims_failed: "cookiebot.cookies.claims.failed",
+    claims_unparsed: "cookiebot.cookies.claims.parse_failures",
+    claimed_amount: "cookiebot.cookies.claimed",
+};
+
+/// Registers the cookie metrics with the recorder, with the names before
+/// they were labeled by bot if `legacy` is set.
+pub(crate) fn register_metrics(legacy: bool) {
+    register_gauge!(METRIC_PRESTIGE, Unit::Count, "current prestige level");
+    if !legacy {
+        return;
+    }
+
+    register_gauge!(LEGACY_METRICS.total, Unit::Count, "total number of cookies");
+    register_counter!(
+        LEGACY_METRICS.claims_attempted,
+        Unit::Count,
+        "number of cookie claims sent to chat"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_skipped,
+        Unit::Count,
+        "number of cookie claims skipped on purpose"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_succeeded,
+        Unit::Count,
+        "number of successful cookie claims"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_failed,
+        Unit::Count,
+        "number of cookie claims that hit the cooldown"
+    );
+    register_counter!(
+        LEGACY_METRICS.claims_unparsed,
+        Unit::Count,
+        "number of cookie claim answers that could not be parsed"
+    );
+    register_histogram!(
+        LEGACY_METRICS.claimed_amount,
+        Unit::Count,
+        "cookies gained or lost per claim"
+    );
 }
-*/
-#[derive(Debug, Clone, Deserialize)]
-struct UserResponse<'a> {
-    cookies: u32,
-    rank: Rank,
-    prestige: u32,
-    booster_cooldown: Cow<'a, str>,
+
+/// Everything a [`CookieBot`] is built from besides its account
+#[derive(Debug, Clone, Default)]
+pub struct CookieBotOptions {
+    /// The thepositivebot section, which also holds the channels, schedule
+    /// and communication options
+    pub config: Config,
+    pub blackouts: Blackouts,
+    pub http: HttpOptions,
+    pub chatters: ChattersApi,
+
+    /// Whether the APIs may present invalid certificates
+    pub accept_invalid_certs: bool,
 }
 
 #[derive(Debug)]
 pub struct CookieBot {
     username: String,
-    token: SecretToken,
-    channel: String,
-    accept_invalid_certs: bool,
+    metrics: ClaimMetrics,
+    token: SharedToken,
+    channels: ChannelFailover,
+    client: http::Client,
+    schedule: Schedule,
+    blackouts: Blackouts,
+    communicate: CommunicateOptions,
+    config: Config,
+    api: RoaringIron,
+    chatters: ChattersApi,
+    presence: Presence,
+    anti_duplicate: AntiDuplicate,
+    pacing: Option<Arc<TokenBucket>>,
+    message_limiter: Option<Arc<TokenBucket>>,
+    chat_transport: ChatTransport,
+    dry_run: Option<DryRun>,
+    jitter: Jitter,
+    quiet_hours: Option<QuietHours>,
 }
 
 impl CookieBot {
+    pub const CLAIM_COOLDOWN: Duration = Duration::from_secs(2 * 3600);
+
+    /// Creates the bot of the account `username` logging in with `token`.
+    pub fn with_options(
+        username: String,
+        token: SharedToken,
+        options: CookieBotOptions,
+    ) -> Result<Self, bot::Error> {
+        let CookieBotOptions {
+            config,
+            blackouts,
+            http,
+            chatters,
+            accept_invalid_certs,
+        } = options;
+        let client = bot::build_client(http, accept_invalid_certs)?;
+        let login = username.to_lowercase();
+
+        Ok(Self {
+            metrics: ClaimMetrics::new(BOT_LABEL, &login, &LEGACY_METRICS),
+            username,
+            token,
+            channels: ChannelFailover::new(BOT_LABEL, &login, config.channel.clone()),
+            client,
+            schedule: config.schedule,
+            blackouts,
+            communicate: config.communicate,
+            api: RoaringIron::with_bases(&config.user_api_base, &config.cooldown_api_base),
+            anti_duplicate: AntiDuplicate::new(config.duplicate_suffix.clone()),
+            config,
+            chatters,
+            presence: Presence::default(),
+            pacing: None,
+            message_limiter: None,
+            chat_transport: ChatTransport::default(),
+            dry_run: None,
+            jitter: Jitter::default(),
+            quiet_hours: None,
+        })
+    }
+
+    /// Creates the bot from positional arguments, which override the
+    /// channels, schedule and communication options of `config`.
+    ///
+    /// Kept for compatibility, prefer [`with_options`](Self::with_options).
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         username: String,
-        token: SecretToken,
-        channel: String,
+        token: SharedToken,
+        channels: Channels,
         accept_invalid_certs: bool,
-    ) -> Self {
-        register_gauge!(METRIC_TOTAL_COOKIES, Unit::Count, "total number of cookies");
-        register_gauge!(METRIC_PRESTIGE, Unit::Count, "current prestige level");
-
-        Self {
+        schedule: Schedule,
+        blackouts: Blackouts,
+        communicate: CommunicateOptions,
+        config: Config,
+        http: HttpOptions,
+        chatters: ChattersApi,
+    ) -> Result<Self, bot::Error> {
+        Self::with_options(
             username,
             token,
-            channel,
-            accept_invalid_certs,
-        }
+            CookieBotOptions {
+                config: Config {
+                    channel: channels,
+                    schedule,
+                    communicate,
+                    ..config
+                },
+                blackouts,
+                http,
+                chatters,
+                accept_invalid_certs,
+            },
+        )
     }
 
-    #[instrument]
-    pub async fn run(&self) -> Result<()> {
+    /// Spaces the commands of the bot with those of the other accounts
+    /// sharing `pacing`.
+    pub fn with_pacing(mut self, pacing: Arc<TokenBucket>) -> Self {
+        self.pacing = Some(pacing);
+        self
+    }
+
+    /// Keeps the messages of the bot within the limit of its account, shared
+    /// with the other bots of the account through `limiter`.
+    pub fn with_message_limiter(mut self, limiter: Arc<TokenBucket>) -> Self {
+        self.message_limiter = Some(limiter);
+        self
+    }
+
+    /// Connects to chat over `transport`.
+    pub const fn with_chat_transport(mut self, transport: ChatTransport) -> Self {
+        self.chat_transport = transport;
+        self
+    }
+
+    /// Logs the commands of the bot instead of sending them if `dry_run` is
+    /// enabled.
+    pub fn with_dry_run(mut self, dry_run: DryRun) -> Self {
+        self.dry_run = Some(dry_run).filter(|dry_run| dry_run.enabled);
+        self
+    }
+
+    /// Adds a random delay from `jitter` to every wait before a claim.
+    pub const fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Keeps the bot from claiming during `quiet_hours`.
+    pub const fn with_quiet_hours(mut self, quiet_hours: Option<QuietHours>) -> Self {
+        self.quiet_hours = quiet_hours;
+        self
+    }
+
+    #[instrument(skip(self), fields(bot = BOT_LABEL, username = %self.get_login()))]
+    pub async fn run(&self) -> Result<(), Error> {
         info!("Running CookieBot");
+        let _running = self.metrics.running();
+        info!("Claim schedule: {}", self.schedule);
+        info!("Claim jitter: {}", self.jitter);
+        if let Some(quiet_hours) = &self.quiet_hours {
+            info!("Quiet hours: {}", quiet_hours);
+        }
+        self.log_login_correction();
 
-        loop {
-            // update metrics
-            let response = self.get_user().await?;
-            gauge!(METRIC_TOTAL_COOKIES, response.cookies as f64);
-            gauge!(METRIC_PRESTIGE, response.prestige as f64);
+        for blackout in self.blackouts.describe_upcoming(3) {
+            info!("Upcoming blackout: {}", blackout);
+        }
 
-            self.wait_for_cooldown().await?;
+        self.resume_saved_state().await;
 
-            if !self
-                .check_chatters("thepositivebot")
-                .await
-                .map_err(Error::CheckChattersError)?
-            {
-                warn!(
-                    "ThePositiveBot is not in #{}. Suspending bot for 30 minutes",
-                    self.channel
-                );
-                sleep(Duration::from_secs(60 * 30)).await;
+        // twitch_irc reconnects and rejoins on its own, so the connection is
+        // only rebuilt if the message stream closes
+        let (mut incoming_messages, mut client) = self.login().await?;
+
+        // when the next cooldown reset can be bought, if known
+        let mut next_cdr: Option<Instant> = None;
+        // when the next booster can be bought, if known
+        let mut next_booster: Option<DateTime<Utc>> = None;
+
+        loop {
+            // without the cooldown API the claim response tells us whether
+            // the cooldown is still active
+            let cooldown_known = match self.update_profile().await? {
+                Some((cooldown, booster_cooldown)) => {
+                    next_booster = booster_cooldown;
+                    board::ready(BOT_LABEL, &self.get_login());
+                    self.wait_for_cooldown(&mut incoming_messages, cooldown)
+                        .await?;
+                    true
+                }
+                None => {
+                    warn!("Cooldown unknown. Claiming anyway");
+                    false
+                }
+            };
+
+            if self.config.skip_probability.should_skip() {
+                self.metrics.skipped(self.get_channel());
+                let delay = self.claim_delay(Self::CLAIM_COOLDOWN);
+                info!("Waiting for {}", delay.as_readable());
+                cooldown::sleep(BOT_LABEL, &self.get_login(), delay).await;
                 continue;
             }
 
-            let config = ClientConfig::new_simple(StaticLoginCredentials::new(
-                self.username.clone(),
-                Some(self.token.expose_secret().to_string()),
-            ));
-            let (mut incoming_messages, client) =
-                TwitchIRCClient::<TCPTransport, StaticLoginCredentials>::new(config);
+            self.wait_for_quiet_hours().await;
+            self.wait_for_presence(&mut incoming_messages, "thepositivebot")
+                .await
+                .map_err(Error::CheckChatters)?;
 
-            client.join(self.channel.clone());
+            admin::wait_while_paused(BOT_LABEL).await;
+            cooldown::set(BOT_LABEL, &self.get_login(), Duration::from_secs(0));
+            let response = match self.claim_cookies(&client, &mut incoming_messages).await {
+                Err(Error::Communication(err)) if err.suspension().is_some() => {
+                    self.wait_out(&err).await;
+                    continue;
+                }
+                Err(Error::Communication(bot::Error::ConnectionLost)) => {
+                    let (new_incoming_messages, new_client) = self.reconnect().await?;
+                    incoming_messages = new_incoming_messages;
+                    client = new_client;
+                    continue;
+                }
+                result => match skip_unexpected_reply(result)? {
+                    Some(response) => response,
+                    None => {
+                        let delay = self.claim_delay(Self::CLAIM_COOLDOWN);
+                        info!("Trying again in {}", delay.as_readable());
+                        cooldown::sleep(BOT_LABEL, &self.get_login(), delay).await;
+                        continue;
+                    }
+                },
+            };
 
-            match self.claim_cookies(&client, &mut incoming_messages).await? {
+            self.record_claim(&response);
+            match response {
                 ClaimCookieResponse::Success {
                     rank,
                     name,
                     amount,
                     total,
                 } => {
-                    gauge!(METRIC_TOTAL_COOKIES, total as f64);
-                    gauge!(METRIC_PRESTIGE, rank.prestige as f64);
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.prestige_gauge(rank.prestige);
+                    self.metrics.succeeded(self.get_channel(), amount as f64);
+
+                    if let Rank::Unknown(name) = &rank.rank {
+                        warn!("Unknown rank {}, only prestiging by cookie count", name);
+                    }
 
                     if amount == 0 {
                         info!("No cookies found");
@@ -151,145 +355,457 @@ impl CookieBot {
                         info!("Got {} {}s", amount, name);
                     }
 
-                    if amount > 7 {
-                        info!("Trying to buy cooldown reduction for 7 cookies");
-                        if self.buy_cdr(&client, &mut incoming_messages).await? {
-                            info!("Cooldown was reset");
-                            continue;
+                    if self
+                        .config
+                        .should_buy_booster(total, next_booster, Utc::now())
+                    {
+                        info!(
+                            "Trying to buy booster for {} cookies",
+                            self.config.booster_cost
+                        );
+                        match skip_unexpected_reply(
+                            self.buy_booster(&client, &mut incoming_messages).await,
+                        )? {
+                            Some(BuyBoosterResponse::Bought) => info!("Bought booster"),
+                            Some(BuyBoosterResponse::Cooldown(remaining)) => {
+                                next_booster = chrono::Duration::from_std(remaining)
+                                    .ok()
+                                    .map(|remaining| Utc::now() + remaining);
+                            }
+                            None => {}
                         }
                     }
 
-                    if total >= 5000 && !self.prestige(&client, &mut incoming_messages).await? {
+                    if self.config.should_buy_cdr(amount, total)
+                        && next_cdr.is_none_or(|at| Instant::now() >= at)
+                    {
+                        info!(
+                            "Trying to buy cooldown reduction for {} cookies",
+                            self.config.cdr_cost
+                        );
+                        match skip_unexpected_reply(
+                            self.buy_cdr(&client, &mut incoming_messages).await,
+                        )? {
+                            Some(BuyCdrResponse::Reset) => {
+                                info!("Cooldown was reset");
+                                next_cdr = None;
+                                continue;
+                            }
+                            Some(BuyCdrResponse::Cooldown(remaining)) => {
+                                next_cdr = Some(Instant::now() + remaining);
+                            }
+                            None => {}
+                        }
+                    }
+
+                    if self.config.should_prestige(&rank, total)
+                        && skip_unexpected_reply(
+                            self.prestige(&client, &mut incoming_messages).await,
+                        )? == Some(false)
+                    {
                         warn!(
-                            "Could not upgrade prestige but cookie count is over 5000 ({})",
-                            total
+                            "Could not upgrade prestige at {} with {} cookies",
+                            rank, total
                         );
                     }
 
                     info!("Waiting for cooldown");
                 }
                 ClaimCookieResponse::Cooldown { rank, total } => {
-                    gauge!(METRIC_TOTAL_COOKIES, total as f64);
-                    gauge!(METRIC_PRESTIGE, rank.prestige as f64);
+                    self.metrics.total(self.get_channel(), total as f64);
+                    self.prestige_gauge(rank.prestige);
+                    self.metrics.failed(self.get_channel());
 
                     info!("Could not claim cookies: Cooldown active");
+
+                    if !cooldown_known {
+                        let delay = self.claim_delay(UNKNOWN_COOLDOWN_RETRY);
+                        info!("Trying again in {}", delay.as_readable());
+                        cooldown::sleep(BOT_LABEL, &self.get_login(), delay).await;
+                    }
                 }
             }
         }
     }
 
-    #[instrument(skip(self))]
-    async fn wait_for_cooldown(&self) -> Result<()> {
-        info!("Checking cookie cooldown");
+    /// Claims cookies once over a new chat connection.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use cookiebot::{once::CancellationToken, secrettoken::Token, CookieBot, SecretToken};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let bot = CookieBot::with_options(
+    ///     "chronophylos".to_string(),
+    ///     SecretToken::new(Token::new("token")).into(),
+    ///     Default::default(),
+    /// )?;
+    ///
+    /// let cancel = CancellationToken::new();
+    /// let response = bot.claim_once(Some(Duration::from_secs(30)), &cancel).await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn claim_once(
+        &self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<ClaimCookieResponse, OnceError<Error>> {
+        run_once(
+            async {
+                let (mut incoming_messages, client) = self.login().await?;
+                self.claim_cookies(&client, &mut incoming_messages).await
+            },
+            timeout,
+            cancel,
+        )
+        .await
+    }
 
-        if let Some(duration) = self.get_cookie_cd().await? {
-            info!("Cooldown active");
+    /// Returns the remaining cookie cooldown or `None` if cookies can be
+    /// claimed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use cookiebot::{once::CancellationToken, secrettoken::Token, CookieBot, SecretToken};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let bot = CookieBot::with_options(
+    /// #     "chronophylos".to_string(),
+    /// #     SecretToken::new(Token::new("token")).into(),
+    /// #     Default::default(),
+    /// # )?;
+    /// if let Ok(None) = bot.check_cooldown(None, &CancellationToken::new()).await {
+    ///     println!("cookies are ready");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_cooldown(
+        &self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Duration>, OnceError<Error>> {
+        run_once(
+            async {
+                let cooldown = self
+                    .api
+                    .cooldown(self.get_client(), &self.get_login())
+                    .await?;
+                Ok(cooldown.remaining())
+            },
+            timeout,
+            cancel,
+        )
+        .await
+    }
 
-            info!("Waiting for {}", duration.as_readable());
-            sleep(duration).await;
-        } else {
-            info!("Cooldown not active")
+    /// Buys a cooldown reset once over a new chat connection.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use cookiebot::{once::CancellationToken, secrettoken::Token, CookieBot, SecretToken};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let bot = CookieBot::with_options(
+    /// #     "chronophylos".to_string(),
+    /// #     SecretToken::new(Token::new("token")).into(),
+    /// #     Default::default(),
+    /// # )?;
+    /// let reset = bot
+    ///     .buy_cdr_once(Some(Duration::from_secs(30)), &CancellationToken::new())
+    ///     .await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn buy_cdr_once(
+        &self,
+        timeout: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<BuyCdrResponse, OnceError<Error>> {
+        run_once(
+            async {
+                let (mut incoming_messages, client) = self.login().await?;
+                self.buy_cdr(&client, &mut incoming_messages).await
+            },
+            timeout,
+            cancel,
+        )
+        .await
+    }
+
+    /// Restores the total and waits out the cooldown saved before the last
+    /// restart.
+    async fn resume_saved_state(&self) {
+        let saved = match state::saved(BOT_LABEL, &self.get_login()) {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        if let Some(total) = saved.total {
+            self.metrics.total(self.get_channel(), total as f64);
+        }
+        if let Some(remaining) = saved.remaining() {
+            info!("Saved cooldown, waiting for {}", remaining.as_readable());
+            board::ready(BOT_LABEL, &self.get_login());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), remaining).await;
+        }
+    }
+
+    /// Sets the prestige level, also without labels under the legacy names.
+    fn prestige_gauge(&self, prestige: u32) {
+        gauge!(
+            METRIC_PRESTIGE,
+            prestige as f64,
+            self.metrics.labels(self.get_channel())
+        );
+        if legacy_names() {
+            gauge!(METRIC_PRESTIGE, prestige as f64);
         }
+    }
 
-        Ok(())
+    /// Waits until the quiet hours are over, plus jitter.
+    async fn wait_for_quiet_hours(&self) {
+        if let Some(remaining) = self.quiet_hours.as_ref().and_then(QuietHours::remaining) {
+            let wait = self.jitter.add_to(remaining);
+            info!("Quiet hours, waiting for {}", wait.as_readable());
+            cooldown::sleep(BOT_LABEL, &self.get_login(), wait).await;
+        }
     }
 
-    #[instrument(skip(self))]
-    async fn get_cookie_cd(&self) -> Result<Option<Duration>> {
-        let client = self.get_client()?;
+    /// Returns how long to wait before claiming when the cooldown expires in
+    /// `cooldown`, following the schedule, adding jitter and skipping
+    /// blackouts.
+    fn claim_delay(&self, cooldown: Duration) -> Duration {
+        self.blackouts
+            .defer(self.jitter.add_to(self.schedule.wait_time(cooldown)))
+    }
 
-        let response: CooldownResponse = client
-            .get(&format!("{}/{}", COOLDOWN_API, self.username))
-            .send()
-            .await
-            .context("Could not send request to api.roaringiron.com")?
-            .json()
+    /// Updates the metrics from the user profile and returns the cookie and
+    /// booster cooldowns.
+    ///
+    /// Returns `None` if the API stays unavailable after retrying.
+    async fn update_profile(
+        &self,
+    ) -> Result<Option<(CooldownResponse, Option<DateTime<Utc>>)>, Error> {
+        match self
+            .api
+            .profile_with_cooldown(self.get_client(), &self.get_login())
             .await
-            .context("Could not deserialize json response")?;
+        {
+            Ok((user, cooldown)) => {
+                self.metrics.total(self.get_channel(), user.cookies as f64);
+                self.prestige_gauge(user.prestige);
 
-        debug!("Got response from api.roaringiron.com: {:?}", response);
+                match user.booster_cooldown {
+                    Some(until) if until > Utc::now() => debug!("Booster available at {}", until),
+                    _ => info!("Booster is available"),
+                }
 
-        if response.can_claim {
-            Ok(None)
-        } else {
-            Ok(Some(Duration::from_secs_f32(response.seconds_left)))
+                Ok(Some((cooldown, user.booster_cooldown)))
+            }
+            Err(err) => {
+                warn!("Giving up on api.roaringiron.com: {}", err);
+                Ok(None)
+            }
         }
     }
 
-    #[instrument(skip(self))]
-    async fn get_user(&self) -> Result<UserResponse<'_>> {
-        let client = self.get_client()?;
-        let response: UserResponse = client
-            .get(&format!(
-                "https://api.roaringiron.com/user/{}",
-                self.username
-            ))
-            .send()
-            .await?
-            .json()
-            .await?;
+    /// Publishes `response` on the board and records it in the history and
+    /// for notifications.
+    fn record_claim(&self, response: &ClaimCookieResponse) {
+        board::claimed(BOT_LABEL, &self.get_login(), response.clone().into());
+        let claim = history::Claim::new(BOT_LABEL, &self.get_login(), response.clone().into());
+        history::record(match response {
+            ClaimCookieResponse::Success { name, .. } => claim.with_name(name),
+            ClaimCookieResponse::Cooldown { .. } => claim,
+        });
+        notify::send(notify::Event::Claimed {
+            bot: BOT_LABEL,
+            account: self.get_login(),
+            outcome: response.clone().into(),
+        });
+    }
 
-        debug!("Got response from api.roaringiron.com: {:?}", response);
+    /// Waits for `cooldown` while listening for claims of the account made
+    /// elsewhere, e.g. by hand, which start the cooldown over.
+    #[instrument(skip(self, incoming_messages))]
+    async fn wait_for_cooldown(
+        &self,
+        incoming_messages: &mut UnboundedReceiver<ServerMessage>,
+        cooldown: CooldownResponse,
+    ) -> Result<(), Error> {
+        info!("Checking cookie cooldown");
 
-        Ok(response)
+        let remaining = cooldown.remaining();
+        cooldown::set(BOT_LABEL, &self.get_login(), remaining.unwrap_or_default());
+        if remaining.is_some() {
+            info!("Cooldown active");
+        } else {
+            info!("Cooldown not active")
+        }
+
+        // on the clock of tokio like the sleep itself
+        let mut deadline = time::Instant::now() + self.claim_delay(remaining.unwrap_or_default());
+        loop {
+            let duration = deadline.saturating_duration_since(time::Instant::now());
+            if duration == Duration::from_secs(0) {
+                return Ok(());
+            }
+
+            info!("Waiting for {}", duration.as_readable());
+            let answer = self
+                .sleep_listening(incoming_messages, deadline, &|answer| {
+                    CLAIM_GOOD.is_match(answer)
+                })
+                .await?;
+            let response: ClaimCookieResponse = match answer.map(|answer| answer.parse()) {
+                Some(Ok(response)) => response,
+                Some(Err(err)) => {
+                    warn!("Could not parse external claim: {}", err);
+                    continue;
+                }
+                None => return Ok(()),
+            };
+
+            info!("External claim detected: {:?}", response);
+            if let ClaimCookieResponse::Success { rank, total, .. } = &response {
+                self.metrics.total(self.get_channel(), *total as f64);
+                self.prestige_gauge(rank.prestige);
+            }
+            self.record_claim(&response);
+            deadline = time::Instant::now() + self.claim_delay(Self::CLAIM_COOLDOWN);
+        }
     }
 
     #[instrument(skip(self, client, incoming_messages))]
     async fn claim_cookies(
         &self,
-        client: &TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
+        client: &dyn ChatClient,
         incoming_messages: &mut UnboundedReceiver<ServerMessage>,
-    ) -> Result<ClaimCookieResponse> {
+    ) -> Result<ClaimCookieResponse, Error> {
         info!("Claiming cookies");
+        self.metrics.attempted(self.get_channel());
 
-        self.communicate(client, incoming_messages, "!cookie")
-            .await?
+        let message = self
+            .communicate(client, incoming_messages, "!cookie")
+            .await?;
+
+        message
             .parse()
-            .context("Could not parse response of cookie command")
+            .inspect_err(|_| self.metrics.unparsed(self.get_channel()))
+            .map_err(|err| Error::parse(&message, err))
     }
 
     #[instrument(skip(self, client, incoming_messages))]
     async fn prestige(
         &self,
-        client: &TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
+        client: &dyn ChatClient,
         incoming_messages: &mut UnboundedReceiver<ServerMessage>,
-    ) -> Result<bool> {
-        Ok(self
+    ) -> Result<bool, Error> {
+        let outcome = self
             .request(
                 client,
                 incoming_messages,
                 "!prestige",
-                PRESTIGE_GOOD.clone(),
-                PRESTIGE_BAD.clone(),
+                &PRESTIGE_GOOD,
+                &PRESTIGE_BAD,
             )
-            .await?)
+            .await?;
+
+        if let Some(rank) = outcome.capture("rank") {
+            info!("Prestiged to {}", rank);
+            notify::send(notify::Event::Prestiged {
+                account: self.get_login(),
+                rank: rank.to_string(),
+            });
+        }
+
+        Ok(outcome.is_good())
     }
 
     #[instrument(skip(self, client, incoming_messages))]
     async fn buy_cdr(
         &self,
-        client: &TwitchIRCClient<TCPTransport, StaticLoginCredentials>,
+        client: &dyn ChatClient,
         incoming_messages: &mut UnboundedReceiver<ServerMessage>,
-    ) -> Result<bool> {
-        Ok(self
+    ) -> Result<BuyCdrResponse, Error> {
+        let outcome = self
             .request(
                 client,
                 incoming_messages,
                 "!cdr",
-                BUY_CDR_GOOD.clone(),
-                BUY_CDR_BAD.clone(),
+                &BUY_CDR_GOOD,
+                &BUY_CDR_BAD,
             )
-            .await?)
+            .await?;
+
+        let response = BuyCdrResponse::from(&outcome);
+        if let BuyCdrResponse::Cooldown(remaining) = response {
+            info!(
+                "Next cooldown reset can be bought in {}",
+                remaining.as_readable()
+            );
+        }
+
+        Ok(response)
+    }
+
+    #[instrument(skip(self, client, incoming_messages))]
+    async fn buy_booster(
+        &self,
+        client: &dyn ChatClient,
+        incoming_messages: &mut UnboundedReceiver<ServerMessage>,
+    ) -> Result<BuyBoosterResponse, Error> {
+        let outcome = self
+            .request(
+                client,
+                incoming_messages,
+                "!booster",
+                &BOOSTER_GOOD,
+                &BOOSTER_BAD,
+            )
+            .await?;
+
+        let response = BuyBoosterResponse::from(&outcome);
+        if let BuyBoosterResponse::Cooldown(remaining) = response {
+            info!("Next booster can be bought in {}", remaining.as_readable());
+        }
+
+        Ok(response)
+    }
+}
+
+/// Returns whether `err` was caused by a reply that no pattern understood.
+const fn is_unexpected_reply(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::Parse { .. } | Error::Communication(bot::Error::NoMatchingRegex(_))
+    )
+}
+
+/// Logs unexpected replies and turns them into `None`, so the bot keeps
+/// running until the patterns are updated.
+fn skip_unexpected_reply<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_unexpected_reply(&err) => {
+            warn!("Unexpected reply from ThePositiveBot: {:#}", err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
     }
 }
 
 impl Bot for CookieBot {
-    fn accepts_invalid_certs(&self) -> bool {
-        self.accept_invalid_certs
+    fn get_client(&self) -> &http::Client {
+        &self.client
     }
 
-    fn get_channel(&self) -> &str {
-        &self.channel
+    fn get_channels(&self) -> &ChannelFailover {
+        &self.channels
     }
 
     fn get_bot_id(&self) -> &str {
@@ -300,7 +816,297 @@ impl Bot for CookieBot {
         &self.username
     }
 
+    fn get_token(&self) -> &SharedToken {
+        &self.token
+    }
+
+    fn get_communicate_options(&self) -> CommunicateOptions {
+        self.communicate
+    }
+
     fn get_generic_answer(&self) -> &Regex {
-        &*GENERIC_ANSWER
+        &GENERIC_ANSWER
+    }
+
+    fn get_chatters_api(&self) -> &ChattersApi {
+        &self.chatters
+    }
+
+    fn get_presence(&self) -> &Presence {
+        &self.presence
+    }
+
+    fn get_anti_duplicate(&self) -> &AntiDuplicate {
+        &self.anti_duplicate
+    }
+
+    fn get_pacing(&self) -> Option<&TokenBucket> {
+        self.pacing.as_deref()
+    }
+
+    fn get_message_limiter(&self) -> Option<&TokenBucket> {
+        self.message_limiter.as_deref()
+    }
+
+    fn get_chat_transport(&self) -> ChatTransport {
+        self.chat_transport
+    }
+
+    fn get_dry_run(&self) -> Option<&DryRun> {
+        self.dry_run.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        chat::testing::{privmsg, ScriptedChat},
+        secrettoken::Token,
+        SecretToken,
+    };
+
+    fn test_bot() -> CookieBot {
+        test_bot_with(Config::default())
+    }
+
+    fn test_bot_with(config: Config) -> CookieBot {
+        let http = HttpOptions {
+            retry_delay: Duration::from_millis(10),
+            ..HttpOptions::default()
+        };
+
+        CookieBot::with_options(
+            "ChronoPhylos".to_string(),
+            SecretToken::new(Token::new("token")).into(),
+            CookieBotOptions {
+                config,
+                http,
+                ..CookieBotOptions::default()
+            },
+        )
+        .unwrap()
+    }
+
+    fn profile() -> serde_json::Value {
+        json!({
+            "cookies": 728,
+            "rank": "default",
+            "prestige": 1,
+            "booster_cooldown": "none",
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn claims_cookies_in_chat() {
+        let (chat, mut receiver) = ScriptedChat::new();
+        chat.answer_next(&[&privmsg(
+            POSITIVE_BOT_USER_ID,
+            "thepositivebot",
+            "thepositivebot",
+            "[Cookies] [P1: default] chronophylos -> Sugar cookie! (+14) PJSugar | 65 total! | 2 hour cooldown...",
+        )]);
+
+        let response = test_bot()
+            .claim_cookies(&chat, &mut receiver)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            response,
+            ClaimCookieResponse::Success {
+                amount: 14,
+                total: 65,
+                ..
+            }
+        ));
+        assert!(chat.said()[0].starts_with("!cookie"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn takes_over_claims_made_elsewhere() {
+        let (chat, mut receiver) = ScriptedChat::new();
+        let bot = CookieBot::with_options(
+            "ExternalClaimer".to_string(),
+            SecretToken::new(Token::new("token")).into(),
+            CookieBotOptions::default(),
+        )
+        .unwrap();
+        let cooldown = CooldownResponse {
+            can_claim: false,
+            seconds_left: 3600.,
+        };
+
+        let start = time::Instant::now();
+        let chatting = async {
+            time::sleep(Duration::from_secs(10 * 60)).await;
+            chat.receive(&privmsg(
+                POSITIVE_BOT_USER_ID,
+                "thepositivebot",
+                "thepositivebot",
+                "[Cookies] [P1: default] externalclaimer you have already claimed a cookie and have 51 of them! 🍪 Please wait in 2 hour intervals!",
+            ));
+            time::sleep(Duration::from_secs(20 * 60)).await;
+            chat.receive(&privmsg(
+                POSITIVE_BOT_USER_ID,
+                "thepositivebot",
+                "thepositivebot",
+                "[Cookies] [P1: default] externalclaimer -> Sugar cookie! (+14) PJSugar | 65 total! | 2 hour cooldown...",
+            ));
+        };
+        let (waited, ()) = tokio::join!(bot.wait_for_cooldown(&mut receiver, cooldown), chatting);
+        waited.unwrap();
+
+        // the claim made by hand starts the cooldown over
+        assert_eq!(
+            start.elapsed(),
+            Duration::from_secs(30 * 60) + CookieBot::CLAIM_COOLDOWN
+        );
+        assert!(chat.said().is_empty());
+        let status = board::snapshot()
+            .into_iter()
+            .find(|status| status.bot == BOT_LABEL && status.account == "externalclaimer")
+            .unwrap();
+        assert_eq!(status.total, Some(65));
+    }
+
+    #[test]
+    fn skips_unexpected_replies() {
+        let unparsed = "[Cookies] something new"
+            .parse::<ClaimCookieResponse>()
+            .map_err(|err| Error::parse("[Cookies] something new", err));
+        let unmatched: Result<(), Error> =
+            Err(bot::Error::NoMatchingRegex("[Shop] hi".to_string()).into());
+        let auth: Result<(), Error> = Err(bot::Error::AuthenticateChat.into());
+
+        assert_eq!(skip_unexpected_reply(unparsed).unwrap(), None);
+        assert_eq!(skip_unexpected_reply(unmatched).unwrap(), None);
+        assert!(skip_unexpected_reply(auth).is_err());
+    }
+
+    #[tokio::test]
+    async fn api_lookups_use_login() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "cookies": 728,
+                "rank": "default",
+                "prestige": 1,
+                "booster_cooldown": "none",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "can_claim": true,
+                "seconds_left": 0.0,
+            })))
+            .mount(&server)
+            .await;
+
+        let mut bot = test_bot();
+        bot.api = RoaringIron::new(server.uri());
+
+        bot.update_profile().await.unwrap();
+        bot.check_cooldown(None, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert_eq!(
+                request.url.path(),
+                request.url.path().to_lowercase(),
+                "request was not made with the login"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn uses_configured_api_bases() {
+        let users = MockServer::start().await;
+        let cooldowns = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "can_claim": true,
+                "seconds_left": 0.0,
+            })))
+            .expect(1)
+            .mount(&cooldowns)
+            .await;
+
+        let bot = test_bot_with(Config {
+            user_api_base: users.uri(),
+            cooldown_api_base: cooldowns.uri(),
+            ..Config::default()
+        });
+
+        let cooldown = bot
+            .check_cooldown(None, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        assert_eq!(cooldown, None);
+        assert!(users.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_transient_api_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(profile()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "can_claim": true,
+                "seconds_left": 0.0,
+            })))
+            .mount(&server)
+            .await;
+
+        let mut bot = test_bot();
+        bot.api = RoaringIron::new(server.uri());
+
+        let cooldown = bot.update_profile().await.unwrap();
+
+        assert_eq!(
+            cooldown.map(|(cooldown, _)| cooldown.remaining()),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_api_is_down() {
+        let server = MockServer::start().await;
+        // the profile and the cooldown are each retried
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2 * u64::from(HttpOptions::default().max_attempts))
+            .mount(&server)
+            .await;
+
+        let mut bot = test_bot();
+        bot.api = RoaringIron::new(server.uri());
+
+        assert_eq!(bot.update_profile().await.unwrap(), None);
     }
 }
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use super::buycdr::shop_cooldown;
+use crate::bot::{MatchKind, RequestOutcome};
+
+/// Result of a buy booster command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuyBoosterResponse {
+    /// Booster was bought
+    Bought,
+
+    /// The next booster can be bought after this duration
+    Cooldown(Duration),
+}
+
+impl From<&RequestOutcome> for BuyBoosterResponse {
+    fn from(outcome: &RequestOutcome) -> Self {
+        match outcome.matched {
+            MatchKind::Good => Self::Bought,
+            MatchKind::Bad => Self::Cooldown(shop_cooldown(outcome)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thepositivebot::patterns::{BOOSTER_BAD, BOOSTER_GOOD};
+
+    fn parse(message: &str) -> BuyBoosterResponse {
+        let outcome = RequestOutcome::classify(message, &BOOSTER_GOOD, &BOOSTER_BAD)
+            .expect("regex should match");
+
+        BuyBoosterResponse::from(&outcome)
+    }
+
+    #[test]
+    fn bought() {
+        assert_eq!(
+            parse("[Shop] chronophylos, you bought a booster!"),
+            BuyBoosterResponse::Bought
+        );
+    }
+
+    #[test]
+    fn cooldown() {
+        assert_eq!(
+            parse("[Shop] chronophylos, you can purchase your next booster in 5 hrs, 2 mins, 10 secs!"),
+            BuyBoosterResponse::Cooldown(Duration::from_secs(5 * 3600 + 2 * 60 + 10))
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::bot::{MatchKind, RequestOutcome};
+
+/// Result of a buy cooldown reset command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuyCdrResponse {
+    /// Cooldown was reset
+    Reset,
+
+    /// The next reset can be bought after this duration
+    Cooldown(Duration),
+}
+
+impl From<&RequestOutcome> for BuyCdrResponse {
+    fn from(outcome: &RequestOutcome) -> Self {
+        match outcome.matched {
+            MatchKind::Good => Self::Reset,
+            MatchKind::Bad => Self::Cooldown(shop_cooldown(outcome)),
+        }
+    }
+}
+
+/// Reads the time until the next purchase from the `h`, `m` and `s` groups
+/// of a shop reply.
+pub(super) fn shop_cooldown(outcome: &RequestOutcome) -> Duration {
+    let part = |name| {
+        outcome
+            .capture(name)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or_default()
+    };
+
+    Duration::from_secs(part("h") * 3600 + part("m") * 60 + part("s"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thepositivebot::patterns::{BUY_CDR_BAD, BUY_CDR_GOOD};
+
+    fn parse(message: &str) -> BuyCdrResponse {
+        let outcome = RequestOutcome::classify(message, &BUY_CDR_GOOD, &BUY_CDR_BAD)
+            .expect("regex should match");
+
+        BuyCdrResponse::from(&outcome)
+    }
+
+    #[test]
+    fn reset() {
+        assert_eq!(
+            parse("[Shop] chronophylos, your cooldown has been reset!"),
+            BuyCdrResponse::Reset
+        );
+    }
+
+    #[test]
+    fn cooldown_hours() {
+        assert_eq!(
+            parse("[Shop] chronophylos, you can purchase your next cooldown reset in 2 hrs, 58 mins, 54 secs!"),
+            BuyCdrResponse::Cooldown(Duration::from_secs(2 * 3600 + 58 * 60 + 54))
+        );
+    }
+
+    #[test]
+    fn cooldown_minutes() {
+        assert_eq!(
+            parse("[Shop] chronophylos, you can purchase your next cooldown reset in 58 mins, 54 secs!"),
+            BuyCdrResponse::Cooldown(Duration::from_secs(58 * 60 + 54))
+        );
+    }
+
+    #[test]
+    fn cooldown_seconds() {
+        assert_eq!(
+            parse("[Shop] chronophylos, you can purchase your next cooldown reset in 54 secs!"),
+            BuyCdrResponse::Cooldown(Duration::from_secs(54))
+        );
+    }
+}
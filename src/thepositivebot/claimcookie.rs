@@ -2,8 +2,10 @@ use std::{fmt::Display, num::ParseIntError, str::FromStr};
 use thiserror::Error;
 use tracing::instrument;
 
+use crate::{diagnostics, normalize::normalize, once::ClaimOutcome};
+
 use super::{
-    patterns::{CLAIM_BAD, CLAIM_GOOD},
+    patterns::{self, CLAIM_BAD, CLAIM_BAD_NAME, CLAIM_GOOD, CLAIM_GOOD_NAME},
     rank::{ParseRankError, Rank},
 };
 
@@ -25,7 +27,7 @@ pub enum ParsePresigeRankError {
     ParseRankError(#[source] ParseRankError),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PrestigeRank {
     pub prestige: u32,
     pub rank: Rank,
@@ -92,12 +94,31 @@ pub enum ParseClaimCookieError {
     InvalidInput,
 }
 
+impl From<ClaimCookieResponse> for ClaimOutcome {
+    fn from(claim: ClaimCookieResponse) -> Self {
+        match claim {
+            ClaimCookieResponse::Success { amount, total, .. } => Self::Claimed {
+                amount: amount.into(),
+                total: total as i64,
+            },
+            ClaimCookieResponse::Cooldown { total, .. } => Self::Cooldown {
+                remaining_secs: None,
+                total: Some(total as i64),
+            },
+        }
+    }
+}
+
 impl FromStr for ClaimCookieResponse {
     type Err = ParseClaimCookieError;
 
     #[instrument]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = &normalize(s);
+
         if let Some(captures) = CLAIM_GOOD.captures(s) {
+            diagnostics::record(CLAIM_GOOD_NAME, &CLAIM_GOOD, &captures);
+
             let rank = captures
                 .name("rank")
                 .ok_or(Self::Err::MissingCaptureGroup("rank"))?
@@ -130,6 +151,8 @@ impl FromStr for ClaimCookieResponse {
                 total,
             })
         } else if let Some(captures) = CLAIM_BAD.captures(s) {
+            diagnostics::record(CLAIM_BAD_NAME, &CLAIM_BAD, &captures);
+
             let rank = captures
                 .name("rank")
                 .ok_or(Self::Err::MissingCaptureGroup("rank"))?
@@ -144,6 +167,8 @@ impl FromStr for ClaimCookieResponse {
 
             Ok(Self::Cooldown { rank, total })
         } else {
+            diagnostics::report(s, patterns::diagnose(s));
+
             Err(Self::Err::InvalidInput)
         }
     }
@@ -170,4 +195,23 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn parse_claimcookie_with_messy_trailer() {
+        let input = "[Cookies] [P1: default] chronophylos -> Sugar cookie! (+14) PJSugar | 65 total! | 2 hour cooldown... 🍪\u{FE0F}  \u{E0000} ";
+        let response = input.parse::<ClaimCookieResponse>().unwrap();
+
+        assert_eq!(
+            response,
+            ClaimCookieResponse::Success {
+                rank: PrestigeRank {
+                    prestige: 1,
+                    rank: Rank::Default
+                },
+                name: "Sugar cookie".to_string(),
+                amount: 14,
+                total: 65
+            }
+        )
+    }
 }
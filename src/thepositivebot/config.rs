@@ -0,0 +1,250 @@
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::{api::API_BASE, claimcookie::PrestigeRank, rank::Rank};
+use crate::{
+    Channels, CommunicateOptions, Jitter, Schedule, SecretToken, SkipProbability, SuffixStrategy,
+};
+
+/// Cookies needed to prestige below Leader rank
+const PRESTIGE_MIN_TOTAL: u64 = 5000;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub disabled: bool,
+    /// Channels to claim in, in order of preference
+    pub channel: Channels,
+    pub schedule: Schedule,
+
+    /// Random delay before every claim instead of the top level
+    /// `claim_jitter`
+    pub claim_jitter: Option<Jitter>,
+
+    /// Chance of skipping a claim on purpose and waiting another cooldown
+    pub skip_probability: SkipProbability,
+    pub communicate: CommunicateOptions,
+
+    /// How commands are changed so Twitch does not drop repeats
+    pub duplicate_suffix: SuffixStrategy,
+
+    /// Account to claim cookies with instead of the top level `username`
+    pub username: Option<String>,
+
+    /// Token of the account instead of the top level `token`, never refreshed
+    pub token: Option<SecretToken>,
+
+    /// Whether cooldown resets should be bought at all
+    pub cdr_enabled: bool,
+
+    /// Price of a cooldown reset in the ThePositiveBot shop
+    pub cdr_cost: u64,
+
+    /// Total that has to be reached before buying a cooldown reset
+    ///
+    /// Without it a reset is only bought when a single claim paid for it.
+    pub cdr_min_total: Option<u64>,
+
+    /// Whether to prestige as soon as ThePositiveBot allows it
+    pub prestige_enabled: bool,
+
+    /// Whether boosters should be bought
+    pub booster_enabled: bool,
+
+    /// Price of a booster in the ThePositiveBot shop
+    pub booster_cost: u64,
+
+    /// Base URL of the API serving user profiles
+    pub user_api_base: String,
+
+    /// Base URL of the API serving cookie cooldowns
+    pub cooldown_api_base: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            channel: "thepositivebot".into(),
+            schedule: Schedule::default(),
+            claim_jitter: None,
+            skip_probability: SkipProbability::default(),
+            communicate: CommunicateOptions::default(),
+            duplicate_suffix: SuffixStrategy::default(),
+            username: None,
+            token: None,
+            cdr_enabled: true,
+            cdr_cost: 7,
+            cdr_min_total: None,
+            prestige_enabled: true,
+            booster_enabled: false,
+            booster_cost: 100,
+            user_api_base: API_BASE.to_string(),
+            cooldown_api_base: API_BASE.to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Returns whether a cooldown reset should be bought after claiming
+    /// `amount` cookies for a new total of `total`.
+    pub fn should_buy_cdr(&self, amount: i32, total: u64) -> bool {
+        if !self.cdr_enabled || total < self.cdr_cost {
+            return false;
+        }
+
+        match self.cdr_min_total {
+            Some(min_total) => total >= min_total,
+            None => u64::try_from(amount).is_ok_and(|amount| amount > self.cdr_cost),
+        }
+    }
+
+    /// Returns whether a booster should be bought with `total` cookies at
+    /// `now` if the booster cooldown ends at `cooldown`.
+    pub fn should_buy_booster(
+        &self,
+        total: u64,
+        cooldown: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.booster_enabled
+            && total > self.booster_cost
+            && cooldown.is_none_or(|cooldown| cooldown <= now)
+    }
+
+    /// Returns whether to prestige at `rank` with `total` cookies.
+    pub fn should_prestige(&self, rank: &PrestigeRank, total: u64) -> bool {
+        self.prestige_enabled && (rank.rank >= Rank::Leader || total >= PRESTIGE_MIN_TOTAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn defaults_to_claim_amount() {
+        let config = Config::default();
+
+        assert!(config.should_buy_cdr(8, 8));
+        assert!(!config.should_buy_cdr(7, 1000));
+        assert!(!config.should_buy_cdr(-3, 1000));
+    }
+
+    #[test]
+    fn uses_total_with_min_total() {
+        let config = Config {
+            cdr_min_total: Some(20),
+            ..Config::default()
+        };
+
+        assert!(config.should_buy_cdr(0, 20));
+        assert!(config.should_buy_cdr(-3, 100));
+        assert!(!config.should_buy_cdr(12, 19));
+    }
+
+    #[test]
+    fn never_below_cost() {
+        let config = Config {
+            cdr_cost: 15,
+            cdr_min_total: Some(10),
+            ..Config::default()
+        };
+
+        assert!(!config.should_buy_cdr(0, 12));
+        assert!(config.should_buy_cdr(0, 15));
+    }
+
+    #[test]
+    fn disabled() {
+        let config = Config {
+            cdr_enabled: false,
+            ..Config::default()
+        };
+
+        assert!(!config.should_buy_cdr(100, 1000));
+    }
+
+    #[test]
+    fn buys_booster_after_cooldown() {
+        let config = Config {
+            booster_enabled: true,
+            ..Config::default()
+        };
+        let now = Utc.ymd(2021, 3, 1).and_hms(12, 0, 0);
+
+        assert!(config.should_buy_booster(101, None, now));
+        assert!(config.should_buy_booster(101, Some(now), now));
+        assert!(!config.should_buy_booster(101, Some(now + chrono::Duration::seconds(1)), now));
+        assert!(!config.should_buy_booster(100, None, now));
+    }
+
+    #[test]
+    fn booster_opt_in() {
+        let now = Utc.ymd(2021, 3, 1).and_hms(12, 0, 0);
+
+        assert!(!Config::default().should_buy_booster(10_000, None, now));
+    }
+
+    fn rank(rank: Rank) -> PrestigeRank {
+        PrestigeRank { prestige: 2, rank }
+    }
+
+    #[test]
+    fn prestiges_with_enough_cookies() {
+        let config = Config::default();
+
+        assert!(config.should_prestige(&rank(Rank::Gold), 5000));
+        assert!(!config.should_prestige(&rank(Rank::Gold), 4999));
+    }
+
+    #[test]
+    fn unknown_rank_needs_cookies() {
+        let config = Config::default();
+        let unknown = rank(Rank::Unknown("mythic".to_string()));
+
+        assert!(!config.should_prestige(&unknown, 100));
+        assert!(config.should_prestige(&unknown, 5000));
+    }
+
+    #[test]
+    fn prestiges_at_leader() {
+        let config = Config::default();
+
+        assert!(config.should_prestige(&rank(Rank::Leader), 100));
+        assert!(!config.should_prestige(&rank(Rank::GrandMasters), 100));
+    }
+
+    #[test]
+    fn prestige_opt_out() {
+        let config = Config {
+            prestige_enabled: false,
+            ..Config::default()
+        };
+
+        assert!(!config.should_prestige(&rank(Rank::Leader), 10_000));
+    }
+
+    #[test]
+    fn deserialize_partial_section() {
+        let config: Config =
+            ron::de::from_str("(cdr_min_total: Some(50))").expect("config should parse");
+
+        let default = Config::default();
+
+        assert_eq!(config.cdr_min_total, Some(50));
+        assert_eq!(config.cdr_enabled, default.cdr_enabled);
+        assert_eq!(config.cdr_cost, default.cdr_cost);
+        assert_eq!(config.prestige_enabled, default.prestige_enabled);
+        assert_eq!(config.booster_enabled, default.booster_enabled);
+        assert_eq!(config.booster_cost, default.booster_cost);
+        assert_eq!(config.user_api_base, default.user_api_base);
+        assert_eq!(config.cooldown_api_base, default.cooldown_api_base);
+        assert!(config.username.is_none());
+        assert!(config.token.is_none());
+    }
+}
@@ -1,6 +1,17 @@
+mod api;
 mod bot;
+mod buybooster;
+mod buycdr;
 mod claimcookie;
+mod config;
 mod patterns;
 mod rank;
 
-pub use bot::CookieBot;
+pub use api::{CookieStatus, RoaringIron};
+pub(crate) use bot::register_metrics;
+pub use bot::{CookieBot, CookieBotOptions};
+pub use buybooster::BuyBoosterResponse;
+pub use buycdr::BuyCdrResponse;
+pub use claimcookie::{ClaimCookieResponse, PrestigeRank};
+pub use config::Config;
+pub use rank::Rank;
@@ -1,16 +1,45 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::diagnostics::{self, Component, Diagnosis};
+
+pub static CLAIM_GOOD_NAME: &str = "thepositivebot::CLAIM_GOOD";
+pub static CLAIM_BAD_NAME: &str = "thepositivebot::CLAIM_BAD";
+
+const CLAIM_GOOD_COMPONENTS: &[Component] = &[
+    ("prefix", r"\[Cookies\] "),
+    ("rank", r"\[(?P<rank>(P\d+: )?\w+)\] "),
+    ("username", r"(?P<username>\w+) -> "),
+    ("cookie", r"(?P<cookie>[^!]+)!+ "),
+    ("amount", r"\((?P<amount>[+-±]\d+)\) \w+ \| "),
+    ("total", r"(?P<total>\d+) total!"),
+];
+
+const CLAIM_BAD_COMPONENTS: &[Component] = &[
+    ("prefix", r"\[Cookies\] "),
+    ("rank", r"\[(?P<rank>(P\d+: )?\w+)\] "),
+    (
+        "username",
+        r"(?P<username>\w+) you have already claimed a cookie and have ",
+    ),
+    ("total", r"(?P<total>\d+) of them!"),
+];
+
 lazy_static! {
     #[derive(Debug)]
-    pub static ref CLAIM_GOOD: Regex = Regex::new(r"\[Cookies\] \[(?P<rank>(P\d+: )?\w+)\] (?P<username>\w+) -> (?P<cookie>[^!]+)!+ \((?P<amount>[+-±]\d+)\) \w+ \| (?P<total>\d+) total!").unwrap();
+    pub static ref CLAIM_GOOD: Regex = diagnostics::build(CLAIM_GOOD_COMPONENTS);
     #[derive(Debug)]
-    pub static ref CLAIM_BAD: Regex = Regex::new(r"\[Cookies\] \[(?P<rank>(P\d+: )?\w+)\] (?P<username>\w+) you have already claimed a cookie and have (?P<total>\d+) of them!").unwrap();
+    pub static ref CLAIM_BAD: Regex = diagnostics::build(CLAIM_BAD_COMPONENTS);
 
     #[derive(Debug)]
     pub static ref BUY_CDR_GOOD: Regex = Regex::new(r"\[Shop\] (?P<username>\w+), your cooldown has been reset!").unwrap();
     #[derive(Debug)]
-    pub static ref BUY_CDR_BAD: Regex = Regex::new(r"\[Shop\] (?P<username>\w+), you can purchase your next cooldown reset in (((?P<h>\d) hrs?, )?(?P<m>\d+) mins?, )?(?P<s>\d+) secs?!").unwrap();
+    pub static ref BUY_CDR_BAD: Regex = Regex::new(r"\[Shop\] (?P<username>\w+), you can purchase your next cooldown reset in (((?P<h>\d+) hrs?, )?(?P<m>\d+) mins?, )?(?P<s>\d+) secs?!").unwrap();
+
+    #[derive(Debug)]
+    pub static ref BOOSTER_GOOD: Regex = Regex::new(r"\[Shop\] (?P<username>\w+), you (have )?(bought|purchased) a booster").unwrap();
+    #[derive(Debug)]
+    pub static ref BOOSTER_BAD: Regex = Regex::new(r"\[Shop\] (?P<username>\w+), you can purchase your next booster in (((?P<h>\d+) hrs?, )?(?P<m>\d+) mins?, )?(?P<s>\d+) secs?!").unwrap();
 
     #[derive(Debug)]
     pub static ref PRESTIGE_GOOD: Regex = Regex::new(r"\[Cookies\] (?P<username>\w+) you reset your rank and are now \[(?P<rank>(P\d: )?\w+)\]!").unwrap();
@@ -21,6 +50,14 @@ lazy_static! {
     pub static ref GENERIC_ANSWER: Regex = Regex::new(r"\[(Cookies|Shop)\]( \[(?P<rank>(P\d+: )?\w+)\])? (?P<username>\w+)").unwrap();
 }
 
+/// Reports which component of the claim patterns failed to match `message`.
+pub fn diagnose(message: &str) -> Option<Diagnosis> {
+    diagnostics::closest(vec![
+        diagnostics::first_failing(CLAIM_GOOD_NAME, CLAIM_GOOD_COMPONENTS, message),
+        diagnostics::first_failing(CLAIM_BAD_NAME, CLAIM_BAD_COMPONENTS, message),
+    ])
+}
+
 #[cfg(test)]
 mod test_regex {
     use super::*;
@@ -199,6 +236,37 @@ mod test_regex {
         assert_eq!(captures.name("s").unwrap().as_str(), "54");
     }
 
+    #[test]
+    fn booster_good() {
+        let captures = BOOSTER_GOOD
+            .captures(
+                "[Shop] chronophylos, you bought a booster! Your next claims are boosted PogChamp",
+            )
+            .expect("regex should match");
+
+        assert_eq!(
+            captures.name("username").unwrap().as_str(),
+            "chronophylos",
+            "wrong username"
+        );
+    }
+
+    #[test]
+    fn booster_bad() {
+        let captures = BOOSTER_BAD
+            .captures("[Shop] chronophylos, you can purchase your next booster in 5 hrs, 2 mins, 10 secs!")
+            .expect("regex should match");
+
+        assert_eq!(
+            captures.name("username").unwrap().as_str(),
+            "chronophylos",
+            "wrong username"
+        );
+        assert_eq!(captures.name("h").unwrap().as_str(), "5");
+        assert_eq!(captures.name("m").unwrap().as_str(), "2");
+        assert_eq!(captures.name("s").unwrap().as_str(), "10");
+    }
+
     #[test]
     fn prestige_good() {
         let captures = PRESTIGE_GOOD
@@ -251,4 +319,29 @@ mod test_regex {
             "wrong username"
         );
     }
+
+    #[test]
+    fn diagnose_mutated_claims() {
+        let tests = [
+            ("[Cookie] [default] chronophylos -> Chocolate Chip! (+6) PartyTime | 31 total!", "prefix"),
+            ("[Cookies] (default) chronophylos -> Chocolate Chip! (+6) PartyTime | 31 total!", "rank"),
+            ("[Cookies] [default] chronophylos => Chocolate Chip! (+6) PartyTime | 31 total!", "username"),
+            ("[Cookies] [default] chronophylos -> Chocolate Chip. (+6) PartyTime | 31 total!", "cookie"),
+            ("[Cookies] [default] chronophylos -> Chocolate Chip! (6) PartyTime | 31 total!", "amount"),
+            ("[Cookies] [default] chronophylos -> Chocolate Chip! (+6) PartyTime | 31 cookies!", "total"),
+            ("[Cookies] [default] chronophylos you have already claimed a cookie and have many of them!", "total"),
+        ];
+
+        for (text, component) in tests.iter() {
+            let diagnosis = diagnose(text).expect("patterns should not match");
+
+            assert_eq!(diagnosis.component, *component, "{}", text);
+        }
+    }
+
+    #[test]
+    fn diagnose_matching_claim() {
+        assert!(diagnose("[Cookies] [P1: default] chronophylos you have already claimed a cookie and have 65 of them!")
+                .is_none());
+    }
 }
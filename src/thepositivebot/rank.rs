@@ -1,10 +1,15 @@
 use serde::Deserialize;
-use std::{fmt::Display, str::FromStr};
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
 use thiserror::Error;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// Ranks of ThePositiveBot from lowest to highest
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
 pub enum Rank {
+    /// A rank this version does not know about
+    ///
+    /// It orders below all known ranks so it never unlocks anything.
+    Unknown(String),
     Default,
     Bronze,
     Silver,
@@ -19,6 +24,7 @@ pub enum Rank {
 impl Display for Rank {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
+            Self::Unknown(name) => name,
             Self::Default => "default",
             Self::Bronze => "bronze",
             Self::Silver => "silver",
@@ -34,10 +40,10 @@ impl Display for Rank {
     }
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Copy, Error)]
 pub enum ParseRankError {
-    #[error("unknown rank name")]
-    UnkownRankError,
+    #[error("empty rank name")]
+    EmptyRankName,
 }
 
 impl FromStr for Rank {
@@ -54,7 +60,59 @@ impl FromStr for Rank {
             "masters" => Ok(Self::Masters),
             "grandmasters" => Ok(Self::GrandMasters),
             "leader" => Ok(Self::Leader),
-            _ => Err(Self::Err::UnkownRankError),
+            "" => Err(Self::Err::EmptyRankName),
+            _ => Ok(Self::Unknown(s.to_string())),
         }
     }
 }
+
+impl TryFrom<String> for Rank {
+    type Error = ParseRankError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_are_ordered() {
+        let ranks = [
+            Rank::Default,
+            Rank::Bronze,
+            Rank::Silver,
+            Rank::Gold,
+            Rank::Platinum,
+            Rank::Diamond,
+            Rank::Masters,
+            Rank::GrandMasters,
+            Rank::Leader,
+        ];
+
+        for pair in ranks.windows(2) {
+            assert!(pair[0] < pair[1], "{} should be below {}", pair[0], pair[1]);
+        }
+        assert_eq!(ranks.iter().max(), Some(&Rank::Leader));
+    }
+
+    #[test]
+    fn unknown_rank_is_lowest() {
+        assert!(Rank::Unknown("mythic".to_string()) < Rank::Default);
+    }
+
+    #[test]
+    fn unknown_rank_round_trips() {
+        let rank: Rank = "mythic".parse().unwrap();
+
+        assert_eq!(rank, Rank::Unknown("mythic".to_string()));
+        assert_eq!(rank.to_string(), "mythic");
+    }
+
+    #[test]
+    fn empty_rank_is_rejected() {
+        assert!("".parse::<Rank>().is_err());
+    }
+}
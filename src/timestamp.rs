@@ -9,7 +9,7 @@ impl Timestamp for Duration {
         let mut time = Vec::new();
         let mut secs = self.as_secs();
 
-        for (name, s) in &[("h", 60 * 60), ("m", 60), ("s", 1)] {
+        for (name, s) in &[("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
             let div = secs / s;
             if div > 0 {
                 time.push(format!("{}{}", div, name));
@@ -17,6 +17,48 @@ impl Timestamp for Duration {
             }
         }
 
+        if time.is_empty() {
+            return "0s".to_string();
+        }
+
         time.join(" ")
     }
 }
+
+/// Negative durations are prefixed with a minus.
+impl Timestamp for chrono::Duration {
+    fn as_readable(&self) -> String {
+        match self.to_std() {
+            Ok(duration) => duration.as_readable(),
+            Err(_) => format!("-{}", (-*self).as_readable()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readable_durations() {
+        let cases = [
+            (0, "0s"),
+            (59, "59s"),
+            (90, "1m 30s"),
+            (25 * 60 * 60, "1d 1h"),
+            (48 * 60 * 60, "2d"),
+            (3 * 24 * 60 * 60 + 4 * 60 + 5, "3d 4m 5s"),
+        ];
+
+        for (secs, readable) in &cases {
+            assert_eq!(Duration::from_secs(*secs).as_readable(), *readable);
+        }
+    }
+
+    #[test]
+    fn readable_chrono_durations() {
+        assert_eq!(chrono::Duration::minutes(90).as_readable(), "1h 30m");
+        assert_eq!(chrono::Duration::seconds(-90).as_readable(), "-1m 30s");
+        assert_eq!(chrono::Duration::zero().as_readable(), "0s");
+    }
+}
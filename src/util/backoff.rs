@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Delays that grow by `factor` after every attempt, up to `max`
+///
+/// With jitter, every delay is moved randomly by up to that fraction of
+/// itself, but never above `max`.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use cookiebot::util::ExponentialBackoff;
+/// let mut backoff = ExponentialBackoff::new(Duration::from_secs(4), Duration::from_secs(60));
+///
+/// assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+/// assert_eq!(backoff.next_delay(), Duration::from_secs(8));
+///
+/// backoff.reset();
+/// assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    max: Duration,
+    factor: u32,
+    jitter: f64,
+    attempt: u32,
+}
+
+impl ExponentialBackoff {
+    /// Creates a backoff that doubles from `initial` up to `max` without
+    /// jitter.
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            factor: 2,
+            jitter: 0.0,
+            attempt: 0,
+        }
+    }
+
+    /// Sets the growth factor. A factor of 1 gives a constant delay.
+    pub const fn with_factor(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Sets the jitter as a fraction of the delay, clamped to `0.0..=1.0`.
+    pub const fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns how many delays were handed out since the last reset.
+    pub const fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Starts over at the initial delay.
+    pub const fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Returns the next delay.
+    pub fn next_delay(&mut self) -> Duration {
+        self.next_delay_with(&mut rand::thread_rng())
+    }
+
+    /// Returns the next delay drawing the jitter from `rng`.
+    pub fn next_delay_with<R: Rng>(&mut self, rng: &mut R) -> Duration {
+        let delay = self.base_delay(self.attempt);
+        self.attempt = self.attempt.saturating_add(1);
+
+        if self.jitter == 0.0 {
+            return delay;
+        }
+
+        let spread = delay.as_secs_f64() * self.jitter;
+        let jittered = delay.as_secs_f64() + rng.gen_range(-spread..=spread);
+
+        Duration::from_secs_f64(jittered.max(0.0)).min(self.max)
+    }
+
+    /// Returns the delay of `attempt` without jitter.
+    fn base_delay(&self, attempt: u32) -> Duration {
+        self.factor
+            .checked_pow(attempt)
+            .and_then(|factor| self.initial.checked_mul(factor))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_cap() {
+        let mut backoff = ExponentialBackoff::new(Duration::from_secs(4), Duration::from_secs(20));
+
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+
+        assert_eq!(delays, vec![4, 8, 16, 20, 20]);
+        assert_eq!(backoff.attempt(), 5);
+    }
+
+    #[test]
+    fn constant_with_factor_one() {
+        let mut backoff = ExponentialBackoff::new(Duration::from_secs(10), Duration::from_secs(60))
+            .with_factor(1);
+
+        for _ in 0..5 {
+            assert_eq!(backoff.next_delay(), Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn huge_attempts_do_not_overflow() {
+        let mut backoff = ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(60));
+
+        for _ in 0..200 {
+            backoff.next_delay();
+        }
+
+        assert_eq!(backoff.next_delay(), Duration::from_secs(60));
+    }
+
+    proptest! {
+        #[test]
+        fn never_exceeds_cap(
+            initial in 0u64..10_000,
+            max in 0u64..100_000,
+            factor in 1u32..10,
+            jitter in 0.0f64..=1.0,
+            seed: u64,
+            attempts in 1usize..100,
+        ) {
+            let max = Duration::from_millis(max);
+            let mut backoff = ExponentialBackoff::new(Duration::from_millis(initial), max)
+                .with_factor(factor)
+                .with_jitter(jitter);
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            for _ in 0..attempts {
+                prop_assert!(backoff.next_delay_with(&mut rng) <= max);
+            }
+        }
+
+        #[test]
+        fn jitter_stays_within_fraction(
+            initial in 1u64..10_000,
+            jitter in 0.0f64..=1.0,
+            seed: u64,
+        ) {
+            let initial = Duration::from_millis(initial);
+            let mut backoff = ExponentialBackoff::new(initial, Duration::from_secs(3600))
+                .with_jitter(jitter);
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            let delay = backoff.next_delay_with(&mut rng).as_secs_f64();
+            let spread = initial.as_secs_f64() * jitter + 1e-9;
+
+            prop_assert!((delay - initial.as_secs_f64()).abs() <= spread);
+        }
+
+        #[test]
+        fn grows_monotonically_without_jitter(
+            initial in 0u64..10_000,
+            max in 0u64..100_000,
+            factor in 1u32..10,
+            attempts in 1usize..100,
+        ) {
+            let mut backoff = ExponentialBackoff::new(
+                Duration::from_millis(initial),
+                Duration::from_millis(max),
+            )
+            .with_factor(factor);
+
+            let mut last = Duration::from_secs(0);
+            for _ in 0..attempts {
+                let delay = backoff.next_delay();
+                prop_assert!(delay >= last);
+                last = delay;
+            }
+        }
+
+        #[test]
+        fn reset_starts_over(initial in 0u64..10_000, attempts in 0usize..50) {
+            let initial = Duration::from_millis(initial);
+            let mut backoff = ExponentialBackoff::new(initial, Duration::from_secs(3600));
+
+            for _ in 0..attempts {
+                backoff.next_delay();
+            }
+            backoff.reset();
+
+            prop_assert_eq!(backoff.next_delay(), initial.min(Duration::from_secs(3600)));
+        }
+    }
+}
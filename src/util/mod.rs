@@ -0,0 +1,10 @@
+//! Small primitives shared by the bots
+//!
+//! All waiting is done on the tokio clock, so tests can run them on paused
+//! time.
+
+pub mod backoff;
+pub mod ratelimit;
+
+pub use backoff::ExponentialBackoff;
+pub use ratelimit::{DeadlineExceeded, SlidingWindowCounter, TokenBucket};
@@ -0,0 +1,323 @@
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, timeout_at, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Deadline passed before a token became available")]
+pub struct DeadlineExceeded;
+
+/// Rate limiter handing out up to `capacity` tokens at once and one more
+/// every `refill_every`
+///
+/// Waiting callers are served in the order they called
+/// [`acquire`](Self::acquire).
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: u32,
+    refill_every: Duration,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BucketState {
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl BucketState {
+    /// Takes a token or returns when the next one is available.
+    fn take_at(
+        &mut self,
+        now: Instant,
+        capacity: u32,
+        refill_every: Duration,
+    ) -> Result<(), Instant> {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let refills = (elapsed.as_nanos() / refill_every.as_nanos()).min(u32::MAX as u128) as u32;
+
+        if refills > 0 {
+            self.tokens = self.tokens.saturating_add(refills).min(capacity);
+            self.last_refill = if self.tokens == capacity {
+                now
+            } else {
+                self.last_refill + refill_every * refills
+            };
+        }
+
+        if self.tokens > 0 {
+            self.tokens -= 1;
+            Ok(())
+        } else {
+            Err(self.last_refill + refill_every)
+        }
+    }
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `refill_every` is zero.
+    pub fn new(capacity: u32, refill_every: Duration) -> Self {
+        assert!(
+            refill_every > Duration::from_secs(0),
+            "refill interval must not be zero"
+        );
+
+        Self {
+            capacity,
+            refill_every,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token if one is available right now.
+    pub async fn try_acquire(&self) -> bool {
+        self.state
+            .lock()
+            .await
+            .take_at(Instant::now(), self.capacity, self.refill_every)
+            .is_ok()
+    }
+
+    /// Waits for a token.
+    ///
+    /// Gives up without taking a token if none is available before
+    /// `deadline`.
+    pub async fn acquire(&self, deadline: Option<Instant>) -> Result<(), DeadlineExceeded> {
+        // holding the lock while waiting keeps callers in order
+        let mut state = match deadline {
+            Some(deadline) => timeout_at(deadline, self.state.lock())
+                .await
+                .map_err(|_elapsed| DeadlineExceeded)?,
+            None => self.state.lock().await,
+        };
+
+        loop {
+            match state.take_at(Instant::now(), self.capacity, self.refill_every) {
+                Ok(()) => return Ok(()),
+                Err(next) if deadline.is_some_and(|deadline| next > deadline) => {
+                    return Err(DeadlineExceeded)
+                }
+                Err(next) => sleep_until(next).await,
+            }
+        }
+    }
+}
+
+/// Counts events within the last `window`
+#[derive(Debug, Clone)]
+pub struct SlidingWindowCounter {
+    window: Duration,
+    events: VecDeque<Instant>,
+}
+
+impl SlidingWindowCounter {
+    pub const fn new(window: Duration) -> Self {
+        Self {
+            window,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Records an event now.
+    pub fn record(&mut self) {
+        self.record_at(Instant::now())
+    }
+
+    /// Returns the number of events within the window.
+    pub fn count(&mut self) -> usize {
+        self.count_at(Instant::now())
+    }
+
+    /// Records an event now unless `limit` events are already in the window.
+    ///
+    /// Returns whether the event was recorded.
+    pub fn try_record(&mut self, limit: usize) -> bool {
+        self.try_record_at(Instant::now(), limit)
+    }
+
+    pub fn record_at(&mut self, now: Instant) {
+        self.expire(now);
+        self.events.push_back(now);
+    }
+
+    pub fn count_at(&mut self, now: Instant) -> usize {
+        self.expire(now);
+        self.events.len()
+    }
+
+    pub fn try_record_at(&mut self, now: Instant, limit: usize) -> bool {
+        if self.count_at(now) >= limit {
+            return false;
+        }
+
+        self.events.push_back(now);
+        true
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(oldest) = self.events.front() {
+            if now.saturating_duration_since(*oldest) < self.window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_over_time() {
+        let bucket = TokenBucket::new(2, Duration::from_secs(10));
+
+        assert!(bucket.try_acquire().await);
+        assert!(bucket.try_acquire().await);
+        assert!(!bucket.try_acquire().await);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(bucket.try_acquire().await);
+        assert!(!bucket.try_acquire().await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_waits_for_refill() {
+        let bucket = TokenBucket::new(1, Duration::from_secs(10));
+        let start = Instant::now();
+
+        bucket.acquire(None).await.unwrap();
+        bucket.acquire(None).await.unwrap();
+
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_respects_deadline() {
+        let bucket = TokenBucket::new(1, Duration::from_secs(10));
+        bucket.acquire(None).await.unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(bucket.acquire(Some(deadline)).await, Err(DeadlineExceeded));
+
+        // the failed attempt did not take the next token
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(bucket.try_acquire().await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiters_are_served_in_order() {
+        let bucket = Arc::new(TokenBucket::new(1, Duration::from_secs(1)));
+        bucket.acquire(None).await.unwrap();
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        for id in 0..5 {
+            let bucket = bucket.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                bucket.acquire(None).await.unwrap();
+                sender.send(id).unwrap();
+            });
+            // make sure the tasks queue up in order
+            tokio::task::yield_now().await;
+        }
+        drop(sender);
+
+        let mut order = Vec::new();
+        while let Some(id) = receiver.recv().await {
+            order.push(id);
+        }
+
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn window_forgets_old_events() {
+        let start = Instant::now();
+        let mut counter = SlidingWindowCounter::new(Duration::from_secs(60));
+
+        counter.record_at(start);
+        counter.record_at(start + Duration::from_secs(30));
+
+        assert_eq!(counter.count_at(start + Duration::from_secs(59)), 2);
+        assert_eq!(counter.count_at(start + Duration::from_secs(60)), 1);
+        assert_eq!(counter.count_at(start + Duration::from_secs(90)), 0);
+    }
+
+    #[test]
+    fn window_rejects_over_limit() {
+        let start = Instant::now();
+        let mut counter = SlidingWindowCounter::new(Duration::from_secs(60));
+
+        assert!(counter.try_record_at(start, 2));
+        assert!(counter.try_record_at(start, 2));
+        assert!(!counter.try_record_at(start + Duration::from_secs(59), 2));
+        assert!(counter.try_record_at(start + Duration::from_secs(60), 2));
+    }
+
+    proptest! {
+        #[test]
+        fn bucket_never_exceeds_rate(
+            capacity in 1u32..10,
+            refill_ms in 1u64..1_000,
+            steps in prop::collection::vec(0u64..2_000, 1..200),
+        ) {
+            let refill_every = Duration::from_millis(refill_ms);
+            let start = Instant::now();
+            let mut state = BucketState { tokens: capacity, last_refill: start };
+            let mut now = start;
+            let mut taken = 0u64;
+
+            for step in steps {
+                now += Duration::from_millis(step);
+                if state.take_at(now, capacity, refill_every).is_ok() {
+                    taken += 1;
+                }
+
+                let elapsed = (now - start).as_millis() as u64;
+                prop_assert!(taken <= u64::from(capacity) + elapsed / refill_ms);
+                prop_assert!(state.tokens <= capacity);
+            }
+        }
+
+        #[test]
+        fn window_never_exceeds_limit(
+            window_ms in 1u64..1_000,
+            limit in 1usize..10,
+            steps in prop::collection::vec(0u64..500, 1..200),
+        ) {
+            let window = Duration::from_millis(window_ms);
+            let start = Instant::now();
+            let mut counter = SlidingWindowCounter::new(window);
+            let mut recorded = Vec::new();
+            let mut now = start;
+
+            for step in steps {
+                now += Duration::from_millis(step);
+                if counter.try_record_at(now, limit) {
+                    recorded.push(now);
+                }
+
+                let in_window = recorded
+                    .iter()
+                    .filter(|at| now.saturating_duration_since(**at) < window)
+                    .count();
+                prop_assert!(in_window <= limit);
+                prop_assert_eq!(counter.count_at(now), in_window);
+            }
+        }
+    }
+}
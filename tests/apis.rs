@@ -0,0 +1,288 @@
+//! The public APIs of the target bots and Twitch, served by a mock server
+
+use std::time::Duration;
+
+use cookiebot::{http, ChattersApi, ChattersOptions, HttpOptions};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+/// Retries quickly, so failing tests do not wait for the default backoff
+const HTTP: HttpOptions = HttpOptions {
+    timeout: Duration::from_secs(5),
+    max_attempts: 2,
+    retry_delay: Duration::from_millis(10),
+    http_proxy: None,
+    https_proxy: None,
+};
+
+/// Writes a config pointing every API at `server` and loads it.
+#[cfg(any(feature = "thepositivebot", feature = "okayegbot"))]
+fn config(server: &MockServer, name: &str) -> cookiebot::Config {
+    let path = std::env::temp_dir().join(format!(
+        "cookiebot-apis-{}-{}.ron",
+        std::process::id(),
+        name
+    ));
+    std::fs::write(
+        &path,
+        format!(
+            r#"(
+                username: "chronophylos",
+                thepositivebot: (user_api_base: "{0}", cooldown_api_base: "{0}"),
+                okayegbot: (api_base: "{0}"),
+                http: (timeout: "5s", max_attempts: 2, retry_delay: "10ms"),
+            )"#,
+            server.uri()
+        ),
+    )
+    .unwrap();
+
+    let config = cookiebot::Config::from_path_without_token(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    config
+}
+
+#[cfg(feature = "thepositivebot")]
+mod roaringiron {
+    use cookiebot::{
+        once::CancellationToken, secrettoken::Token, CookieBot, CookieBotOptions, SecretToken,
+        Section, Status,
+    };
+
+    use super::*;
+
+    async fn mock_user(server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn reads_cookie_status() {
+        let server = MockServer::start().await;
+        mock_user(
+            &server,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "cookies": 728,
+                "rank": "default",
+                "prestige": 1,
+                "booster_cooldown": "none",
+            })),
+        )
+        .await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "can_claim": false,
+                "seconds_left": 120.0,
+            })))
+            .mount(&server)
+            .await;
+
+        let status = Status::fetch(&config(&server, "cookies"), "chronophylos")
+            .await
+            .unwrap();
+
+        match status.cookies {
+            Section::Read(cookies) => {
+                assert_eq!(cookies.cookies, 728);
+                assert_eq!(cookies.prestige, 1);
+                assert_eq!(cookies.cooldown_secs, Some(120));
+            }
+            Section::Failed { error } => panic!("cookies failed: {}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_on_invalid_json() {
+        let server = MockServer::start().await;
+        mock_user(
+            &server,
+            ResponseTemplate::new(200).set_body_string("<html>Maintenance</html>"),
+        )
+        .await;
+
+        let status = Status::fetch(&config(&server, "invalid-json"), "chronophylos")
+            .await
+            .unwrap();
+
+        assert!(matches!(status.cookies, Section::Failed { .. }));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/chronophylos"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let status = Status::fetch(&config(&server, "server-error"), "chronophylos")
+            .await
+            .unwrap();
+
+        match status.cookies {
+            Section::Failed { error } => assert!(error.contains("after 2 attempts"), "{}", error),
+            Section::Read(cookies) => panic!("read {:?} from a failing API", cookies),
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_cooldown_of_bot() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/cooldown/chronophylos"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "can_claim": false,
+                "seconds_left": 90.0,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = config(&server, "cooldown");
+
+        let bot = CookieBot::with_options(
+            "chronophylos".to_string(),
+            SecretToken::new(Token::new("token")).into(),
+            CookieBotOptions {
+                config: config.thepositivebot.clone(),
+                http: config.http,
+                ..CookieBotOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            bot.check_cooldown(None, &CancellationToken::new())
+                .await
+                .unwrap(),
+            Some(Duration::from_secs(90))
+        );
+    }
+}
+
+#[cfg(feature = "okayegbot")]
+mod okayeg {
+    use cookiebot::{EgStatus, Section, Status};
+    use wiremock::matchers::query_param;
+
+    use super::*;
+
+    async fn mock_user(server: &MockServer, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .and(query_param("username", "chronophylos"))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn reads_eg_status() {
+        let server = MockServer::start().await;
+        mock_user(
+            &server,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "userid": 54946241,
+                "username": "chronophylos",
+                "egs": 42,
+                "cooldown": "2021-03-01T12:00:00Z",
+            })),
+        )
+        .await;
+
+        let status = Status::fetch(&config(&server, "egs"), "chronophylos")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            status.egs,
+            Section::Read(Some(EgStatus {
+                egs: 42,
+                cooldown_secs: None,
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_user_has_no_status() {
+        let server = MockServer::start().await;
+        mock_user(&server, ResponseTemplate::new(404)).await;
+
+        let status = Status::fetch(&config(&server, "unknown-user"), "chronophylos")
+            .await
+            .unwrap();
+
+        assert_eq!(status.egs, Section::Read(None));
+    }
+
+    #[tokio::test]
+    async fn fails_on_server_error() {
+        let server = MockServer::start().await;
+        mock_user(&server, ResponseTemplate::new(500)).await;
+
+        let status = Status::fetch(&config(&server, "eg-server-error"), "chronophylos")
+            .await
+            .unwrap();
+
+        assert!(matches!(status.egs, Section::Failed { .. }));
+    }
+}
+
+mod chatters {
+    use super::*;
+
+    fn client() -> http::Client {
+        http::Client::new(reqwest::Client::new(), HTTP)
+    }
+
+    #[tokio::test]
+    async fn looks_up_chatters() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/group/user/thepositivebot/chatters"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "chatter_count": 2,
+                "chatters": {
+                    "broadcaster": ["thepositivebot"],
+                    "vips": [],
+                    "moderators": [],
+                    "staff": [],
+                    "admins": [],
+                    "global_mods": [],
+                    "viewers": ["chronophylos"],
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        let api = ChattersApi::with_bases(ChattersOptions::default(), server.uri(), server.uri());
+        let chatters = api.chatters(&client(), "thepositivebot").await.unwrap();
+
+        assert!(chatters.contains("thepositivebot"));
+        assert!(chatters.contains("chronophylos"));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/group/user/thepositivebot/chatters"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let api = ChattersApi::with_bases(ChattersOptions::default(), server.uri(), server.uri());
+
+        assert!(api.chatters(&client(), "thepositivebot").await.is_err());
+    }
+}